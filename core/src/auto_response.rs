@@ -0,0 +1,353 @@
+//! keyword-triggered auto-responses to plain chat,
+//! e.g. someone saying "Linux" gets the GNU/Linux interjection.
+//!
+//! triggers are configured in a TOML file like `assets/auto_responses.toml`.
+//! each one has a probability, a per-channel cooldown and the channels it's enabled in,
+//! so it stays funny instead of spammy.
+use std::{path::Path, sync::Arc, time::Duration};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Channel, Response, User,
+    cooldown::Cooldown,
+    copypasta::Copypastas,
+    custom_command::{TemplateContext, render_template},
+    dice::DiceRoller,
+    event_processor::{Event, EventConsumer, EventError, EventType},
+    io::{parse_toml_str, read_file_to_string},
+};
+
+/// what sets off a trigger
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// a whole word or phrase, ignoring case
+    Keyword(String),
+    /// a regular expression, see [`regex`] for the syntax
+    Regex(String),
+}
+
+/// what a trigger says back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoResponse {
+    /// a copypasta by name, with its placeholders filled in
+    Pasta(String),
+    /// some text, which can use the same placeholders as a copypasta
+    Text(String),
+    /// react to the message with an emoji
+    React(String),
+}
+
+/// a `[[trigger]]` in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriggerConfig {
+    pub name: String,
+    pub when: Pattern,
+    pub respond: AutoResponse,
+    /// chance of responding when the pattern matches, from 0 to 1
+    #[serde(default = "always")]
+    pub probability: f64,
+    /// how long before the trigger can go off again in the same channel
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    /// the channels the trigger is enabled in
+    pub channels: Vec<Channel>,
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AutoResponseConfig {
+    #[serde(default, rename = "trigger")]
+    pub triggers: Vec<TriggerConfig>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AutoResponseError {
+    #[error("trigger `{name}` has an invalid pattern: {source}")]
+    InvalidPattern { name: String, source: regex::Error },
+
+    #[error("trigger `{name}` has probability {probability}, it should be between 0 and 1")]
+    InvalidProbability { name: String, probability: f64 },
+
+    #[error("unable to load auto-responses: {0}")]
+    Load(#[from] crate::error::Error),
+}
+
+#[derive(Debug, Clone)]
+struct Trigger {
+    config: TriggerConfig,
+    pattern: Regex,
+    cooldown: Cooldown<Channel>,
+}
+
+impl TryFrom<TriggerConfig> for Trigger {
+    type Error = AutoResponseError;
+
+    fn try_from(config: TriggerConfig) -> Result<Self, Self::Error> {
+        if !(0.0..=1.0).contains(&config.probability) {
+            return Err(AutoResponseError::InvalidProbability {
+                name: config.name,
+                probability: config.probability,
+            });
+        }
+
+        let pattern = match &config.when {
+            Pattern::Keyword(keyword) => format!(r"(?i)\b{}\b", regex::escape(keyword)),
+            Pattern::Regex(pattern) => pattern.clone(),
+        };
+        let pattern = Regex::new(&pattern).map_err(|source| AutoResponseError::InvalidPattern {
+            name: config.name.clone(),
+            source,
+        })?;
+
+        Ok(Self {
+            cooldown: Cooldown::new(Duration::from_secs(config.cooldown_secs)),
+            pattern,
+            config,
+        })
+    }
+}
+
+impl Trigger {
+    /// whether the trigger goes off for `text` in `channel` this time.
+    /// the cooldown only starts if it does
+    fn fires(&self, text: &str, channel: Channel) -> bool {
+        self.config.channels.contains(&channel)
+            && self.pattern.is_match(text)
+            && rand::random_bool(self.config.probability)
+            && self.cooldown.try_use(channel).is_ok()
+    }
+}
+
+/// responds to [`EventType::Plain`] messages that set off a trigger.
+/// at most one trigger responds to each message
+#[derive(Debug, Clone)]
+pub struct AutoResponder {
+    triggers: Arc<[Trigger]>,
+    copypastas: Copypastas,
+}
+
+impl TryFrom<AutoResponseConfig> for AutoResponder {
+    type Error = AutoResponseError;
+
+    fn try_from(config: AutoResponseConfig) -> Result<Self, Self::Error> {
+        let triggers = config
+            .triggers
+            .into_iter()
+            .map(Trigger::try_from)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            triggers,
+            copypastas: Copypastas::default(),
+        })
+    }
+}
+
+impl AutoResponder {
+    pub fn from_toml_str(contents: &str) -> Result<Self, AutoResponseError> {
+        let config: AutoResponseConfig = parse_toml_str(contents)?;
+        Self::try_from(config)
+    }
+
+    pub async fn from_toml_file(path: impl AsRef<Path>) -> Result<Self, AutoResponseError> {
+        let contents = read_file_to_string(path).await?;
+        Self::from_toml_str(&contents)
+    }
+
+    /// use shared [`Copypastas`], e.g. the ones `pasta add` edits
+    pub fn with_copypastas(self, copypastas: Copypastas) -> Self {
+        Self { copypastas, ..self }
+    }
+
+    async fn respond(&self, trigger: &Trigger, event: &Event) -> Result<Response, EventError> {
+        let template = match &trigger.config.respond {
+            AutoResponse::React(emoji) => return Ok(Response::Reaction(emoji.clone())),
+            AutoResponse::Text(text) => text.clone(),
+            AutoResponse::Pasta(name) => match self.copypastas.copy_pasta(name).await {
+                Some(pasta) => pasta,
+                None => {
+                    tracing::warn!(
+                        trigger = %trigger.config.name,
+                        %name,
+                        "auto-response pasta is gone"
+                    );
+                    return Ok(Response::Ignored);
+                }
+            },
+        };
+
+        let context = TemplateContext {
+            user: &event.user,
+            channel: event.channel,
+            args: "",
+            copypastas: &self.copypastas,
+        };
+
+        let text = render_template(&template, &context, &DiceRoller::secure()).await?;
+        Ok(text.into())
+    }
+}
+
+#[async_trait::async_trait]
+impl EventConsumer for AutoResponder {
+    async fn consume_event(&self, event: &Event) -> Result<Response, EventError> {
+        let text = event.content.render_without_thinking_parts();
+
+        match self
+            .triggers
+            .iter()
+            .find(|trigger| trigger.fires(&text, event.channel))
+        {
+            Some(trigger) => {
+                tracing::info!(trigger = %trigger.config.name, "auto-responding");
+                self.respond(trigger, event).await
+            }
+            None => Ok(Response::Ignored),
+        }
+    }
+
+    fn should_consume_event(&self, event: &Event) -> bool {
+        // never talk to ourselves
+        matches!(event.event_type, EventType::Plain) && event.user != User::Ultron
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(channel: Channel, content: &str) -> Event {
+        Event::builder()
+            .user(User::from("bob"))
+            .content(content.to_string())
+            .event_type(EventType::Plain)
+            .channel(channel)
+            .build()
+    }
+
+    #[test]
+    fn bundled_config_parses() {
+        let responder =
+            AutoResponder::from_toml_str(include_str!("../../assets/auto_responses.toml"))
+                .expect("bundled auto-responses should parse");
+        assert!(!responder.triggers.is_empty());
+    }
+
+    #[test]
+    fn broken_triggers_are_rejected() {
+        let error = AutoResponder::from_toml_str(
+            r#"
+            [[trigger]]
+            name = "broken"
+            when = { regex = "(unclosed" }
+            respond = { text = "nope" }
+            channels = ["debug"]
+            "#,
+        )
+        .expect_err("invalid regex should fail");
+        assert!(matches!(error, AutoResponseError::InvalidPattern { .. }));
+
+        let error = AutoResponder::from_toml_str(
+            r#"
+            [[trigger]]
+            name = "eager"
+            when = { keyword = "hi" }
+            respond = { text = "hi" }
+            probability = 2.0
+            channels = ["debug"]
+            "#,
+        )
+        .expect_err("probability over 1 should fail");
+        assert!(matches!(
+            error,
+            AutoResponseError::InvalidProbability { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn triggers_respect_channels_and_cooldowns() {
+        let responder = AutoResponder::from_toml_str(
+            r#"
+            [[trigger]]
+            name = "interjection"
+            when = { keyword = "linux" }
+            respond = { pasta = "linux" }
+            cooldown_secs = 3600
+            channels = ["debug", "fun_zone_bots"]
+
+            [[trigger]]
+            name = "crab"
+            when = { regex = "(?i)rust(acean)?s?\\b" }
+            respond = { react = "🦀" }
+            channels = ["debug"]
+
+            [[trigger]]
+            name = "never"
+            when = { keyword = "hello" }
+            respond = { text = "hi {user}" }
+            probability = 0.0
+            channels = ["debug"]
+            "#,
+        )
+        .expect("config should parse");
+
+        let response = responder
+            .consume_event(&plain(Channel::Debug, "I use Linux btw"))
+            .await
+            .expect("should respond");
+        assert!(response.to_text().starts_with("I'd just like to interject"));
+
+        let response = responder
+            .consume_event(&plain(Channel::Debug, "linux again"))
+            .await
+            .expect("should respond");
+        assert_eq!(response, Response::Ignored, "cooling down in debug");
+
+        let response = responder
+            .consume_event(&plain(Channel::FunZoneBots, "linux!"))
+            .await
+            .expect("should respond");
+        assert_ne!(
+            response,
+            Response::Ignored,
+            "other channels have their own cooldown"
+        );
+
+        let response = responder
+            .consume_event(&plain(Channel::Dnd, "linux"))
+            .await
+            .expect("should respond");
+        assert_eq!(response, Response::Ignored, "not enabled in dnd");
+
+        let response = responder
+            .consume_event(&plain(Channel::Debug, "penguins are not linuxy"))
+            .await
+            .expect("should respond");
+        assert_eq!(response, Response::Ignored, "keywords match whole words");
+
+        let response = responder
+            .consume_event(&plain(Channel::Debug, "Rustaceans assemble"))
+            .await
+            .expect("should respond");
+        assert_eq!(response, Response::Reaction("🦀".to_string()));
+
+        let response = responder
+            .consume_event(&plain(Channel::Debug, "hello"))
+            .await
+            .expect("should respond");
+        assert_eq!(response, Response::Ignored, "probability 0 never fires");
+
+        let ultron = Event {
+            user: User::Ultron,
+            ..plain(Channel::Debug, "linux")
+        };
+        assert!(!responder.should_consume_event(&ultron));
+    }
+}
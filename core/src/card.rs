@@ -0,0 +1,163 @@
+//! structured output for commands.
+//! chat adapters can render a [`Card`] natively, e.g. as a Discord embed,
+//! or fall back to [`Card::to_markdown`].
+use bon::Builder;
+use serde::{Deserialize, Serialize};
+
+/// an RGB color, e.g. `0xff0000`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Color(pub u32);
+
+impl Color {
+    /// Ultron's signature menacing red
+    pub const ULTRON: Color = Color(0xb3_00_00);
+    pub const SUCCESS: Color = Color(0x2e_cc_71);
+    pub const INFO: Color = Color(0x34_98_db);
+}
+
+/// a structured message with a title, fields, code blocks and so on.
+/// all parts are optional.
+#[derive(Builder, Debug, Clone, PartialEq, Default, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Card {
+    #[builder(into)]
+    pub title: Option<String>,
+    #[builder(into)]
+    pub description: Option<String>,
+    #[builder(default)]
+    pub fields: Vec<CardField>,
+    #[builder(default)]
+    pub code_blocks: Vec<CodeBlock>,
+    #[builder(into)]
+    pub footer: Option<String>,
+    pub color: Option<Color>,
+}
+
+/// a named value in a [`Card`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct CardField {
+    pub name: String,
+    pub value: String,
+    /// whether the field can be shown side by side with other inline fields
+    pub inline: bool,
+}
+
+impl CardField {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            inline: false,
+        }
+    }
+
+    pub fn inline(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            inline: true,
+            ..Self::new(name, value)
+        }
+    }
+}
+
+/// a block of preformatted text
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct CodeBlock {
+    /// used for syntax highlighting, if the adapter supports it
+    pub language: Option<String>,
+    pub code: String,
+}
+
+impl CodeBlock {
+    pub fn new(code: impl Into<String>) -> Self {
+        Self {
+            language: None,
+            code: code.into(),
+        }
+    }
+
+    pub fn with_language(self, language: impl Into<String>) -> Self {
+        Self {
+            language: Some(language.into()),
+            ..self
+        }
+    }
+}
+
+impl std::fmt::Display for CodeBlock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let language = self.language.as_deref().unwrap_or("");
+        write!(f, "```{}\n{}\n```", language, self.code)
+    }
+}
+
+impl Card {
+    /// render the card as markdown text
+    /// for adapters that can't do anything fancier
+    pub fn to_markdown(&self) -> String {
+        let title = self.title.iter().map(|title| format!("**{}**", title));
+        let description = self.description.iter().cloned();
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| format!("**{}**: {}", field.name, field.value));
+        let code_blocks = self.code_blocks.iter().map(CodeBlock::to_string);
+        let footer = self.footer.iter().map(|footer| format!("_{}_", footer));
+
+        title
+            .chain(description)
+            .chain(fields)
+            .chain(code_blocks)
+            .chain(footer)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// the content of the card without any decoration.
+    /// titles and footers are dropped.
+    pub fn to_plain_text(&self) -> String {
+        let description = self.description.iter().cloned();
+        let fields = self
+            .fields
+            .iter()
+            .map(|field| format!("{}: {}", field.name, field.value));
+        let code_blocks = self.code_blocks.iter().map(|block| block.code.clone());
+
+        description
+            .chain(fields)
+            .chain(code_blocks)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+impl std::fmt::Display for Card {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_markdown())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_renders_markdown_fallback() {
+        let card = Card::builder()
+            .title("🎲 4d6k3")
+            .description("[6, 5, 4, 1]")
+            .fields(vec![CardField::inline("total", "**15**")])
+            .code_blocks(vec![CodeBlock::new("15 ████").with_language("text")])
+            .footer("rolled by bob")
+            .color(Color::ULTRON)
+            .build();
+
+        insta::assert_snapshot!(card.to_markdown(), @r"
+        **🎲 4d6k3**
+        [6, 5, 4, 1]
+        **total**: **15**
+        ```text
+        15 ████
+        ```
+        _rolled by bob_
+        ");
+    }
+}
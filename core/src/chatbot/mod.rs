@@ -1,4 +1,9 @@
-use crate::{Channel, DEFAULT_COMMAND_PREFIX, User, command::CommandParseError};
+use crate::{Channel, DEFAULT_COMMAND_PREFIX, User, card::Card, command::CommandParseError};
+
+pub mod outgoing;
+pub mod terminal;
+
+use outgoing::OutgoingMessage;
 
 pub trait ChatBot: Clone + Send + Sync {
     type Error: Into<crate::error::Error>;
@@ -9,6 +14,35 @@ pub trait ChatBot: Clone + Send + Sync {
         message: &str,
     ) -> impl Future<Output = Result<(), Self::Error>> + Send;
 
+    /// send an [`OutgoingMessage`] with replies, embeds, attachments and so on.
+    /// adapters that can do any of those natively should override this,
+    /// by default the message is sent as [`OutgoingMessage::to_text`].
+    fn send(
+        &self,
+        channel: Channel,
+        message: &OutgoingMessage,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async move {
+            if message.is_empty() {
+                return Ok(());
+            }
+            self.send_message(channel, &message.to_text()).await
+        }
+    }
+
+    /// send a structured [`Card`] with [`ChatBot::send`],
+    /// so by default it's sent as markdown.
+    fn send_card(
+        &self,
+        channel: Channel,
+        card: &Card,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async move {
+            self.send(channel, &OutgoingMessage::from(card.clone()))
+                .await
+        }
+    }
+
     fn debug(
         &self,
         message: &str,
@@ -40,6 +74,10 @@ pub struct ChatInput {
     pub content: String,
     #[builder(into)]
     pub channel: Channel,
+    /// who the platform says sent this, like `discord:1234`.
+    /// `None` when anyone could claim to be `user`, like on IRC
+    #[builder(into)]
+    pub identity: Option<String>,
 }
 
 impl ChatInput {
@@ -48,6 +86,7 @@ impl ChatInput {
             user: User::Anonymous,
             content: content.to_string(),
             channel,
+            identity: None,
         }
     }
 
@@ -0,0 +1,205 @@
+//! messages for a [`super::ChatBot`] that are more than plain text:
+//! replies, embeds, attachments, reactions and who gets pinged.
+//!
+//! chat adapters that can't do some of these fall back on [`OutgoingMessage::to_text`].
+use serde::{Deserialize, Serialize};
+
+use crate::{Response, card::Card};
+
+/// the id of a message on the chat platform, e.g. a Discord message snowflake
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, derive_more::Display)]
+pub struct MessageRef(pub String);
+
+impl From<u64> for MessageRef {
+    fn from(id: u64) -> Self {
+        Self(id.to_string())
+    }
+}
+
+/// a file to upload along with a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(filename: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Self {
+            filename: filename.into(),
+            data: data.into(),
+        }
+    }
+}
+
+/// who a message is allowed to ping.
+/// by default only users, so nothing Ultron says can ping `@everyone` or a role by accident
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mentions {
+    pub users: bool,
+    pub roles: bool,
+    pub everyone: bool,
+    /// the author of the message being replied to
+    pub replied_user: bool,
+}
+
+impl Mentions {
+    /// nobody gets pinged
+    pub const NONE: Mentions = Mentions {
+        users: false,
+        roles: false,
+        everyone: false,
+        replied_user: false,
+    };
+
+    /// everybody who's mentioned gets pinged
+    pub const ALL: Mentions = Mentions {
+        users: true,
+        roles: true,
+        everyone: true,
+        replied_user: true,
+    };
+}
+
+impl Default for Mentions {
+    fn default() -> Self {
+        Self {
+            users: true,
+            ..Self::NONE
+        }
+    }
+}
+
+/// everything a [`super::ChatBot`] can send in one go
+#[derive(bon::Builder, Debug, Clone, PartialEq, Default)]
+pub struct OutgoingMessage {
+    /// the text of the message, which can be empty if there's something else to send
+    #[builder(into, default)]
+    pub content: String,
+    /// the message this one replies to, and that [`Self::reactions`] go on
+    #[builder(into)]
+    pub reply_to: Option<MessageRef>,
+    /// [`Card`]s to render natively, e.g. as Discord embeds
+    #[builder(default)]
+    pub embeds: Vec<Card>,
+    #[builder(default)]
+    pub attachments: Vec<Attachment>,
+    /// emoji to react to [`Self::reply_to`] with
+    #[builder(default)]
+    pub reactions: Vec<String>,
+    #[builder(default)]
+    pub mentions: Mentions,
+}
+
+impl OutgoingMessage {
+    pub fn with_reply_to(self, reply_to: impl Into<MessageRef>) -> Self {
+        Self {
+            reply_to: Some(reply_to.into()),
+            ..self
+        }
+    }
+
+    pub fn with_mentions(self, mentions: Mentions) -> Self {
+        Self { mentions, ..self }
+    }
+
+    /// whether there's nothing to send or react with
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+            && self.embeds.is_empty()
+            && self.attachments.is_empty()
+            && self.reactions.is_empty()
+    }
+
+    /// the message as plain text, for chat platforms that only do text.
+    /// embeds become markdown, attachments become their file names
+    /// and reactions become emoji
+    pub fn to_text(&self) -> String {
+        let content = (!self.content.is_empty()).then(|| self.content.clone());
+        let attachments = self
+            .attachments
+            .iter()
+            .map(|attachment| format!("📎 `{}`", attachment.filename));
+        let reactions = (!self.reactions.is_empty()).then(|| self.reactions.join(" "));
+
+        content
+            .into_iter()
+            .chain(self.embeds.iter().map(Card::to_markdown))
+            .chain(attachments)
+            .chain(reactions)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+impl From<String> for OutgoingMessage {
+    fn from(content: String) -> Self {
+        Self {
+            content,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<&str> for OutgoingMessage {
+    fn from(content: &str) -> Self {
+        Self::from(content.to_string())
+    }
+}
+
+impl From<Card> for OutgoingMessage {
+    fn from(card: Card) -> Self {
+        Self {
+            embeds: vec![card],
+            ..Self::default()
+        }
+    }
+}
+
+/// an ignored response is an empty message
+impl From<Response> for OutgoingMessage {
+    fn from(response: Response) -> Self {
+        match response {
+            Response::PlainChat(message) => message.into(),
+            Response::Bot(message) => message.render_without_thinking_parts().into(),
+            Response::Card(card) => card.into(),
+            Response::Reaction(emoji) => Self {
+                reactions: vec![emoji],
+                ..Self::default()
+            },
+            Response::Ignored => Self::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outgoing_messages_degrade_to_text() {
+        let message = OutgoingMessage::builder()
+            .content("look at this")
+            .embeds(vec![Card::builder().title("a card").build()])
+            .attachments(vec![Attachment::new("roll.png", [0u8; 4])])
+            .reactions(vec!["🎲".to_string()])
+            .build();
+
+        insta::assert_snapshot!(message.to_text(), @r"
+        look at this
+
+        **a card**
+
+        📎 `roll.png`
+
+        🎲
+        ");
+
+        assert!(OutgoingMessage::from(Response::Ignored).is_empty());
+        assert_eq!(
+            OutgoingMessage::from(Response::Reaction("🦀".to_string())).to_text(),
+            "🦀"
+        );
+        assert!(!Mentions::default().everyone);
+    }
+}
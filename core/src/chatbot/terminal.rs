@@ -0,0 +1,335 @@
+//! a [`ChatBot`] that talks over the terminal instead of Discord,
+//! so commands and prompts can be tried out offline.
+//!
+//! every line typed into the [`Repl`] is a chat message, e.g. `!ultron roll d20`.
+//! lines starting with `@ultron` go to the language model,
+//! and lines starting with `/` control the REPL, see [`REPL_HELP`].
+use std::sync::Arc;
+
+use strum::IntoEnumIterator as _;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    sync::Mutex,
+};
+
+use crate::{
+    Channel, Response, User,
+    chatbot::{ChatBot, ChatInput, outgoing::OutgoingMessage},
+    event_processor::{Event, EventProcessor, EventType},
+    nlp::response::MessagePart,
+};
+
+pub const REPL_HELP: &str = "\
+type a message as if it was sent in chat, e.g. `!ultron roll d20`
+`@ultron <message>` talk to the language model
+`/channel <name>` switch channels
+`/user <name>` switch users
+`/help` show this again
+`/quit` leave";
+
+/// what the language model is addressed by in the [`Repl`]
+const LANGUAGE_MODEL_PREFIX: &str = "@ultron";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TerminalError {
+    #[error("terminal I/O failed: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<TerminalError> for crate::error::Error {
+    fn from(value: TerminalError) -> Self {
+        crate::error::Error::ChatBot(Box::new(value))
+    }
+}
+
+/// prints everything Ultron says to a writer, usually stdout
+#[derive(Debug)]
+pub struct TerminalBot<W> {
+    output: Arc<Mutex<W>>,
+}
+
+impl<W> Clone for TerminalBot<W> {
+    fn clone(&self) -> Self {
+        Self {
+            output: self.output.clone(),
+        }
+    }
+}
+
+impl TerminalBot<tokio::io::Stdout> {
+    pub fn stdout() -> Self {
+        Self::new(tokio::io::stdout())
+    }
+}
+
+impl<W> TerminalBot<W>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(output: W) -> Self {
+        Self {
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+
+    async fn print(&self, text: &str) -> Result<(), TerminalError> {
+        let mut output = self.output.lock().await;
+        output.write_all(text.as_bytes()).await?;
+        output.flush().await?;
+        Ok(())
+    }
+
+    async fn say(
+        &self,
+        channel: Channel,
+        speaker: &str,
+        message: &str,
+    ) -> Result<(), TerminalError> {
+        self.print(&format!("[#{channel}] {speaker}: {message}\n"))
+            .await
+    }
+
+    /// print a response, including the thinking parts other adapters leave out
+    pub async fn print_response(
+        &self,
+        channel: Channel,
+        response: Response,
+    ) -> Result<(), TerminalError> {
+        let Response::Bot(message) = response else {
+            return self.send(channel, &OutgoingMessage::from(response)).await;
+        };
+
+        for part in message.parts() {
+            match part {
+                MessagePart::Thinking(thinking) => {
+                    self.say(channel, "ultron 💭", thinking.trim()).await?
+                }
+                MessagePart::Text(text) => self.say(channel, "ultron", text.trim()).await?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<W> ChatBot for TerminalBot<W>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Error = TerminalError;
+
+    async fn send_message(&self, channel: Channel, message: &str) -> Result<(), Self::Error> {
+        self.say(channel, "ultron", message).await
+    }
+}
+
+/// reads chat messages from the terminal and feeds them to an [`EventProcessor`],
+/// as a simulated [`User`] in a simulated [`Channel`]
+#[derive(Debug)]
+pub struct Repl<W> {
+    event_processor: Arc<EventProcessor>,
+    bot: TerminalBot<W>,
+    user: User,
+    channel: Channel,
+}
+
+impl<W> Repl<W>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(event_processor: Arc<EventProcessor>, bot: TerminalBot<W>) -> Self {
+        Self {
+            event_processor,
+            bot,
+            user: User::from("developer"),
+            channel: Channel::Debug,
+        }
+    }
+
+    pub fn with_user(self, user: impl Into<User>) -> Self {
+        Self {
+            user: user.into(),
+            ..self
+        }
+    }
+
+    pub fn with_channel(self, channel: Channel) -> Self {
+        Self { channel, ..self }
+    }
+
+    /// read lines until the input runs out or someone types `/quit`
+    pub async fn run<R>(mut self, input: R) -> Result<(), TerminalError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        self.bot.print(&format!("{REPL_HELP}\n")).await?;
+
+        let mut lines = input.lines();
+        loop {
+            self.prompt().await?;
+
+            let Some(line) = lines.next_line().await? else {
+                break;
+            };
+
+            if !self.handle_line(line.trim()).await? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn prompt(&self) -> Result<(), TerminalError> {
+        self.bot
+            .print(&format!("[#{}] {}> ", self.channel, self.user))
+            .await
+    }
+
+    /// returns whether to keep going
+    async fn handle_line(&mut self, line: &str) -> Result<bool, TerminalError> {
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+
+        match (command, argument) {
+            ("", _) => {}
+            ("/quit" | "/exit", _) => return Ok(false),
+            ("/help", _) => self.bot.print(&format!("{REPL_HELP}\n")).await?,
+            ("/user", "") => self.note(&format!("you are {}", self.user)).await?,
+            ("/user", user) => {
+                self.user = User::from(user);
+                self.note(&format!("you are now {}", self.user)).await?;
+            }
+            ("/channel", "") => self.note(&format!("you're in #{}", self.channel)).await?,
+            ("/channel", channel) => match channel.trim_start_matches('#').parse() {
+                Ok(channel) => {
+                    self.channel = channel;
+                    self.note(&format!("you're now in #{}", self.channel))
+                        .await?;
+                }
+                Err(_) => {
+                    let channels = Channel::iter()
+                        .map(|channel| channel.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    self.note(&format!(
+                        "no channel called `{channel}`, try one of {channels}"
+                    ))
+                    .await?;
+                }
+            },
+            (LANGUAGE_MODEL_PREFIX, prompt) => {
+                let event = Event::builder()
+                    .user(self.user.clone())
+                    .content(prompt.to_string())
+                    .event_type(EventType::LanguageModel)
+                    .channel(self.channel)
+                    .build();
+                self.process(event).await?;
+            }
+            _ => {
+                let chat_input = ChatInput::builder()
+                    .user(self.user.clone())
+                    .content(line)
+                    .channel(self.channel)
+                    .build();
+
+                match Event::new(&chat_input, EventType::Plain) {
+                    Ok(event) => self.process(event).await?,
+                    Err(error) => self.note(&format!("ya blew it: {error}")).await?,
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn process(&self, event: Event) -> Result<(), TerminalError> {
+        match Box::pin(self.event_processor.process(event)).await {
+            Ok(responses) => {
+                for response in responses {
+                    self.bot.print_response(self.channel, response).await?;
+                }
+            }
+            Err(error) => self.note(&format!("ya blew it: {error}")).await?,
+        }
+
+        Ok(())
+    }
+
+    /// something from the REPL itself, rather than Ultron
+    async fn note(&self, message: &str) -> Result<(), TerminalError> {
+        self.bot.print(&format!("* {message}\n")).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command::CommandConsumer,
+        dice::DiceRoller,
+        nlp::{AgentError, ChatAgent, response::MessageParts},
+    };
+
+    /// thinks out loud, then says what it was told
+    #[derive(Debug, Clone)]
+    struct PonderingAgent;
+
+    impl ChatAgent for PonderingAgent {
+        async fn chat(&self, event: &Event) -> Result<Event, AgentError> {
+            let content = [
+                MessagePart::Thinking("hmm".to_string()),
+                MessagePart::Text(event.content.to_string()),
+            ]
+            .into_iter()
+            .collect::<MessageParts>();
+
+            Ok(Event {
+                user: User::Ultron,
+                content,
+                ..event.clone()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn repl_feeds_lines_to_the_event_processor() {
+        let event_processor = EventProcessor::new()
+            .with_consumer(CommandConsumer::new(DiceRoller::max()))
+            .with_consumer(PonderingAgent);
+        let bot = TerminalBot::new(Vec::new());
+        let repl = Repl::new(Arc::new(event_processor), bot.clone());
+
+        let input = "\
+!ultron echo hello
+/channel dnd
+/user bob
+@ultron hi there
+/channel tavern
+/quit
+!ultron echo never";
+        repl.run(input.as_bytes())
+            .await
+            .expect("repl should not error");
+
+        let output = bot.output.lock().await.clone();
+        let output = String::from_utf8(output).expect("output should be text");
+        let output = output
+            .strip_prefix(&format!("{REPL_HELP}\n"))
+            .expect("should start with help")
+            .trim_end();
+
+        insta::assert_snapshot!(output, @r"
+        [#debug] developer> [#debug] ultron: hello
+        [#debug] developer> * you're now in #dnd
+        [#dnd] developer> * you are now bob
+        [#dnd] bob> [#dnd] ultron 💭: hmm
+        [#dnd] ultron: hi there
+        [#dnd] bob> * no channel called `tavern`, try one of debug, psa, dnd, fun_zone_bots, fun_zone_stream
+        [#dnd] bob>
+        ");
+    }
+}
@@ -1,14 +1,28 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
 use serde::{Deserialize, Serialize};
-use strum::{Display, EnumIter, EnumMessage, IntoEnumIterator as _};
+use strum::{Display, EnumIter, EnumMessage, EnumProperty, IntoEnumIterator as _, IntoStaticStr};
 use tyche::dice;
 
 use crate::{
-    Response,
-    copypasta::{copy_pasta, copy_pasta_names},
-    dice::{DiceRollResult, DiceRoller, RollerImpl},
+    Channel, Response, User,
+    card::{Card, CardField, Color},
+    copypasta::Copypastas,
+    custom_command::{
+        CustomCommands, TemplateContext, is_valid_name, render_simple, render_template,
+    },
+    dice::{
+        DiceRollResult, DiceRoller, RollerImpl,
+        audit::RollAudit,
+        history::{RollHistory, RollRecord},
+        macros::DiceMacros,
+        sheet::{CharacterSheets, parse_assignments, sheet_card, substitute_variables},
+        stats::RollStats,
+    },
     event_processor::{Event, EventConsumer, EventError, EventType},
+    initiative::{DEFAULT_INITIATIVE_ROLL, Initiative},
+    karma::KarmaStore,
+    rate_limit::RateLimiter,
 };
 
 /// consumes [`Event`]s and produces [`Response`]s
@@ -21,26 +35,237 @@ use crate::{
 #[derive(Debug, Clone)]
 pub struct CommandConsumer<TRoller> {
     pub dice_roller: DiceRoller<TRoller>,
+    pub karma: KarmaStore,
+    pub custom_commands: CustomCommands,
+    /// handles the `llm` command, usually a [`crate::nlp::ChatAgent`]
+    pub language_model: Option<Arc<dyn EventConsumer>>,
+    pub rate_limiter: RateLimiter,
+    pub dice_macros: DiceMacros,
+    pub roll_history: RollHistory,
+    pub character_sheets: CharacterSheets,
+    pub initiative: Initiative,
+    /// makes rolls verifiable, see [`RollAudit`]
+    pub roll_audit: Option<RollAudit>,
+    pub copypastas: Copypastas,
 }
 
+/// how many rolls `roll history` shows if you don't ask for a number
+const DEFAULT_HISTORY_LENGTH: usize = 5;
+
+/// the most rolls `roll history` will show
+const MAX_HISTORY_LENGTH: usize = 20;
+
+/// the most stages allowed in a single pipeline, e.g. `roll d20 | echo`
+pub const MAX_PIPELINE_STAGES: usize = 8;
+
 impl<TRoller> CommandConsumer<TRoller>
 where
     TRoller: RollerImpl,
 {
     pub fn new(dice_roller: DiceRoller<TRoller>) -> Self {
-        Self { dice_roller }
+        Self {
+            dice_roller,
+            karma: KarmaStore::default(),
+            custom_commands: CustomCommands::default(),
+            language_model: None,
+            rate_limiter: RateLimiter::default(),
+            dice_macros: DiceMacros::default(),
+            roll_history: RollHistory::default(),
+            character_sheets: CharacterSheets::default(),
+            initiative: Initiative::default(),
+            roll_audit: None,
+            copypastas: Copypastas::default(),
+        }
+    }
+
+    /// use a shared [`KarmaStore`],
+    /// e.g. the one used by [`crate::karma::KarmaConsumer`]
+    pub fn with_karma(self, karma: KarmaStore) -> Self {
+        Self { karma, ..self }
+    }
+
+    /// use a persistent set of [`CustomCommands`]
+    pub fn with_custom_commands(self, custom_commands: CustomCommands) -> Self {
+        Self {
+            custom_commands,
+            ..self
+        }
+    }
+
+    /// let the `llm` command talk to a language model
+    pub fn with_language_model<T>(self, language_model: T) -> Self
+    where
+        T: EventConsumer + 'static,
+    {
+        Self {
+            language_model: Some(Arc::new(language_model)),
+            ..self
+        }
+    }
+
+    /// use a shared [`RateLimiter`],
+    /// e.g. to expose its counters over HTTP
+    pub fn with_rate_limiter(self, rate_limiter: RateLimiter) -> Self {
+        Self {
+            rate_limiter,
+            ..self
+        }
+    }
+
+    /// use persistent [`DiceMacros`],
+    /// e.g. shared with the MCP server
+    pub fn with_dice_macros(self, dice_macros: DiceMacros) -> Self {
+        Self {
+            dice_macros,
+            ..self
+        }
+    }
+
+    /// record rolls in a shared [`RollHistory`],
+    /// e.g. to expose it over MCP
+    pub fn with_roll_history(self, roll_history: RollHistory) -> Self {
+        Self {
+            roll_history,
+            ..self
+        }
+    }
+
+    /// use persistent [`CharacterSheets`] for `@name` variables in rolls
+    pub fn with_character_sheets(self, character_sheets: CharacterSheets) -> Self {
+        Self {
+            character_sheets,
+            ..self
+        }
+    }
+
+    /// keep initiative order in a persistent [`Initiative`] tracker
+    pub fn with_initiative(self, initiative: Initiative) -> Self {
+        Self { initiative, ..self }
+    }
+
+    /// roll with seeds from a [`RollAudit`] so every roll can be verified later.
+    /// this replaces the randomness of the [`DiceRoller`] for plain rolls
+    pub fn with_roll_audit(self, roll_audit: RollAudit) -> Self {
+        Self {
+            roll_audit: Some(roll_audit),
+            ..self
+        }
+    }
+
+    /// use persistent [`Copypastas`], e.g. with editors from the CLI
+    pub fn with_copypastas(self, copypastas: Copypastas) -> Self {
+        Self { copypastas, ..self }
+    }
+
+    /// run a command, or a pipeline of commands separated by `|`, with `\|` for a literal `|`.
+    /// each stage in a pipeline gets the text output of the previous stage
+    /// appended to its input, e.g. `roll 4d6k3 | echo Strength:`
+    pub async fn consume(&self, event: &Event) -> Result<Response, EventError> {
+        let input = match event.event_type {
+            EventType::Command => event.content.to_string(),
+            _ => return Err(CommandParseError::MissingPrefix(event.content.to_string()).into()),
+        };
+
+        let mut response = Response::Ignored;
+        for (index, stage) in pipeline_stages(&input)?.into_iter().enumerate() {
+            let stage_input = if index == 0 {
+                stage
+            } else {
+                format!("{} {}", stage, response.to_text())
+            };
+
+            response = self.run(&stage_input, event).await?;
+        }
+
+        Ok(response)
     }
 
-    pub async fn consume(&self, event: &Event) -> Result<String, EventError> {
-        let command: Command = event.try_into()?;
+    /// run a single command
+    async fn run(&self, input: &str, event: &Event) -> Result<Response, EventError> {
+        let command = match input.parse::<Command>() {
+            // fall back to user-defined commands
+            Err(CommandParseError::UndefinedCommand { command, args })
+                if self.custom_commands.get(&command).await.is_some() =>
+            {
+                Command::Custom {
+                    name: command,
+                    args,
+                }
+            }
+            result => result?,
+        };
+
+        if command.is_rate_limited()
+            && let Err(limited) = self
+                .rate_limiter
+                .check(&command, &event.user, event.channel)
+                .await
+        {
+            tracing::info!(%limited, "command rate limited");
+            return Ok(limited.to_string().into());
+        }
+
         let context = CommandContext {
             dice_roller: self.dice_roller.clone(),
+            karma: self.karma.clone(),
+            custom_commands: self.custom_commands.clone(),
+            language_model: self.language_model.clone(),
+            dice_macros: self.dice_macros.clone(),
+            roll_history: self.roll_history.clone(),
+            character_sheets: self.character_sheets.clone(),
+            initiative: self.initiative.clone(),
+            roll_audit: self.roll_audit.clone(),
+            copypastas: self.copypastas.clone(),
+            user: event.user.clone(),
+            identity: event.identity.clone(),
+            channel: event.channel,
         };
-        let response = command.execute(context)?;
+        let response = command.execute(context).await?;
         Ok(response)
     }
 }
 
+/// split the input into pipeline stages on `|`.
+/// `\|` is a literal `|`, and definitions are never split, so templates can contain `|`.
+fn pipeline_stages(input: &str) -> Result<Vec<String>, CommandParseError> {
+    if input.split_whitespace().next() == Some("define") {
+        return Ok(vec![input.to_string()]);
+    }
+
+    let mut stages = Vec::new();
+    let mut stage = String::new();
+    let mut characters = input.chars().peekable();
+    while let Some(character) = characters.next() {
+        match character {
+            '\\' if characters.peek() == Some(&'|') => {
+                characters.next();
+                stage.push('|');
+            }
+            '|' => stages.push(std::mem::take(&mut stage)),
+            character => stage.push(character),
+        }
+    }
+    stages.push(stage);
+
+    let stages: Vec<String> = stages
+        .into_iter()
+        .map(|stage| stage.trim().to_string())
+        .collect();
+
+    if stages.len() > MAX_PIPELINE_STAGES {
+        return Err(CommandParseError::PipelineTooLong {
+            stages: stages.len(),
+            max: MAX_PIPELINE_STAGES,
+        });
+    }
+
+    if stages.len() > 1 && stages.iter().any(|stage| stage.is_empty()) {
+        return Err(CommandParseError::EmptyPipelineStage(input.to_string()));
+    }
+
+    Ok(stages)
+}
+
 #[cfg(test)]
 impl CommandConsumer<tyche::dice::roller::Max> {
     pub fn with_max_dice_roller() -> Self {
@@ -54,7 +279,7 @@ where
     TRoller: RollerImpl + 'static,
 {
     async fn consume_event(&self, event: &Event) -> Result<Response, EventError> {
-        self.consume(event).await.map(Response::PlainChat)
+        self.consume(event).await
     }
 
     fn should_consume_event(&self, event: &Event) -> bool {
@@ -62,12 +287,31 @@ where
     }
 }
 
+/// whether `name` triggers a builtin command.
+/// these can't be used for [`Command::Define`].
+fn is_builtin_command(name: &str) -> bool {
+    CommandDiscriminants::iter()
+        .filter(|command| *command != CommandDiscriminants::Custom)
+        .any(|command| <&'static str>::from(command) == name)
+}
+
 #[derive(thiserror::Error, Debug, PartialEq, Clone)]
 pub enum CommandParseError {
     #[error("input is missing prefix {0}")]
     MissingPrefix(String),
     #[error("input is missing command {0}")]
     MissingCommand(String),
+    #[error("command '{command}' is missing argument '{argument}'")]
+    MissingArgument {
+        command: String,
+        argument: &'static str,
+    },
+    #[error("command '{command}' got an invalid argument '{argument}'")]
+    InvalidArgument { command: String, argument: String },
+    #[error("pipeline has an empty stage: {0}")]
+    EmptyPipelineStage(String),
+    #[error("pipeline has {stages} stages, the most allowed is {max}")]
+    PipelineTooLong { stages: usize, max: usize },
     #[error("undefined command in input '{command}' with args {args:?}")]
     UndefinedCommand {
         command: String,
@@ -78,15 +322,45 @@ pub enum CommandParseError {
 #[derive(
     Debug, Clone, PartialEq, strum::EnumDiscriminants, Serialize, Deserialize, utoipa::ToSchema,
 )]
-#[strum_discriminants(derive(EnumIter, Display, EnumMessage))]
+#[strum_discriminants(derive(EnumIter, Display, EnumMessage, EnumProperty, Hash, IntoStaticStr))]
 #[strum_discriminants(strum(serialize_all = "snake_case"))]
 pub enum Command {
     #[strum_discriminants(strum(message = "make Ultron say something"))]
     Echo(String),
     #[strum_discriminants(strum(message = "roll some dice"))]
     Roll(String),
-    #[strum_discriminants(strum(message = "things that bear repeating"))]
+    #[strum_discriminants(strum(
+        to_string = "pasta",
+        message = "things that bear repeating, e.g. `pasta linux @bob`, `pasta random`, `pasta search <word>` or `pasta add <name> <text>`",
+        props(user_cooldown_secs = "30", channel_cooldown_secs = "10")
+    ))]
     Copypasta(String),
+    #[strum_discriminants(strum(message = "check the karma of something"))]
+    Karma(String),
+    #[strum_discriminants(strum(message = "the things with the most karma"))]
+    Leaderboard,
+    #[strum_discriminants(strum(
+        message = "teach Ultron a new trick, e.g. `define greet hello {user}, welcome to {channel}`",
+        props(daily_quota = "20")
+    ))]
+    Define { name: String, template: String },
+    #[strum_discriminants(strum(message = "make Ultron forget a trick"))]
+    Undefine(String),
+    #[strum_discriminants(strum(
+        message = "ask the language model, e.g. `pasta rust | llm summarize this`",
+        props(user_cooldown_secs = "10", daily_quota = "50")
+    ))]
+    Llm(String),
+    /// a command defined at runtime with [`Command::Define`]
+    #[strum_discriminants(strum(
+        message = "run a user-defined command",
+        props(user_cooldown_secs = "5")
+    ))]
+    Custom { name: String, args: Option<String> },
+    #[strum_discriminants(strum(message = "manage your character sheet for `@var` rolls"))]
+    Sheet(String),
+    #[strum_discriminants(strum(message = "track initiative order in a fight"))]
+    Init(String),
     #[strum_discriminants(strum(message = "get help"))]
     Help,
 }
@@ -95,46 +369,634 @@ pub enum Command {
 /// that lives for the duration of the command execution.
 pub struct CommandContext<T: dice::Roller> {
     pub dice_roller: DiceRoller<T>,
+    pub karma: KarmaStore,
+    pub custom_commands: CustomCommands,
+    pub language_model: Option<Arc<dyn EventConsumer>>,
+    pub dice_macros: DiceMacros,
+    pub roll_history: RollHistory,
+    pub character_sheets: CharacterSheets,
+    pub initiative: Initiative,
+    pub roll_audit: Option<RollAudit>,
+    pub copypastas: Copypastas,
+    /// the user who issued the command
+    pub user: User,
+    /// who the platform says issued the command, see [`Event::identity`]
+    pub identity: Option<String>,
+    /// the channel the command was issued in
+    pub channel: Channel,
 }
 
 impl Command {
-    pub fn execute<TRoller>(self, context: CommandContext<TRoller>) -> Result<String, EventError>
+    /// whether the [`RateLimiter`] gets a say.
+    /// only serving pasta is limited, editors shouldn't wait to fix a typo
+    fn is_rate_limited(&self) -> bool {
+        match self {
+            Command::Copypasta(input) => !matches!(
+                input.split_whitespace().next(),
+                Some("add" | "edit" | "remove")
+            ),
+            _ => true,
+        }
+    }
+
+    pub async fn execute<TRoller>(
+        self,
+        context: CommandContext<TRoller>,
+    ) -> Result<Response, EventError>
     where
         TRoller: RollerImpl,
     {
         // escape sequence is wrong here
         tracing::debug!(command = ?self, "executing command");
-        let result: String = match self {
-            Command::Echo(message) => message.to_string(),
-            Command::Roll(input) => {
-                let dice_roll = DiceRollResult::from_str(&input, context.dice_roller.clone())?;
-                dice_roll.to_string()
-            }
-            Command::Help => CommandDiscriminants::iter().fold(String::new(), |acc, command| {
-                format!(
-                    "{}\n✨`{}` 👉 {}",
-                    acc,
-                    command,
-                    command.get_message().unwrap_or("oops no message")
-                )
-            }),
-            Command::Copypasta(input) => {
-                if input == "list" {
-                    let names = copy_pasta_names()
+        let result: Response = match self {
+            Command::Echo(message) => message.into(),
+            Command::Roll(input) => execute_roll(&input, &context).await?,
+            Command::Sheet(input) => execute_sheet(&input, &context).await?,
+            Command::Init(input) => execute_init(&input, &context).await?,
+            Command::Help => {
+                let builtin = CommandDiscriminants::iter()
+                    .filter(|command| *command != CommandDiscriminants::Custom)
+                    .map(|command| {
+                        CardField::new(
+                            format!("✨`{}`", command),
+                            command.get_message().unwrap_or("oops no message"),
+                        )
+                    });
+
+                let custom = context.custom_commands.names().await;
+                let custom = (!custom.is_empty()).then(|| {
+                    let names = custom
                         .into_iter()
                         .map(|name| format!("✨`{}`", name))
                         .collect::<Vec<_>>()
-                        .join("\n\n");
-                    format!("types of pasta 🍝:\n\n{}", names)
+                        .join("\n");
+                    CardField::new("user-defined commands 🧪", names)
+                });
+
+                Card::builder()
+                    .title("commands")
+                    .fields(builtin.chain(custom).collect())
+                    .footer("prefix commands with `!ultron`")
+                    .color(Color::ULTRON)
+                    .build()
+                    .into()
+            }
+            Command::Copypasta(input) => execute_pasta(&input, &context).await?,
+            Command::Karma(thing) => {
+                let score = context.karma.get(&thing).await;
+                format!("`{}` has {} karma", thing, score).into()
+            }
+            Command::Leaderboard => {
+                let leaderboard = context.karma.leaderboard().await;
+                if leaderboard.is_empty() {
+                    "nobody has any karma. typical 🙄".to_string().into()
                 } else {
-                    copy_pasta(&input).unwrap_or(format!("'{}' not found. try again loser", input))
+                    let entries = leaderboard
+                        .into_iter()
+                        .enumerate()
+                        .map(|(rank, (thing, score))| {
+                            format!("{}. `{}` {}", rank + 1, thing, score)
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    format!("karma leaderboard 🏆\n\n{}", entries).into()
                 }
             }
+            Command::Define { name, template } => {
+                let reply = if !is_valid_name(&name) {
+                    format!("`{}` is a terrible name for a command. try again", name)
+                } else if is_builtin_command(&name) {
+                    format!("`{}` is already a command. nice try", name)
+                } else {
+                    match context.custom_commands.define(&name, &template).await? {
+                        Some(_) => format!("redefined `{}` 🧠", name),
+                        None => format!("learned `{}` 🧠", name),
+                    }
+                };
+                reply.into()
+            }
+            Command::Undefine(name) => match context.custom_commands.remove(&name).await? {
+                Some(_) => format!("forgot `{}` 🗑️", name).into(),
+                None => format!("'{}' not found. try again loser", name).into(),
+            },
+            Command::Llm(prompt) => {
+                let Some(language_model) = context.language_model else {
+                    return Ok("my brain is offline 🔌".to_string().into());
+                };
+
+                let event = Event::builder()
+                    .user(context.user)
+                    .content(prompt)
+                    .event_type(EventType::LanguageModel)
+                    .channel(context.channel)
+                    .build();
+
+                language_model.consume_event(&event).await?
+            }
+            Command::Custom { name, args } => {
+                let template = context.custom_commands.get(&name).await.ok_or(
+                    CommandParseError::UndefinedCommand {
+                        command: name.clone(),
+                        args: args.clone(),
+                    },
+                )?;
+
+                let template_context = TemplateContext {
+                    user: &context.user,
+                    channel: context.channel,
+                    args: args.as_deref().unwrap_or(""),
+                    copypastas: &context.copypastas,
+                };
+
+                render_template(&template, &template_context, &context.dice_roller)
+                    .await?
+                    .into()
+            }
         };
         Ok(result)
     }
 }
 
+/// `roll` and its macro subcommands
+async fn execute_roll<TRoller>(
+    input: &str,
+    context: &CommandContext<TRoller>,
+) -> Result<Response, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let (subcommand, rest) = input
+        .split_once(char::is_whitespace)
+        .map(|(subcommand, rest)| (subcommand, rest.trim()))
+        .unwrap_or((input, ""));
+
+    let response = match subcommand {
+        "save" => {
+            let (name, expression) = rest
+                .split_once(char::is_whitespace)
+                .map(|(name, expression)| (name, expression.trim()))
+                .ok_or(CommandParseError::MissingArgument {
+                    command: "roll save".to_string(),
+                    argument: "expression",
+                })?;
+
+            match context
+                .dice_macros
+                .save(&context.user, name, expression)
+                .await?
+            {
+                Some(previous) => {
+                    format!("`{}` is now `{}` (was `{}`) 🎲", name, expression, previous)
+                }
+                None => format!("saved `{}` as `{}` 🎲", name, expression),
+            }
+            .into()
+        }
+        "delete" if !rest.is_empty() => {
+            match context.dice_macros.delete(&context.user, rest).await? {
+                Some(_) => format!("deleted `{}` 🗑️", rest),
+                None => format!("'{}' not found. try again loser", rest),
+            }
+            .into()
+        }
+        "macros" => {
+            let macros = context.dice_macros.list(&context.user).await;
+            if macros.is_empty() {
+                format!(
+                    "{} has no macros. try `roll save attack 1d20+5`",
+                    context.user
+                )
+                .into()
+            } else {
+                Card::builder()
+                    .title(format!("🎲 {}'s macros", context.user))
+                    .fields(
+                        macros
+                            .into_iter()
+                            .map(|(name, expression)| {
+                                CardField::inline(name, format!("`{}`", expression))
+                            })
+                            .collect(),
+                    )
+                    .color(Color::ULTRON)
+                    .build()
+                    .into()
+            }
+        }
+        "stats" if !rest.is_empty() => {
+            let expression = context.dice_macros.expand(&context.user, rest).await;
+            let expression = context
+                .character_sheets
+                .substitute(&context.user, &expression)
+                .await?;
+            // enumerating or sampling can take a while, keep it off the async threads
+            let limits = *context.dice_roller.limits();
+            let stats =
+                tokio::task::spawn_blocking(move || RollStats::compute(&expression, &limits))
+                    .await??;
+            Card::from(&stats).into()
+        }
+        "history" => {
+            let limit = match rest {
+                "" => DEFAULT_HISTORY_LENGTH,
+                limit => limit
+                    .parse::<usize>()
+                    .map_err(|_| CommandParseError::InvalidArgument {
+                        command: "roll history".to_string(),
+                        argument: limit.to_string(),
+                    })?
+                    .min(MAX_HISTORY_LENGTH),
+            };
+
+            let rolls = context.roll_history.recent(&context.user, limit).await;
+            if rolls.is_empty() {
+                format!("{} hasn't rolled anything yet 🎲", context.user).into()
+            } else {
+                Card::builder()
+                    .title(format!("🎲 {}'s last {} rolls", context.user, rolls.len()))
+                    .description(
+                        rolls
+                            .iter()
+                            .map(RollRecord::to_string)
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )
+                    .color(Color::ULTRON)
+                    .build()
+                    .into()
+            }
+        }
+        "luck" => {
+            let luck = context.roll_history.luck(&context.user).await;
+            format!("🍀 {}: {}", context.user, luck).into()
+        }
+        "luckiest" => {
+            let session = context.roll_history.session_luck(Channel::Dnd).await;
+            match session.first() {
+                None => "nobody has rolled in #dnd lately. cowards 🐔"
+                    .to_string()
+                    .into(),
+                Some((luckiest, _)) => Card::builder()
+                    .title(format!("🍀 luckiest player: {}", luckiest))
+                    .fields(
+                        session
+                            .iter()
+                            .map(|(user, luck)| CardField::new(user, luck.to_string()))
+                            .collect(),
+                    )
+                    .footer("this session in #dnd")
+                    .color(Color::SUCCESS)
+                    .build()
+                    .into(),
+            }
+        }
+        "verify" | "session" => match &context.roll_audit {
+            Some(roll_audit) => execute_audit(subcommand, rest, roll_audit, context).await?,
+            None => "rolls aren't being audited here 🤷".to_string().into(),
+        },
+        _ => {
+            let expression = context.dice_macros.expand(&context.user, input).await;
+            let (dice_roll, footer) = match &context.roll_audit {
+                // don't burn a roll ID on the help message
+                Some(roll_audit) if expression != "help" => {
+                    let expression = context
+                        .character_sheets
+                        .substitute(&context.user, &expression)
+                        .await?;
+                    let (ticket, dice_roll) = roll_audit
+                        .roll(&context.user, &expression, context.dice_roller.limits())
+                        .await?;
+                    let footer = format!("roll #{0} · `roll verify {0}`", ticket.id);
+                    (dice_roll, Some(footer))
+                }
+                _ => {
+                    let sheet = context.character_sheets.get(&context.user).await;
+                    match DiceRollResult::from_str_with_sheet(
+                        &expression,
+                        context.dice_roller.clone(),
+                        &sheet,
+                    )? {
+                        DiceRollResult::Roll(dice_roll) => (dice_roll, None),
+                        help @ DiceRollResult::Help(_) => return Ok(help.to_string().into()),
+                    }
+                }
+            };
+
+            context
+                .roll_history
+                .record(RollRecord::new(
+                    &context.user,
+                    context.channel,
+                    &expression,
+                    &dice_roll,
+                ))
+                .await?;
+            Card {
+                footer,
+                ..Card::from(&dice_roll)
+            }
+            .into()
+        }
+    };
+
+    Ok(response)
+}
+
+/// `roll verify` and `roll session`
+async fn execute_audit<TRoller>(
+    subcommand: &str,
+    rest: &str,
+    roll_audit: &RollAudit,
+    context: &CommandContext<TRoller>,
+) -> Result<Response, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let invalid = || CommandParseError::InvalidArgument {
+        command: format!("roll {}", subcommand),
+        argument: rest.to_string(),
+    };
+
+    let response = match (subcommand, rest) {
+        ("verify", id) => {
+            let id = id.trim_start_matches('#').parse().map_err(|_| invalid())?;
+            let verification = roll_audit.verify(id, context.dice_roller.limits()).await?;
+            Card::from(&verification).into()
+        }
+        ("session", "") => Card::from(&roll_audit.session().await?).into(),
+        ("session", "end") => match roll_audit.end_session().await? {
+            Some(session) => Card::from(&session).into(),
+            None => "no audit session is running 🤷".to_string().into(),
+        },
+        _ => return Err(invalid().into()),
+    };
+
+    Ok(response)
+}
+
+/// `pasta` and its subcommands. anything else is the name of a pasta,
+/// optionally followed by a `{target}`, e.g. `pasta linux @bob`
+async fn execute_pasta<TRoller>(
+    input: &str,
+    context: &CommandContext<TRoller>,
+) -> Result<Response, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let (subcommand, rest) = input
+        .split_once(char::is_whitespace)
+        .map(|(subcommand, rest)| (subcommand, rest.trim()))
+        .unwrap_or((input, ""));
+
+    let missing = |argument| CommandParseError::MissingArgument {
+        command: format!("pasta {}", subcommand),
+        argument,
+    };
+
+    let copypastas = &context.copypastas;
+    let response = match subcommand {
+        "list" => {
+            let names = copypastas
+                .copy_pasta_names()
+                .await
+                .into_iter()
+                .map(|name| format!("✨`{}`", name))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            format!("types of pasta 🍝:\n\n{}", names).into()
+        }
+        "search" => {
+            if rest.is_empty() {
+                return Err(missing("word").into());
+            }
+            let names = copypastas.search(rest).await;
+            if names.is_empty() {
+                format!("no pasta mentions `{}` 🤷", rest).into()
+            } else {
+                let names = names
+                    .into_iter()
+                    .map(|name| format!("✨`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                format!("pasta that mentions `{}` 🔍:\n\n{}", rest, names).into()
+            }
+        }
+        "random" => match copypastas.random().await {
+            Some((_, pasta)) => serve_pasta(&pasta, rest, context),
+            None => "the pantry is empty 🤷".to_string().into(),
+        },
+        "add" | "edit" => {
+            let (name, text) = rest
+                .split_once(char::is_whitespace)
+                .map(|(name, text)| (name, text.trim()))
+                .ok_or_else(|| missing("text"))?;
+            if name.is_empty() {
+                return Err(missing("name").into());
+            }
+
+            if subcommand == "add" {
+                copypastas
+                    .add(&context.user, context.identity.as_deref(), name, text)
+                    .await?;
+                format!("cooked up `{}` 🍝", name).into()
+            } else {
+                copypastas
+                    .edit(&context.user, context.identity.as_deref(), name, text)
+                    .await?;
+                format!("reheated `{}` 🍝", name).into()
+            }
+        }
+        "remove" => {
+            if rest.is_empty() {
+                return Err(missing("name").into());
+            }
+            copypastas
+                .remove(&context.user, context.identity.as_deref(), rest)
+                .await?;
+            format!("threw out `{}` 🗑️", rest).into()
+        }
+        name => match copypastas.find(name).await {
+            Some((_, pasta)) => serve_pasta(&pasta, rest, context),
+            None => format!("'{}' not found. try again loser", name).into(),
+        },
+    };
+
+    Ok(response)
+}
+
+/// fill in the simple placeholders in a pasta, aimed at `target`.
+/// `{roll:..}` and `{pasta:..}` stay as they are, see [`render_simple`]
+fn serve_pasta<TRoller>(pasta: &str, target: &str, context: &CommandContext<TRoller>) -> Response
+where
+    TRoller: RollerImpl,
+{
+    let template_context = TemplateContext {
+        user: &context.user,
+        channel: context.channel,
+        args: target,
+        copypastas: &context.copypastas,
+    };
+
+    render_simple(pasta, &template_context).into()
+}
+
+/// `sheet` and its subcommands
+async fn execute_sheet<TRoller>(
+    input: &str,
+    context: &CommandContext<TRoller>,
+) -> Result<Response, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let (subcommand, rest) = input
+        .split_once(char::is_whitespace)
+        .map(|(subcommand, rest)| (subcommand, rest.trim()))
+        .unwrap_or((input, ""));
+
+    let response = match subcommand {
+        "" | "show" => {
+            let sheet = context.character_sheets.get(&context.user).await;
+            if sheet.is_empty() {
+                format!(
+                    "{} has no sheet. try `sheet set str 3 prof 2`",
+                    context.user
+                )
+                .into()
+            } else {
+                sheet_card(&context.user, &sheet).into()
+            }
+        }
+        "set" => {
+            let variables = parse_assignments(rest)?;
+            if variables.is_empty() {
+                return Err(CommandParseError::MissingArgument {
+                    command: "sheet set".to_string(),
+                    argument: "variables",
+                }
+                .into());
+            }
+
+            let sheet = context
+                .character_sheets
+                .set(&context.user, variables)
+                .await?;
+            sheet_card(&context.user, &sheet).into()
+        }
+        "unset" if !rest.is_empty() => {
+            let names: Vec<&str> = rest
+                .split_whitespace()
+                .map(|name| name.trim_start_matches('@'))
+                .collect();
+            let removed = context
+                .character_sheets
+                .unset(&context.user, &names)
+                .await?;
+            if removed.is_empty() {
+                "nothing to forget 🤷".to_string().into()
+            } else {
+                format!("forgot `@{}` 🗑️", removed.join("`, `@")).into()
+            }
+        }
+        "import" if !rest.is_empty() => {
+            // people paste JSON into Discord wrapped in a code block
+            let actor_json = rest.trim_start_matches("```json").trim_matches('`').trim();
+            let sheet = context
+                .character_sheets
+                .import_foundry(&context.user, actor_json)
+                .await?;
+            sheet_card(&context.user, &sheet).into()
+        }
+        subcommand => {
+            return Err(CommandParseError::InvalidArgument {
+                command: "sheet".to_string(),
+                argument: subcommand.to_string(),
+            }
+            .into());
+        }
+    };
+
+    Ok(response)
+}
+
+/// `init` and its subcommands, per channel
+async fn execute_init<TRoller>(
+    input: &str,
+    context: &CommandContext<TRoller>,
+) -> Result<Response, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let (subcommand, rest) = input
+        .split_once(char::is_whitespace)
+        .map(|(subcommand, rest)| (subcommand, rest.trim()))
+        .unwrap_or((input, ""));
+
+    let initiative = &context.initiative;
+    let channel = context.channel;
+    let mut roller = context.dice_roller.clone();
+
+    let encounter = match subcommand {
+        "" | "show" => initiative.get(channel).await,
+        "add" => {
+            let (name, expression) = match rest.split_once(char::is_whitespace) {
+                Some((name, expression)) => (name, expression.trim()),
+                None => (rest, DEFAULT_INITIATIVE_ROLL),
+            };
+            if name.is_empty() {
+                return Err(CommandParseError::MissingArgument {
+                    command: "init add".to_string(),
+                    argument: "name",
+                }
+                .into());
+            }
+
+            let expression = context.dice_macros.expand(&context.user, expression).await;
+            let sheet = context.character_sheets.get(&context.user).await;
+            let expression = substitute_variables(&expression, &sheet)?;
+
+            initiative
+                .update(channel, |encounter| {
+                    encounter.add(name, &expression, &mut roller)
+                })
+                .await?
+        }
+        "remove" if !rest.is_empty() => {
+            initiative
+                .update(channel, |encounter| encounter.remove(rest).map(|_| ()))
+                .await?
+        }
+        "roll" => {
+            initiative
+                .update(channel, |encounter| encounter.roll(&mut roller))
+                .await?
+        }
+        "next" => {
+            initiative
+                .update(channel, |encounter| encounter.advance().map(|_| ()))
+                .await?
+        }
+        "clear" => {
+            initiative.clear(channel).await?;
+            return Ok("the fight is over. for now ⚔️".to_string().into());
+        }
+        subcommand => {
+            return Err(CommandParseError::InvalidArgument {
+                command: "init".to_string(),
+                argument: subcommand.to_string(),
+            }
+            .into());
+        }
+    };
+
+    if encounter.combatants.is_empty() {
+        return Ok("nobody is fighting. try `init add Goblin 1d20+2`"
+            .to_string()
+            .into());
+    }
+
+    Ok(Card::from(&encounter).into())
+}
+
 impl TryFrom<&Event> for Command {
     type Error = CommandParseError;
 
@@ -163,6 +1025,27 @@ impl FromStr for Command {
             "echo" => Ok(Command::Echo(rest.to_string())),
             "roll" => Ok(Command::Roll(rest.to_string())),
             "pasta" => Ok(Command::Copypasta(rest.to_string())),
+            "karma" if !rest.is_empty() => Ok(Command::Karma(rest.to_string())),
+            "leaderboard" => Ok(Command::Leaderboard),
+            "llm" if !rest.is_empty() => Ok(Command::Llm(rest.to_string())),
+            "define" => {
+                let (name, template) = rest
+                    .split_once(char::is_whitespace)
+                    .map(|(name, template)| (name, template.trim()))
+                    .filter(|(_, template)| !template.is_empty())
+                    .ok_or(CommandParseError::MissingArgument {
+                        command: command.to_string(),
+                        argument: "template",
+                    })?;
+
+                Ok(Command::Define {
+                    name: name.to_string(),
+                    template: template.to_string(),
+                })
+            }
+            "undefine" if !rest.is_empty() => Ok(Command::Undefine(rest.to_string())),
+            "sheet" => Ok(Command::Sheet(rest.to_string())),
+            "init" => Ok(Command::Init(rest.to_string())),
             "help" => Ok(Command::Help),
             command => Err(CommandParseError::UndefinedCommand {
                 command: command.to_string(),
@@ -179,6 +1062,18 @@ impl FromStr for Command {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{copypasta::CopypastaError, dice::DiceRollError};
+
+    /// a command from `user` on discord, in #dnd
+    fn command_event(user: &str, content: &str) -> Event {
+        Event::builder()
+            .user(User::from(user))
+            .identity(format!("discord:{user}"))
+            .content(content.to_string())
+            .event_type(EventType::Command)
+            .channel(Channel::Dnd)
+            .build()
+    }
 
     #[test]
     fn command_parse() {
@@ -186,6 +1081,329 @@ mod tests {
         assert_eq!(command, Command::Echo("hello".to_string()));
     }
 
+    #[test]
+    fn command_parse_karma() {
+        let command: Command = "karma rust".parse().unwrap();
+        assert_eq!(command, Command::Karma("rust".to_string()));
+
+        let command: Command = "leaderboard".parse().unwrap();
+        assert_eq!(command, Command::Leaderboard);
+    }
+
+    #[test]
+    fn builtin_commands_parse() {
+        for command in
+            CommandDiscriminants::iter().filter(|command| *command != CommandDiscriminants::Custom)
+        {
+            let name: &'static str = command.into();
+            assert!(is_builtin_command(name));
+            assert!(
+                !matches!(
+                    format!("{name} something").parse::<Command>(),
+                    Err(CommandParseError::UndefinedCommand { .. })
+                ),
+                "`{name}` should be a builtin command"
+            );
+        }
+        assert!(!is_builtin_command("greet"));
+        assert!(!is_builtin_command("custom"));
+    }
+
+    #[test]
+    fn command_parse_define() {
+        let command: Command = "define greet Hello {user}, welcome to {channel}!"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            command,
+            Command::Define {
+                name: "greet".to_string(),
+                template: "Hello {user}, welcome to {channel}!".to_string(),
+            }
+        );
+
+        let command: Result<Command, CommandParseError> = "define greet".parse();
+        assert_eq!(
+            command.expect_err("should fail to parse"),
+            CommandParseError::MissingArgument {
+                command: "define".to_string(),
+                argument: "template",
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_commands_run_templates() {
+        let consumer = CommandConsumer::with_max_dice_roller();
+        let response = consumer
+            .consume(&command_event(
+                "bob",
+                "define greet Hello {user}, welcome to {channel}! {args}",
+            ))
+            .await
+            .expect("define should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("learned `greet` 🧠".to_string())
+        );
+
+        let response = consumer
+            .consume(&command_event("bob", "greet have fun"))
+            .await
+            .expect("custom command should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("Hello bob, welcome to dnd! have fun".to_string())
+        );
+
+        let response = consumer
+            .consume(&command_event("bob", "define roll nope"))
+            .await
+            .expect("define should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("`roll` is already a command. nice try".to_string())
+        );
+
+        let Response::Card(help) = consumer
+            .consume(&command_event("bob", "help"))
+            .await
+            .expect("help should not error")
+        else {
+            panic!("help should be a card");
+        };
+        assert!(help.to_markdown().contains("✨`greet`"));
+
+        consumer
+            .consume(&command_event("bob", "undefine greet"))
+            .await
+            .expect("undefine should not error");
+        let error = consumer
+            .consume(&command_event("bob", "greet"))
+            .await
+            .expect_err("greet should be forgotten");
+        assert!(matches!(
+            error,
+            EventError::CommandParse(CommandParseError::UndefinedCommand { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn editors_can_cook_up_pasta() {
+        let consumer = CommandConsumer::with_max_dice_roller()
+            .with_copypastas(Copypastas::default().with_editors(["discord:alice"]));
+        let error = consumer
+            .consume(&command_event("bob", "pasta add bob bob was here"))
+            .await
+            .expect_err("bob isn't an editor");
+        assert!(matches!(
+            error,
+            EventError::Copypasta(CopypastaError::NotAnEditor(_))
+        ));
+
+        let response = consumer
+            .consume(&command_event("alice", "pasta add hello hello {target}"))
+            .await
+            .expect("alice should add pasta");
+        assert_eq!(
+            response,
+            Response::PlainChat("cooked up `hello` 🍝".to_string())
+        );
+
+        let response = consumer
+            .consume(&command_event("bob", "define greet {pasta:hello}"))
+            .await
+            .expect("define should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("learned `greet` 🧠".to_string())
+        );
+        let response = consumer
+            .consume(&command_event("bob", "greet"))
+            .await
+            .expect("greet should not error");
+        assert_eq!(response, Response::PlainChat("hello {target}".to_string()));
+
+        // somewhere else, so alice's lookup below isn't cooling down
+        let lookup = Event {
+            channel: Channel::Debug,
+            ..command_event("bob", "pasta hel @carol")
+        };
+        let response = consumer
+            .consume(&lookup)
+            .await
+            .expect("lookup should not error");
+        assert_eq!(response, Response::PlainChat("hello @carol".to_string()));
+
+        consumer
+            .consume(&command_event("alice", "pasta remove hello"))
+            .await
+            .expect("alice should remove pasta");
+        assert!(consumer.copypastas.copy_pasta("hello").await.is_none());
+        let response = consumer
+            .consume(&command_event("alice", "pasta hello"))
+            .await
+            .expect("lookup should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("'hello' not found. try again loser".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn pipelines_feed_output_forward() {
+        let consumer =
+            CommandConsumer::with_max_dice_roller().with_language_model(crate::nlp::EchoAgent);
+        let response = consumer
+            .consume(&command_event("bob", "echo hello | echo bob says: | echo"))
+            .await
+            .expect("pipeline should not error");
+        assert_eq!(response, Response::PlainChat("bob says: hello".to_string()));
+
+        let response = consumer
+            .consume(&command_event("bob", "pasta rust | llm summarize"))
+            .await
+            .expect("pipeline should not error");
+        let Response::Bot(message) = response else {
+            panic!("llm stage should respond with a bot message");
+        };
+        assert!(
+            message
+                .render_without_thinking_parts()
+                .starts_with("summarize Rust has zero-cost abstractions")
+        );
+
+        let error = consumer
+            .consume(&command_event("bob", "echo hello | | echo"))
+            .await
+            .expect_err("empty stages should fail");
+        assert!(matches!(
+            error,
+            EventError::CommandParse(CommandParseError::EmptyPipelineStage(_))
+        ));
+    }
+
+    #[test]
+    fn pipelines_do_not_split_definitions() {
+        let stages = pipeline_stages("define shout {args} | loud").expect("should parse");
+        assert_eq!(stages, vec!["define shout {args} | loud"]);
+    }
+
+    #[tokio::test]
+    async fn roll_expands_saved_macros() {
+        let consumer = CommandConsumer::with_max_dice_roller();
+        consumer
+            .consume(&command_event("bob", "roll save attack 1d20+7"))
+            .await
+            .expect("saving a macro should not error");
+
+        let Response::Card(card) = consumer
+            .consume(&command_event("bob", "roll attack+2"))
+            .await
+            .expect("rolling a macro should not error")
+        else {
+            panic!("roll should respond with a card");
+        };
+        assert!(card.to_markdown().contains("**total**: **29**"));
+    }
+
+    #[tokio::test]
+    async fn rolls_use_character_sheet_variables() {
+        let consumer = CommandConsumer::with_max_dice_roller();
+        consumer
+            .consume(&command_event("bob", "sheet set str 3 prof 2"))
+            .await
+            .expect("setting a sheet should not error");
+
+        let Response::Card(card) = consumer
+            .consume(&command_event("bob", "roll 1d20+@str+@prof"))
+            .await
+            .expect("rolling with variables should not error")
+        else {
+            panic!("roll should respond with a card");
+        };
+        assert!(card.to_markdown().contains("**total**: **25**"));
+
+        let error = consumer
+            .consume(&command_event("bob", "roll 1d20+@wis"))
+            .await
+            .expect_err("unknown variables should error");
+        assert!(matches!(
+            error,
+            EventError::DiceRollParse(DiceRollError::UnknownVariable(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rolls_are_recorded_in_history() {
+        let consumer = CommandConsumer::with_max_dice_roller();
+        consumer
+            .consume(&command_event("bob", "roll d20+1"))
+            .await
+            .expect("rolling should not error");
+
+        let Response::Card(card) = consumer
+            .consume(&command_event("bob", "roll history"))
+            .await
+            .expect("history should not error")
+        else {
+            panic!("history should respond with a card");
+        };
+        assert!(card.to_markdown().contains("`d20+1` [20] = **21**"));
+
+        let luck = consumer
+            .consume(&command_event("bob", "roll luck"))
+            .await
+            .expect("luck should not error");
+        assert!(luck.to_text().contains("1 nat 20s"));
+    }
+
+    #[tokio::test]
+    async fn audited_rolls_can_be_verified() {
+        let consumer = CommandConsumer::with_max_dice_roller()
+            .with_roll_audit(crate::dice::audit::RollAudit::default());
+        let Response::Card(card) = consumer
+            .consume(&command_event("bob", "roll 4d6K3"))
+            .await
+            .expect("rolling should not error")
+        else {
+            panic!("roll should respond with a card");
+        };
+        assert_eq!(card.footer.as_deref(), Some("roll #1 · `roll verify 1`"));
+
+        let Response::Card(verified) = consumer
+            .consume(&command_event("bob", "roll verify 1"))
+            .await
+            .expect("verifying should not error")
+        else {
+            panic!("verify should respond with a card");
+        };
+        assert_eq!(verified.title.as_deref(), Some("✅ roll #1 checks out"));
+
+        consumer
+            .consume(&command_event("bob", "roll session end"))
+            .await
+            .expect("ending the session should not error");
+        let verified = consumer
+            .consume(&command_event("bob", "roll verify 1"))
+            .await
+            .expect("verifying should not error");
+        assert!(
+            verified
+                .to_text()
+                .contains("matches the revealed session seed")
+        );
+    }
+
+    #[test]
+    fn pipelines_can_escape_pipes() {
+        let stages = pipeline_stages(r"echo left \| right | llm explain").expect("should parse");
+        assert_eq!(stages, vec!["echo left | right", "llm explain"]);
+
+        let stages = pipeline_stages(r"echo C:\Users").expect("should parse");
+        assert_eq!(stages, vec![r"echo C:\Users"]);
+    }
+
     #[test]
     fn command_parse_missing_command() {
         let command: Result<Command, CommandParseError> = "".parse();
@@ -0,0 +1,101 @@
+//! rate limiting for things that get spammed.
+use std::{
+    fmt,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// tracks when a key was last used
+/// and refuses to let it be used again until `duration` has passed.
+/// clones share the same state.
+#[derive(Clone)]
+pub struct Cooldown<K> {
+    duration: Duration,
+    last_used: Arc<papaya::HashMap<K, Instant>>,
+}
+
+// papaya's map is only `Debug` for hashable keys, and the timestamps aren't worth printing anyway
+impl<K> fmt::Debug for Cooldown<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cooldown")
+            .field("duration", &self.duration)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K> Cooldown<K>
+where
+    K: Hash + Eq + Clone + Send + Sync,
+{
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            last_used: Arc::new(papaya::HashMap::new()),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// mark `key` as used now if it's off cooldown.
+    /// returns the time remaining if it's still cooling down.
+    pub fn try_use(&self, key: K) -> Result<(), Duration> {
+        self.try_use_at(key, Instant::now())
+    }
+
+    fn try_use_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        if let Some(remaining) = self.remaining_at(&key, now) {
+            return Err(remaining);
+        }
+
+        self.mark_used_at(key, now);
+        Ok(())
+    }
+
+    /// the time remaining if `key` is still cooling down, without using it
+    pub fn remaining(&self, key: &K) -> Option<Duration> {
+        self.remaining_at(key, Instant::now())
+    }
+
+    fn remaining_at(&self, key: &K, now: Instant) -> Option<Duration> {
+        let last_used = self.last_used.pin();
+        let elapsed = now.saturating_duration_since(*last_used.get(key)?);
+        (elapsed < self.duration).then(|| self.duration - elapsed)
+    }
+
+    /// start the cooldown for `key` now, whether or not it was cooling down
+    pub fn mark_used(&self, key: K) {
+        self.mark_used_at(key, Instant::now());
+    }
+
+    fn mark_used_at(&self, key: K, now: Instant) {
+        self.last_used.pin().insert(key, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cooldown_blocks_until_elapsed() {
+        let cooldown = Cooldown::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(cooldown.try_use_at("rust", start).is_ok());
+        assert!(cooldown.try_use_at("linux", start).is_ok());
+
+        let remaining = cooldown
+            .try_use_at("rust", start + Duration::from_secs(10))
+            .expect_err("should still be cooling down");
+        assert_eq!(remaining, Duration::from_secs(50));
+
+        assert!(
+            cooldown
+                .try_use_at("rust", start + Duration::from_secs(60))
+                .is_ok()
+        );
+    }
+}
@@ -1,41 +1,324 @@
-use std::{collections::HashMap, sync::OnceLock};
+//! things that bear repeating, e.g. `!ultron pasta linux`.
+//!
+//! pastas come in three layers, and the first one with a name wins:
+//! - the [`Copypastas`] store, changed at runtime with `pasta add`, `pasta edit`
+//!   and `pasta remove` by the users allowed to
+//! - a TOML file on the server, which can be [`Reload`]ed without undoing any `pasta edit`
+//! - the ones in `assets/copypasta.toml` that ship with Ultron
+//!
+//! pastas are templates like custom commands, so they can say `{user}`, `{channel}`
+//! or `{target}`, e.g. `!ultron pasta linux @bob`. see [`crate::custom_command`].
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 
-use crate::io::parse_toml_str;
+use rand::seq::IndexedRandom as _;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use crate::{
+    User, custom_command::is_valid_name, io::parse_toml_str, reload::Reload, store::Store,
+};
 
 const COPYPASTA_CONTENTS: &str = include_str!("../../assets/copypasta.toml");
 
-static COPY_PASTAS: OnceLock<HashMap<String, String>> = OnceLock::new();
+/// subcommands of `pasta` that can't be used as pasta names
+const RESERVED_NAMES: &[&str] = &["list", "random", "search", "add", "edit", "remove"];
 
-fn init_map() -> HashMap<String, String> {
+/// the pastas that ship with Ultron
+pub fn bundled_copy_pastas() -> crate::error::Result<BTreeMap<String, String>> {
     parse_toml_str(COPYPASTA_CONTENTS)
-        .inspect_err(|error| {
-            tracing::error!(
-                %error,
-                "unable to read copypasta file from assets",
-            );
-        })
-        .unwrap_or_default()
 }
 
-/// get a list of all available copy pastas.
-pub fn copy_pasta_names() -> Vec<String> {
-    COPY_PASTAS.get_or_init(init_map).keys().cloned().collect()
+/// pastas by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pastas(BTreeMap<String, String>);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CopypastaError {
+    #[error("{0} isn't allowed to touch the pasta 🍝")]
+    NotAnEditor(User),
+
+    #[error("`{0}` is a terrible name for a pasta. try again")]
+    InvalidName(String),
+
+    #[error("`{0}` is already a pasta. try `pasta edit {0} ...`")]
+    AlreadyExists(String),
+
+    #[error("'{0}' not found. try again loser")]
+    NotFound(String),
+
+    #[error("`{0}` comes from the pasta file on the server, take it out of there")]
+    FromFile(String),
+
+    #[error("`{0}` comes with Ultron, it's here to stay")]
+    Bundled(String),
+
+    #[error("failed to save pasta: {0}")]
+    Storage(#[from] crate::error::Error),
+}
+
+/// persistent copypastas, and who's allowed to change them
+#[derive(Debug, Clone)]
+pub struct Copypastas {
+    store: Store<Pastas>,
+    /// the last pastas [`Reload`]ed from a file, under the stored ones
+    file: Arc<RwLock<BTreeMap<String, String>>>,
+    /// the [`bundled_copy_pastas`], under everything else
+    bundled: Arc<BTreeMap<String, String>>,
+    editors: Arc<BTreeSet<String>>,
+}
+
+impl Default for Copypastas {
+    fn default() -> Self {
+        Self::from(Store::default())
+    }
 }
 
-/// get a copy pasta by its name.
-pub fn copy_pasta(name: &str) -> Option<String> {
-    COPY_PASTAS.get_or_init(init_map).get(name).cloned()
+impl From<Store<Pastas>> for Copypastas {
+    fn from(store: Store<Pastas>) -> Self {
+        let bundled = bundled_copy_pastas().unwrap_or_else(|error| {
+            tracing::error!(%error, "unable to read copypasta file from assets");
+            BTreeMap::new()
+        });
+
+        Self {
+            store,
+            file: Arc::default(),
+            bundled: Arc::new(bundled),
+            editors: Arc::default(),
+        }
+    }
+}
+
+impl Copypastas {
+    /// let these users add, edit and remove pastas, by their platform-qualified ID
+    /// like `discord:1234`. nobody can by default.
+    pub fn with_editors(self, editors: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            editors: Arc::new(editors.into_iter().map(Into::into).collect()),
+            ..self
+        }
+    }
+
+    /// names can be spoofed on some platforms, so only the [`Event::identity`] counts.
+    ///
+    /// [`Event::identity`]: crate::event_processor::Event::identity
+    pub fn can_edit(&self, identity: Option<&str>) -> bool {
+        identity.is_some_and(|identity| self.editors.contains(identity))
+    }
+
+    /// get a list of all available copy pastas.
+    pub async fn copy_pasta_names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self
+            .store
+            .read(|pastas| pastas.0.keys().cloned().collect())
+            .await;
+        names.extend(self.file.read().await.keys().cloned());
+        names.extend(self.bundled.keys().cloned());
+        names.into_iter().collect()
+    }
+
+    /// get a copy pasta by its name.
+    pub async fn copy_pasta(&self, name: &str) -> Option<String> {
+        match self.store.read(|pastas| pastas.0.get(name).cloned()).await {
+            Some(pasta) => Some(pasta),
+            None => self.unstored(name).await,
+        }
+    }
+
+    /// find a pasta by its name, or the start of its name, or its name with a typo or two.
+    /// returns the actual name along with the pasta
+    pub async fn find(&self, name: &str) -> Option<(String, String)> {
+        find_pasta(&self.all().await, name)
+    }
+
+    /// a random pasta and its name
+    pub async fn random(&self) -> Option<(String, String)> {
+        let pastas: Vec<_> = self.all().await.into_iter().collect();
+        pastas.choose(&mut rand::rng()).cloned()
+    }
+
+    /// names of the pastas that mention `word`, ignoring case
+    pub async fn search(&self, word: &str) -> Vec<String> {
+        let word = word.to_lowercase();
+        self.all()
+            .await
+            .into_iter()
+            .filter(|(name, pasta)| {
+                name.to_lowercase().contains(&word) || pasta.to_lowercase().contains(&word)
+            })
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    /// every pasta, with the stored ones on top of the file on top of the bundled ones
+    async fn all(&self) -> BTreeMap<String, String> {
+        let mut pastas = (*self.bundled).clone();
+        pastas.extend(self.file.read().await.clone());
+        pastas.extend(self.store.read(|stored| stored.0.clone()).await);
+        pastas
+    }
+
+    /// what `name` would be without the store
+    async fn unstored(&self, name: &str) -> Option<String> {
+        match self.file.read().await.get(name) {
+            Some(pasta) => Some(pasta.clone()),
+            None => self.bundled.get(name).cloned(),
+        }
+    }
+
+    /// add a new pasta
+    pub async fn add(
+        &self,
+        user: &User,
+        identity: Option<&str>,
+        name: &str,
+        text: &str,
+    ) -> Result<(), CopypastaError> {
+        self.check_editor(user, identity)?;
+        if !is_valid_name(name) || RESERVED_NAMES.contains(&name) {
+            return Err(CopypastaError::InvalidName(name.to_string()));
+        }
+        if self.unstored(name).await.is_some() {
+            return Err(CopypastaError::AlreadyExists(name.to_string()));
+        }
+
+        self.store
+            .update(|pastas| match pastas.0.contains_key(name) {
+                true => Err(CopypastaError::AlreadyExists(name.to_string())),
+                false => {
+                    pastas.0.insert(name.to_string(), text.to_string());
+                    Ok(())
+                }
+            })
+            .await?
+    }
+
+    /// change an existing pasta, returning what it used to say.
+    /// editing a pasta from the file or a bundled one stores the edit on top of it
+    pub async fn edit(
+        &self,
+        user: &User,
+        identity: Option<&str>,
+        name: &str,
+        text: &str,
+    ) -> Result<String, CopypastaError> {
+        self.check_editor(user, identity)?;
+        let unstored = self.unstored(name).await;
+
+        self.store
+            .update(|pastas| match (pastas.0.get_mut(name), unstored) {
+                (Some(pasta), _) => Ok(std::mem::replace(pasta, text.to_string())),
+                (None, Some(previous)) => {
+                    pastas.0.insert(name.to_string(), text.to_string());
+                    Ok(previous)
+                }
+                (None, None) => Err(CopypastaError::NotFound(name.to_string())),
+            })
+            .await?
+    }
+
+    /// remove a pasta, returning what it said.
+    /// removing an edited pasta from the file or a bundled one brings back the original
+    pub async fn remove(
+        &self,
+        user: &User,
+        identity: Option<&str>,
+        name: &str,
+    ) -> Result<String, CopypastaError> {
+        self.check_editor(user, identity)?;
+
+        match self.store.update(|pastas| pastas.0.remove(name)).await? {
+            Some(pasta) => Ok(pasta),
+            None if self.file.read().await.contains_key(name) => {
+                Err(CopypastaError::FromFile(name.to_string()))
+            }
+            None if self.bundled.contains_key(name) => {
+                Err(CopypastaError::Bundled(name.to_string()))
+            }
+            None => Err(CopypastaError::NotFound(name.to_string())),
+        }
+    }
+
+    fn check_editor(&self, user: &User, identity: Option<&str>) -> Result<(), CopypastaError> {
+        match self.can_edit(identity) {
+            true => Ok(()),
+            false => Err(CopypastaError::NotAnEditor(user.clone())),
+        }
+    }
+}
+
+fn find_pasta(pastas: &BTreeMap<String, String>, name: &str) -> Option<(String, String)> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let found = pastas.get_key_value(name).or_else(|| {
+        pastas
+            .iter()
+            .find(|(candidate, _)| candidate.starts_with(name))
+    });
+
+    let found = found.or_else(|| {
+        // allow about one typo per two letters, up to 3
+        let max_distance = (name.chars().count() / 2).min(3);
+        pastas
+            .iter()
+            .map(|entry| (edit_distance(entry.0, name), entry))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, entry)| entry)
+    });
+
+    found.map(|(name, pasta)| (name.clone(), pasta.clone()))
+}
+
+/// how many single character insertions, deletions or substitutions it takes
+/// to turn `a` into `b`, a.k.a. the Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// replaces the pastas from the last version of the file.
+/// the store isn't touched, so stored pastas with the same name still win,
+/// but the file wins over the bundled pastas
+#[async_trait::async_trait]
+impl Reload for Copypastas {
+    async fn reload(&self, contents: &str) -> crate::error::Result<()> {
+        let pastas: BTreeMap<String, String> = parse_toml_str(contents)?;
+        *self.file.write().await = pastas;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
-
     use super::*;
 
-    #[test]
-    fn test_copy_pasta_names() {
-        let names: BTreeSet<String> = copy_pasta_names().into_iter().collect();
+    const ALICE: &str = "discord:1";
+
+    #[tokio::test]
+    async fn test_copy_pasta_names() {
+        let names: BTreeSet<String> = Copypastas::default()
+            .copy_pasta_names()
+            .await
+            .into_iter()
+            .collect();
 
         insta::assert_json_snapshot!(names, @r#"
         [
@@ -49,16 +332,216 @@ mod tests {
         "#);
     }
 
-    #[test]
-    fn test_copy_pasta() {
-        let pasta = copy_pasta("rust").expect("should find rust pasta");
+    #[tokio::test]
+    async fn test_copy_pasta() {
+        let pasta = Copypastas::default()
+            .copy_pasta("rust")
+            .await
+            .expect("should find rust pasta");
 
         insta::assert_snapshot!(pasta, @"Rust has zero-cost abstractions, move semantics, guaranteed memory safety, threads without data races, trait-based generics, pattern matching, type inference, minimal runtime and efficient C bindings.");
     }
 
-    #[test]
-    fn test_copy_pasta_nonexistent() {
-        let pasta = copy_pasta("nonexistent");
+    #[tokio::test]
+    async fn test_copy_pasta_nonexistent() {
+        let pasta = Copypastas::default().copy_pasta("nonexistent").await;
         assert!(pasta.is_none());
     }
+
+    #[tokio::test]
+    async fn pastas_are_found_by_prefix_and_typos() {
+        let pastas = Copypastas::default();
+        let name = |found: Option<(String, String)>| found.map(|(name, _)| name);
+
+        assert_eq!(name(pastas.find("linux").await).as_deref(), Some("linux"));
+        assert_eq!(
+            name(pastas.find("rick").await).as_deref(),
+            Some("rick_and_morty")
+        );
+        assert_eq!(name(pastas.find("rsut").await).as_deref(), Some("rust"));
+        assert_eq!(
+            name(pastas.find("googlerz").await).as_deref(),
+            Some("googlers")
+        );
+        assert!(pastas.find("nonexistent").await.is_none());
+        assert!(pastas.find("").await.is_none());
+
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[tokio::test]
+    async fn pastas_can_be_searched_and_picked_at_random() {
+        let pastas = Copypastas::default();
+
+        assert_eq!(pastas.search("MEMORY SAFETY").await, vec!["rust"]);
+        assert!(
+            pastas
+                .search("definitely not in any pasta")
+                .await
+                .is_empty()
+        );
+
+        let (name, pasta) = pastas.random().await.expect("should pick a pasta");
+        assert_eq!(pastas.copy_pasta(&name).await, Some(pasta));
+    }
+
+    #[tokio::test]
+    async fn only_editors_can_change_pastas() {
+        let pastas = Copypastas::default().with_editors([ALICE]);
+        let alice = User::from("alice");
+        let bob = User::from("bob");
+
+        assert!(matches!(
+            pastas
+                .add(&bob, Some("discord:2"), "bob", "bob was here")
+                .await,
+            Err(CopypastaError::NotAnEditor(_))
+        ));
+        // anyone can call themselves alice on IRC
+        assert!(matches!(
+            pastas.add(&alice, None, "bob", "bob was here").await,
+            Err(CopypastaError::NotAnEditor(_))
+        ));
+        assert!(matches!(
+            pastas.add(&bob, Some("1"), "bob", "bob was here").await,
+            Err(CopypastaError::NotAnEditor(_))
+        ));
+
+        pastas
+            .add(
+                &alice,
+                Some(ALICE),
+                "navy_seal",
+                "what did you just say about me",
+            )
+            .await
+            .expect("alice should add pasta");
+        assert!(matches!(
+            pastas.add(&alice, Some(ALICE), "navy_seal", "again").await,
+            Err(CopypastaError::AlreadyExists(_))
+        ));
+
+        let previous = pastas
+            .edit(
+                &alice,
+                Some(ALICE),
+                "navy_seal",
+                "what did you just type about me",
+            )
+            .await
+            .expect("alice should edit pasta");
+        assert_eq!(previous, "what did you just say about me");
+        assert_eq!(
+            pastas.copy_pasta("navy_seal").await.as_deref(),
+            Some("what did you just type about me")
+        );
+
+        assert!(matches!(
+            pastas.remove(&bob, Some("discord:2"), "navy_seal").await,
+            Err(CopypastaError::NotAnEditor(_))
+        ));
+        pastas
+            .remove(&alice, Some(ALICE), "navy_seal")
+            .await
+            .expect("alice should remove pasta");
+        assert!(pastas.copy_pasta("navy_seal").await.is_none());
+        assert!(matches!(
+            pastas.remove(&alice, Some(ALICE), "linux").await,
+            Err(CopypastaError::Bundled(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn files_override_bundled_pastas() {
+        let pastas = Copypastas::default().with_editors([ALICE]);
+        let alice = User::from("alice");
+        let bundled = pastas.copy_pasta("linux").await.expect("linux is bundled");
+
+        pastas
+            .reload("linux = 'linux from disk'")
+            .await
+            .expect("should reload pastas");
+        assert_eq!(
+            pastas.copy_pasta("linux").await.as_deref(),
+            Some("linux from disk")
+        );
+        assert_eq!(
+            pastas.find("linux").await,
+            Some(("linux".to_string(), "linux from disk".to_string()))
+        );
+
+        let previous = pastas
+            .edit(&alice, Some(ALICE), "linux", "linux from alice")
+            .await
+            .expect("alice should edit pasta");
+        assert_eq!(previous, "linux from disk");
+        pastas
+            .remove(&alice, Some(ALICE), "linux")
+            .await
+            .expect("alice should remove her edit");
+
+        pastas.reload("").await.expect("should reload pastas");
+        assert_eq!(pastas.copy_pasta("linux").await, Some(bundled));
+    }
+
+    #[tokio::test]
+    async fn reloads_dont_undo_edits() {
+        let pastas = Copypastas::default().with_editors([ALICE]);
+        let alice = User::from("alice");
+
+        pastas
+            .edit(&alice, Some(ALICE), "rust", "rust from the store")
+            .await
+            .expect("alice should edit pasta");
+        pastas
+            .reload("rust = 'rust from disk'\nfresh = 'fresh from disk'")
+            .await
+            .expect("should reload pastas");
+        assert_eq!(
+            pastas.copy_pasta("rust").await.as_deref(),
+            Some("rust from the store"),
+            "stored pastas win"
+        );
+        assert_eq!(
+            pastas.copy_pasta("fresh").await.as_deref(),
+            Some("fresh from disk")
+        );
+        assert!(
+            pastas
+                .copy_pasta_names()
+                .await
+                .contains(&"fresh".to_string())
+        );
+
+        pastas
+            .edit(&alice, Some(ALICE), "fresh", "fresh from alice")
+            .await
+            .expect("alice should edit pasta from the file");
+        pastas
+            .reload(r#"fresh = "fresh from disk again""#)
+            .await
+            .expect("should reload pastas");
+        assert_eq!(
+            pastas.copy_pasta("fresh").await.as_deref(),
+            Some("fresh from alice")
+        );
+
+        pastas
+            .remove(&alice, Some(ALICE), "fresh")
+            .await
+            .expect("alice should remove her edit");
+        assert_eq!(
+            pastas.copy_pasta("fresh").await.as_deref(),
+            Some("fresh from disk again")
+        );
+        assert!(matches!(
+            pastas.remove(&alice, Some(ALICE), "fresh").await,
+            Err(CopypastaError::FromFile(_))
+        ));
+        assert!(matches!(
+            pastas.add(&alice, Some(ALICE), "fresh", "again").await,
+            Err(CopypastaError::AlreadyExists(_))
+        ));
+    }
 }
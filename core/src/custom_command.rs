@@ -0,0 +1,260 @@
+//! user-defined commands, created at runtime with `!ultron define <name> <template>`.
+//!
+//! templates can contain placeholders that are filled in when the command runs:
+//! - `{user}` the user who ran the command
+//! - `{channel}` the channel the command was run in
+//! - `{args}` anything after the command name
+//! - `{target}` whoever `{args}` names, e.g. `@bob`, or else the user
+//! - `{roll:<expr>}` a dice roll, e.g. `{roll:1d20}`
+//! - `{pasta:<name>}` a copypasta, e.g. `{pasta:linux}`
+//!
+//! unknown placeholders are left as is. copypastas only get the ones without a `:`,
+//! see [`render_simple`], so a stored pasta can't roll dice or pull in other pastas.
+use std::collections::BTreeMap;
+
+use crate::{
+    Channel, User,
+    copypasta::Copypastas,
+    dice::{DiceRollResult, DiceRoller, RollerImpl},
+    error::Result,
+    event_processor::EventError,
+    store::Store,
+};
+
+/// persistent user-defined commands, mapping names to templates
+#[derive(Debug, Clone, Default)]
+pub struct CustomCommands(Store<BTreeMap<String, String>>);
+
+impl From<Store<BTreeMap<String, String>>> for CustomCommands {
+    fn from(store: Store<BTreeMap<String, String>>) -> Self {
+        Self(store)
+    }
+}
+
+impl CustomCommands {
+    pub async fn get(&self, name: &str) -> Option<String> {
+        self.0.read(|commands| commands.get(name).cloned()).await
+    }
+
+    pub async fn names(&self) -> Vec<String> {
+        self.0
+            .read(|commands| commands.keys().cloned().collect())
+            .await
+    }
+
+    /// define or redefine a command, returning the previous template
+    pub async fn define(&self, name: &str, template: &str) -> Result<Option<String>> {
+        self.0
+            .update(|commands| commands.insert(name.to_string(), template.to_string()))
+            .await
+    }
+
+    /// remove a command, returning its template
+    pub async fn remove(&self, name: &str) -> Result<Option<String>> {
+        self.0.update(|commands| commands.remove(name)).await
+    }
+}
+
+/// whether `name` can be used as a custom command name
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// values available to placeholders in a template
+#[derive(Debug, Clone)]
+pub struct TemplateContext<'a> {
+    pub user: &'a User,
+    pub channel: Channel,
+    pub args: &'a str,
+    /// where `{pasta:<name>}` comes from
+    pub copypastas: &'a Copypastas,
+}
+
+/// fill in the placeholders in `template`
+pub async fn render_template<TRoller>(
+    template: &str,
+    context: &TemplateContext<'_>,
+    dice_roller: &DiceRoller<TRoller>,
+) -> std::result::Result<String, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some((start, end)) = find_placeholder(rest) {
+        output.push_str(&rest[..start]);
+
+        let placeholder = &rest[start + 1..end];
+        match render_placeholder(placeholder, context, dice_roller).await? {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[start..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// fill in `{user}`, `{channel}`, `{args}` and `{target}` in `template`, e.g. a pasta.
+/// everything else, like `{roll:1d20}`, is left as is
+pub fn render_simple(template: &str, context: &TemplateContext<'_>) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some((start, end)) = find_placeholder(rest) {
+        output.push_str(&rest[..start]);
+        match render_simple_placeholder(&rest[start + 1..end], context) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// where the next `{` and its `}` are
+fn find_placeholder(text: &str) -> Option<(usize, usize)> {
+    let start = text.find('{')?;
+    let end = text[start..].find('}')?;
+    Some((start, start + end))
+}
+
+async fn render_placeholder<TRoller>(
+    placeholder: &str,
+    context: &TemplateContext<'_>,
+    dice_roller: &DiceRoller<TRoller>,
+) -> std::result::Result<Option<String>, EventError>
+where
+    TRoller: RollerImpl,
+{
+    let value = match placeholder.split_once(':') {
+        Some(("roll", expression)) => {
+            let roll = DiceRollResult::from_str(expression.trim(), dice_roller.clone())?;
+            Some(roll.to_string())
+        }
+        Some(("pasta", name)) => context.copypastas.copy_pasta(name.trim()).await,
+        Some(_) => None,
+        None => render_simple_placeholder(placeholder, context),
+    };
+
+    Ok(value)
+}
+
+fn render_simple_placeholder(placeholder: &str, context: &TemplateContext<'_>) -> Option<String> {
+    match placeholder {
+        "user" => Some(context.user.to_string()),
+        "channel" => Some(context.channel.to_string()),
+        "args" => Some(context.args.to_string()),
+        "target" => match context.args.trim() {
+            "" => Some(context.user.to_string()),
+            target => Some(target.to_string()),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn render_template_fills_placeholders() {
+        let user = User::from("bob");
+        let copypastas = Copypastas::default();
+        let context = TemplateContext {
+            user: &user,
+            channel: Channel::Dnd,
+            args: "the goblin",
+            copypastas: &copypastas,
+        };
+
+        let rendered = render_template(
+            "Hello {user}, welcome to {channel}! you hit {args} for {roll:1d8} {unknown}",
+            &context,
+            &DiceRoller::max(),
+        )
+        .await
+        .expect("should render template");
+
+        assert!(rendered.starts_with("Hello bob, welcome to dnd! you hit the goblin for "));
+        assert!(rendered.ends_with(" = **8** {unknown}"));
+    }
+
+    #[tokio::test]
+    async fn render_template_handles_unclosed_braces() {
+        let user = User::Anonymous;
+        let copypastas = Copypastas::default();
+        let context = TemplateContext {
+            user: &user,
+            channel: Channel::Debug,
+            args: "",
+            copypastas: &copypastas,
+        };
+
+        let rendered = render_template("{user} says {oops", &context, &DiceRoller::max())
+            .await
+            .expect("should render template");
+        assert_eq!(rendered, "anonymous says {oops");
+    }
+
+    #[tokio::test]
+    async fn target_falls_back_to_the_user() {
+        let user = User::from("bob");
+        let copypastas = Copypastas::default();
+        let mut context = TemplateContext {
+            user: &user,
+            channel: Channel::Dnd,
+            args: "",
+            copypastas: &copypastas,
+        };
+
+        let rendered = render_template("{target} is it", &context, &DiceRoller::max())
+            .await
+            .expect("should render template");
+        assert_eq!(rendered, "bob is it");
+
+        context.args = " @carol ";
+        let rendered = render_template("{target} is it", &context, &DiceRoller::max())
+            .await
+            .expect("should render template");
+        assert_eq!(rendered, "@carol is it");
+    }
+
+    #[test]
+    fn render_simple_leaves_rolls_and_pastas_alone() {
+        let user = User::from("bob");
+        let copypastas = Copypastas::default();
+        let context = TemplateContext {
+            user: &user,
+            channel: Channel::Dnd,
+            args: "@carol",
+            copypastas: &copypastas,
+        };
+
+        assert_eq!(
+            render_simple(
+                "{user} rolls {roll:1d20} at {target}, {pasta:linux} {oops",
+                &context
+            ),
+            "bob rolls {roll:1d20} at @carol, {pasta:linux} {oops"
+        );
+    }
+
+    #[test]
+    fn custom_command_names_are_validated() {
+        assert!(is_valid_name("greet"));
+        assert!(is_valid_name("greet-2_u"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("greet me"));
+        assert!(!is_valid_name("{user}"));
+    }
+}
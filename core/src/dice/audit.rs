@@ -0,0 +1,441 @@
+//! a verifiable audit trail for rolls, so nobody can accuse Ultron of cheating.
+//!
+//! every audit session has a secret seed. the SHA-256 of the seed (the commitment)
+//! is published when the session starts, and the seed itself is revealed when it ends.
+//! each roll gets an ID and a seed derived from the session seed and the ID,
+//! and is rolled with the configured roller seeded with it (see [`RollerConfig::seeded`]),
+//! so anyone can recompute it.
+//!
+//! the secret seed is kept in the data directory until it's revealed,
+//! so whoever can read that can predict rolls. don't let the DM in there.
+use rand::{TryRngCore as _, rngs::OsRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use time::OffsetDateTime;
+
+use crate::{
+    User,
+    card::{Card, CardField, Color},
+    dice::{
+        DiceRoll, DiceRollError, DiceRoller,
+        limits::DiceLimits,
+        rollers::{ConfiguredRoller, RollerConfig},
+    },
+    store::Store,
+};
+
+/// only keep this many audited rolls around
+const MAX_AUDITED_ROLLS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditSession {
+    pub id: u32,
+    /// hex SHA-256 of the seed, published up front
+    pub commitment: String,
+    seed: u64,
+    pub started_at: OffsetDateTime,
+    /// set once the session is over and the seed is revealed
+    pub ended_at: Option<OffsetDateTime>,
+}
+
+impl AuditSession {
+    fn start(id: u32) -> Result<Self, AuditError> {
+        let seed = OsRng.try_next_u64()?;
+        Ok(Self {
+            id,
+            commitment: commitment(seed),
+            seed,
+            started_at: OffsetDateTime::now_utc(),
+            ended_at: None,
+        })
+    }
+
+    /// the seed, but only once the session is over
+    pub fn revealed_seed(&self) -> Option<u64> {
+        self.ended_at.map(|_| self.seed)
+    }
+}
+
+/// a roll that can be checked later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditedRoll {
+    pub id: u64,
+    pub session: u32,
+    pub user: String,
+    /// exactly what was rolled, after macros and sheet variables
+    pub expression: String,
+    /// derived from the session seed and the roll ID, see [`roll_seed`]
+    pub seed: u64,
+    /// the kind of roller the seed went into
+    #[serde(default)]
+    pub roller: RollerConfig,
+    /// the RNG the roller used, see [`RollerConfig::seeded_algorithm`].
+    /// missing from rolls recorded before it was
+    #[serde(default)]
+    pub algorithm: Option<String>,
+    pub total: i32,
+    pub timestamp: OffsetDateTime,
+}
+
+/// the ID and seed a roll was made with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollTicket {
+    pub id: u64,
+    pub session: u32,
+    pub seed: u64,
+    pub roller: RollerConfig,
+}
+
+impl RollTicket {
+    /// the roller this roll has to use to be verifiable
+    pub fn roller(&self, limits: &DiceLimits) -> DiceRoller<ConfiguredRoller> {
+        DiceRoller::from(self.roller.seeded(self.seed)).with_limits(*limits)
+    }
+}
+
+/// the result of `roll verify`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verification {
+    pub roll: AuditedRoll,
+    pub session: AuditSession,
+    /// the total from rolling again with the roll's seed
+    pub recomputed_total: i32,
+    /// whether the revealed session seed matches the commitment
+    /// and produces the roll's seed. `None` until the session is over.
+    pub seed_checks_out: Option<bool>,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.recomputed_total == self.roll.total && self.seed_checks_out != Some(false)
+    }
+}
+
+impl From<&AuditSession> for Card {
+    fn from(session: &AuditSession) -> Self {
+        let (title, seed, footer) = match session.revealed_seed() {
+            Some(seed) => (
+                format!("🔓 audit session #{} is over", session.id),
+                Some(CardField::new("seed", format!("`{}`", seed))),
+                "check it with `echo -n <seed> | sha256sum`",
+            ),
+            None => (
+                format!("🔒 audit session #{}", session.id),
+                None,
+                "the seed is revealed with `roll session end`",
+            ),
+        };
+
+        Card::builder()
+            .title(title)
+            .fields(
+                std::iter::once(CardField::new(
+                    "commitment",
+                    format!("`{}`", session.commitment),
+                ))
+                .chain(seed)
+                .collect(),
+            )
+            .footer(footer)
+            .color(Color::INFO)
+            .build()
+    }
+}
+
+impl From<&Verification> for Card {
+    fn from(verification: &Verification) -> Self {
+        let roll = &verification.roll;
+        let (title, color) = match verification.is_valid() {
+            true => (format!("✅ roll #{} checks out", roll.id), Color::SUCCESS),
+            false => (
+                format!("❌ roll #{} doesn't add up", roll.id),
+                Color::ULTRON,
+            ),
+        };
+        let seed = match verification.seed_checks_out {
+            Some(true) => "matches the revealed session seed",
+            Some(false) => "doesn't match the revealed session seed",
+            None => "session still running, seed not revealed yet",
+        };
+
+        Card::builder()
+            .title(title)
+            .description(format!("{} rolled `{}`", roll.user, roll.expression))
+            .fields(vec![
+                CardField::inline("recorded", format!("**{}**", roll.total)),
+                CardField::inline("rerolled", format!("**{}**", verification.recomputed_total)),
+                CardField::inline("roll seed", format!("`{}`", roll.seed)),
+                CardField::inline("rng", roll.algorithm.as_deref().unwrap_or("unknown")),
+                CardField::new(format!("session #{}", verification.session.id), seed),
+            ])
+            .color(color)
+            .build()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("there's no roll #{0}")]
+    UnknownRoll(u64),
+
+    #[error("roll #{0} belongs to a session that doesn't exist")]
+    UnknownSession(u64),
+
+    #[error("couldn't reroll: {0}")]
+    Dice(#[from] DiceRollError),
+
+    #[error("failed to save the audit trail: {0}")]
+    Storage(#[from] crate::error::Error),
+
+    #[error("couldn't get a random seed from the OS: {0}")]
+    Entropy(#[from] rand::rand_core::OsError),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditState {
+    sessions: Vec<AuditSession>,
+    rolls: Vec<AuditedRoll>,
+    next_roll_id: u64,
+}
+
+impl AuditState {
+    /// the running session, starting one if needed
+    fn current_session(&mut self) -> Result<&mut AuditSession, AuditError> {
+        let needs_new = self
+            .sessions
+            .last()
+            .is_none_or(|session| session.ended_at.is_some());
+        if needs_new {
+            let id = self.sessions.last().map_or(1, |session| session.id + 1);
+            self.sessions.push(AuditSession::start(id)?);
+        }
+
+        let last = self.sessions.len() - 1;
+        Ok(&mut self.sessions[last])
+    }
+}
+
+/// persistent audit trail of every roll
+#[derive(Debug, Clone, Default)]
+pub struct RollAudit {
+    store: Store<AuditState>,
+    /// the configured roller, seeded per roll
+    roller: RollerConfig,
+}
+
+impl From<Store<AuditState>> for RollAudit {
+    fn from(store: Store<AuditState>) -> Self {
+        Self {
+            store,
+            roller: RollerConfig::default(),
+        }
+    }
+}
+
+impl RollAudit {
+    /// roll with a seeded version of `roller`, e.g. the one from `--dice-roller`
+    pub fn with_roller(self, roller: RollerConfig) -> Self {
+        Self { roller, ..self }
+    }
+
+    /// the running session, starting one if needed, so its commitment can be published
+    pub async fn session(&self) -> Result<AuditSession, AuditError> {
+        let session = self
+            .store
+            .update(|state| state.current_session().map(|session| session.clone()))
+            .await??;
+        Ok(session)
+    }
+
+    /// end the running session and reveal its seed
+    pub async fn end_session(&self) -> crate::error::Result<Option<AuditSession>> {
+        self.store
+            .update(|state| {
+                let session = state
+                    .sessions
+                    .last_mut()
+                    .filter(|session| session.ended_at.is_none())?;
+                session.ended_at = Some(OffsetDateTime::now_utc());
+                Some(session.clone())
+            })
+            .await
+    }
+
+    /// roll `expression` with the next roll ID and its seed, and record it.
+    /// the ID is only used up if the roll works
+    pub async fn roll(
+        &self,
+        user: &User,
+        expression: &str,
+        limits: &DiceLimits,
+    ) -> Result<(RollTicket, DiceRoll), AuditError> {
+        self.store
+            .update(|state| {
+                let id = state.next_roll_id + 1;
+                let session = state.current_session()?;
+                let ticket = RollTicket {
+                    id,
+                    session: session.id,
+                    seed: roll_seed(session.seed, id),
+                    roller: self.roller.clone(),
+                };
+                let roll = ticket.roller(limits).roll_dice(expression)?;
+
+                state.next_roll_id = id;
+                state.rolls.push(AuditedRoll {
+                    id,
+                    session: ticket.session,
+                    user: user.to_string(),
+                    expression: expression.to_string(),
+                    seed: ticket.seed,
+                    roller: ticket.roller.clone(),
+                    algorithm: ticket.roller.seeded_algorithm().map(str::to_string),
+                    total: roll.total(),
+                    timestamp: OffsetDateTime::now_utc(),
+                });
+                let overflow = state.rolls.len().saturating_sub(MAX_AUDITED_ROLLS);
+                state.rolls.drain(..overflow);
+
+                Ok((ticket, roll))
+            })
+            .await?
+    }
+
+    /// roll again with the recorded seed and check the session seed if it's been revealed
+    pub async fn verify(&self, id: u64, limits: &DiceLimits) -> Result<Verification, AuditError> {
+        let (roll, session) = self
+            .store
+            .read(|state| {
+                let roll = state.rolls.iter().find(|roll| roll.id == id).cloned();
+                let session = roll.as_ref().and_then(|roll| {
+                    state
+                        .sessions
+                        .iter()
+                        .find(|session| session.id == roll.session)
+                        .cloned()
+                });
+                (roll, session)
+            })
+            .await;
+
+        let roll = roll.ok_or(AuditError::UnknownRoll(id))?;
+        let session = session.ok_or(AuditError::UnknownSession(id))?;
+
+        let ticket = RollTicket {
+            id: roll.id,
+            session: roll.session,
+            seed: roll.seed,
+            roller: roll.roller.clone(),
+        };
+        let recomputed_total = ticket.roller(limits).roll_dice(&roll.expression)?.total();
+
+        let seed_checks_out = session.revealed_seed().map(|seed| {
+            commitment(seed) == session.commitment && roll_seed(seed, roll.id) == roll.seed
+        });
+
+        Ok(Verification {
+            roll,
+            session,
+            recomputed_total,
+            seed_checks_out,
+        })
+    }
+}
+
+/// hex SHA-256 of the seed's decimal representation,
+/// so it's easy to check with `echo -n <seed> | sha256sum`
+pub fn commitment(seed: u64) -> String {
+    Sha256::digest(seed.to_string())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// the first 8 bytes (little endian) of SHA-256 of `<session seed>:<roll id>`
+pub fn roll_seed(session_seed: u64, roll_id: u64) -> u64 {
+    let digest = Sha256::digest(format!("{}:{}", session_seed, roll_id));
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn audited_rolls_can_be_verified() {
+        let audit = RollAudit::default();
+        let limits = DiceLimits::default();
+        let bob = User::from("bob");
+
+        let session = audit.session().await.expect("should start a session");
+        assert!(audit.roll(&bob, "not dice", &limits).await.is_err());
+        let (ticket, _) = audit
+            .roll(&bob, "4d6K3+2", &limits)
+            .await
+            .expect("should roll");
+        assert_eq!(ticket.id, 1, "failed rolls don't use up an ID");
+        assert_eq!(ticket.session, session.id);
+
+        let verification = audit
+            .verify(ticket.id, &limits)
+            .await
+            .expect("should verify roll");
+        assert!(verification.is_valid());
+        assert_eq!(verification.seed_checks_out, None);
+
+        let ended = audit
+            .end_session()
+            .await
+            .expect("should end session")
+            .expect("a session was running");
+        let seed = ended.revealed_seed().expect("seed should be revealed");
+        assert_eq!(commitment(seed), session.commitment);
+
+        let verification = audit
+            .verify(ticket.id, &limits)
+            .await
+            .expect("should verify roll");
+        assert_eq!(verification.seed_checks_out, Some(true));
+
+        // the next roll starts a fresh session
+        let (next, _) = audit.roll(&bob, "d20", &limits).await.expect("should roll");
+        assert_eq!(next.session, session.id + 1);
+
+        assert!(matches!(
+            audit.verify(999, &limits).await,
+            Err(AuditError::UnknownRoll(999))
+        ));
+    }
+
+    #[tokio::test]
+    async fn audited_rolls_use_the_configured_roller() {
+        let audit = RollAudit::default().with_roller(RollerConfig::Secure);
+        let limits = DiceLimits::default();
+
+        let (ticket, roll) = audit
+            .roll(&User::from("bob"), "8d6", &limits)
+            .await
+            .expect("should roll");
+        assert_eq!(ticket.roller, RollerConfig::Secure);
+
+        let verification = audit
+            .verify(ticket.id, &limits)
+            .await
+            .expect("should verify roll");
+        assert!(verification.is_valid());
+        assert_eq!(verification.roll.total, roll.total());
+        assert_eq!(verification.roll.roller, RollerConfig::Secure);
+        assert_eq!(verification.roll.algorithm.as_deref(), Some("chacha20"));
+    }
+
+    #[test]
+    fn commitments_are_sha256_of_the_seed() {
+        // echo -n 42 | sha256sum
+        assert_eq!(
+            commitment(42),
+            "73475cb40a568e8da8a045ced110137e159f890ac4da883b6b17dc651b3a8049"
+        );
+    }
+}
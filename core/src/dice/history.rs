@@ -0,0 +1,337 @@
+//! a log of every roll, so we can find out who's been blessed by the dice gods.
+//! a "session" is a run of rolls in a channel without a long break,
+//! see [`SESSION_GAP`].
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tokio::sync::RwLock;
+
+use crate::{
+    Channel, User,
+    dice::{DiceRoll, RolledDie},
+    error::Result,
+    io::{append_json_line, file_exists, read_json_lines, write_json_lines},
+};
+
+/// only keep this many rolls around
+const MAX_RECORDS: usize = 5_000;
+
+/// rewrite the history file without the old rolls once it has this many lines
+const MAX_FILE_LINES: usize = 2 * MAX_RECORDS;
+
+/// a break this long between rolls in a channel starts a new session
+pub const SESSION_GAP: Duration = Duration::hours(4);
+
+/// a roll that someone made
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollRecord {
+    pub user: String,
+    pub channel: Channel,
+    /// the expression after macros were expanded
+    pub expression: String,
+    pub dice: Vec<RolledDie>,
+    pub total: i32,
+    pub timestamp: OffsetDateTime,
+}
+
+impl RollRecord {
+    pub fn new(user: &User, channel: Channel, expression: &str, roll: &DiceRoll) -> Self {
+        Self {
+            user: user.to_string(),
+            channel,
+            expression: expression.to_string(),
+            dice: roll.dice().to_vec(),
+            total: roll.total(),
+            timestamp: OffsetDateTime::now_utc(),
+        }
+    }
+}
+
+impl std::fmt::Display for RollRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dice = self
+            .dice
+            .iter()
+            .map(|die| match die.kept {
+                true => die.value.to_string(),
+                false => format!("~~{}~~", die.value),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "`{}` [{}] = **{}**", self.expression, dice, self.total)
+    }
+}
+
+/// how lucky someone has been
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Luck {
+    pub rolls: usize,
+    pub nat20s: usize,
+    pub nat1s: usize,
+    /// how high the kept dice landed on average,
+    /// from 0 (always 1s) to 1 (always the max)
+    pub average: Option<f64>,
+}
+
+impl Luck {
+    fn from_records<'a>(records: impl IntoIterator<Item = &'a RollRecord>) -> Self {
+        let mut luck = Luck::default();
+        let (mut sum, mut dice) = (0.0, 0_u32);
+
+        for record in records {
+            luck.rolls += 1;
+            for die in record.dice.iter().filter(|die| die.kept && die.sides > 1) {
+                luck.nat20s += usize::from(die.is_nat20());
+                luck.nat1s += usize::from(die.is_nat1());
+                sum += f64::from(die.value - 1) / f64::from(die.sides - 1);
+                dice += 1;
+            }
+        }
+
+        luck.average = (dice > 0).then(|| sum / f64::from(dice));
+        luck
+    }
+}
+
+impl std::fmt::Display for Luck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} rolls, {} nat 20s, {} nat 1s",
+            self.rolls, self.nat20s, self.nat1s
+        )?;
+        if let Some(average) = self.average {
+            write!(f, ", rolls {:.0}% high on average", average * 100.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// persistent roll history, oldest first.
+/// each roll is appended to a JSON lines file as it happens,
+/// and the file is only rewritten once it's grown to [`MAX_FILE_LINES`].
+/// clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct RollHistory {
+    path: Option<PathBuf>,
+    log: Arc<RwLock<RollLog>>,
+}
+
+#[derive(Debug, Default)]
+struct RollLog {
+    /// the last [`MAX_RECORDS`] rolls
+    records: Vec<RollRecord>,
+    /// how many rolls are in the file, including ones that were trimmed from `records`
+    file_lines: usize,
+}
+
+impl RollHistory {
+    /// a history that only lives in memory
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// open the history at `path`, which is created on the first roll if it doesn't exist
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut records: Vec<RollRecord> = if file_exists(&path).await? {
+            read_json_lines(&path).await?
+        } else {
+            Vec::new()
+        };
+        let file_lines = records.len();
+        trim(&mut records);
+
+        Ok(Self {
+            path: Some(path),
+            log: Arc::new(RwLock::new(RollLog {
+                records,
+                file_lines,
+            })),
+        })
+    }
+
+    pub async fn record(&self, record: RollRecord) -> Result<()> {
+        let mut log = self.log.write().await;
+
+        if let Some(path) = &self.path {
+            append_json_line(path, &record).await?;
+            log.file_lines += 1;
+        }
+
+        log.records.push(record);
+        trim(&mut log.records);
+
+        if let Some(path) = &self.path
+            && log.file_lines >= MAX_FILE_LINES
+        {
+            write_json_lines(path, &log.records).await?;
+            log.file_lines = log.records.len();
+        }
+
+        Ok(())
+    }
+
+    /// a user's most recent rolls, newest first
+    pub async fn recent(&self, user: &User, limit: usize) -> Vec<RollRecord> {
+        let user = user.to_string();
+        let log = self.log.read().await;
+        log.records
+            .iter()
+            .rev()
+            .filter(|record| record.user == user)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// a user's luck over all their rolls
+    pub async fn luck(&self, user: &User) -> Luck {
+        let user = user.to_string();
+        let log = self.log.read().await;
+        Luck::from_records(log.records.iter().filter(|record| record.user == user))
+    }
+
+    /// everyone's luck in the current session in `channel`, luckiest first
+    pub async fn session_luck(&self, channel: Channel) -> Vec<(String, Luck)> {
+        let log = self.log.read().await;
+        let session = current_session(&log.records, channel);
+
+        let mut by_user: BTreeMap<&str, Vec<&RollRecord>> = BTreeMap::new();
+        for record in session {
+            by_user
+                .entry(record.user.as_str())
+                .or_default()
+                .push(record);
+        }
+
+        let mut luck: Vec<(String, Luck)> = by_user
+            .into_iter()
+            .map(|(user, records)| (user.to_string(), Luck::from_records(records)))
+            .collect();
+        luck.sort_by(|(_, a), (_, b)| {
+            b.average
+                .partial_cmp(&a.average)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(b.nat20s.cmp(&a.nat20s))
+        });
+        luck
+    }
+}
+
+/// drop the oldest rolls past [`MAX_RECORDS`]
+fn trim(records: &mut Vec<RollRecord>) {
+    let overflow = records.len().saturating_sub(MAX_RECORDS);
+    records.drain(..overflow);
+}
+
+/// the trailing rolls in `channel` without a [`SESSION_GAP`] between them
+fn current_session(records: &[RollRecord], channel: Channel) -> Vec<&RollRecord> {
+    let mut session: Vec<&RollRecord> = Vec::new();
+
+    for record in records
+        .iter()
+        .rev()
+        .filter(|record| record.channel == channel)
+    {
+        if let Some(next) = session.last()
+            && next.timestamp - record.timestamp > SESSION_GAP
+        {
+            break;
+        }
+        session.push(record);
+    }
+
+    session.reverse();
+    session
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d20(user: &str, value: u8, hours_ago: i64) -> RollRecord {
+        RollRecord {
+            user: user.to_string(),
+            channel: Channel::Dnd,
+            expression: "d20".to_string(),
+            dice: vec![RolledDie {
+                sides: 20,
+                value,
+                kept: true,
+            }],
+            total: i32::from(value),
+            timestamp: OffsetDateTime::now_utc() - Duration::hours(hours_ago),
+        }
+    }
+
+    #[tokio::test]
+    async fn history_tracks_recent_rolls_and_luck() {
+        let history = RollHistory::default();
+        let bob = User::from("bob");
+
+        for record in [d20("bob", 20, 0), d20("bob", 1, 0), d20("alice", 12, 0)] {
+            history.record(record).await.expect("should record roll");
+        }
+
+        let recent = history.recent(&bob, 1).await;
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].total, 1);
+
+        let luck = history.luck(&bob).await;
+        assert_eq!(luck.rolls, 2);
+        assert_eq!(luck.nat20s, 1);
+        assert_eq!(luck.nat1s, 1);
+        assert_eq!(luck.average, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn session_luck_ignores_old_sessions() {
+        let history = RollHistory::default();
+
+        for record in [
+            d20("carol", 20, 30),
+            d20("bob", 3, 1),
+            d20("alice", 18, 0),
+            d20("bob", 5, 0),
+        ] {
+            history.record(record).await.expect("should record roll");
+        }
+
+        let luck = history.session_luck(Channel::Dnd).await;
+        let users: Vec<&str> = luck.iter().map(|(user, _)| user.as_str()).collect();
+        assert_eq!(users, vec!["alice", "bob"]);
+    }
+
+    #[tokio::test]
+    async fn history_is_appended_to_disk() {
+        let path = std::env::temp_dir().join(format!(
+            "ultron_roll_history_test_{}.jsonl",
+            std::process::id()
+        ));
+        let _ = tokio::fs::remove_file(&path).await;
+
+        let history = RollHistory::open(&path).await.expect("should open history");
+        for record in [d20("bob", 20, 0), d20("alice", 12, 0)] {
+            history.record(record).await.expect("should record roll");
+        }
+
+        let contents = tokio::fs::read_to_string(&path)
+            .await
+            .expect("should read history file");
+        assert_eq!(contents.lines().count(), 2);
+
+        let reopened = RollHistory::open(&path)
+            .await
+            .expect("should reopen history");
+        assert_eq!(reopened.recent(&User::from("bob"), 5).await.len(), 1);
+
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}
@@ -0,0 +1,145 @@
+//! caps on how big a roll can get, so `!ultron roll 255d255*255d255*...`
+//! can't stall the bot or flood the channel.
+//! every path that rolls dice (Discord, HTTP, MCP) goes through a [`crate::dice::DiceRoller`],
+//! which checks its [`DiceLimits`] before and after rolling.
+use tyche::Expr;
+
+use crate::dice::DiceRollError;
+
+/// how big a roll is allowed to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiceLimits {
+    /// dice rolled in one command, over all repetitions
+    pub max_dice: usize,
+    /// sides on any one die
+    pub max_sides: usize,
+    /// how deeply operators can nest.
+    /// parens only count if they change the shape of the expression,
+    /// since tyche doesn't keep them around
+    pub max_depth: usize,
+    /// characters in the rendered result
+    pub max_output_len: usize,
+}
+
+impl Default for DiceLimits {
+    fn default() -> Self {
+        Self {
+            max_dice: 200,
+            max_sides: 100,
+            max_depth: 16,
+            // leaves room in a 2000 character Discord message
+            max_output_len: 1500,
+        }
+    }
+}
+
+/// which of the [`DiceLimits`] was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum Limit {
+    #[strum(to_string = "dice")]
+    Dice,
+    #[strum(to_string = "sides")]
+    Sides,
+    #[strum(to_string = "levels of nesting")]
+    Depth,
+    #[strum(to_string = "characters of output")]
+    OutputLength,
+}
+
+impl DiceLimits {
+    /// check an expression before rolling it `repetitions` times
+    pub fn check_expr(&self, expr: &Expr, repetitions: usize) -> Result<(), DiceRollError> {
+        let dice = self.walk(expr, 1)?;
+        self.check(Limit::Dice, dice.saturating_mul(repetitions))
+    }
+
+    /// check one of the limits against a value
+    pub fn check(&self, limit: Limit, value: usize) -> Result<(), DiceRollError> {
+        let max = match limit {
+            Limit::Dice => self.max_dice,
+            Limit::Sides => self.max_sides,
+            Limit::Depth => self.max_depth,
+            Limit::OutputLength => self.max_output_len,
+        };
+
+        match value > max {
+            true => Err(DiceRollError::LimitExceeded { limit, value, max }),
+            false => Ok(()),
+        }
+    }
+
+    /// count the dice in `expr`, checking sides and depth on the way
+    fn walk(&self, expr: &Expr, depth: usize) -> Result<usize, DiceRollError> {
+        self.check(Limit::Depth, depth)?;
+
+        match expr {
+            Expr::Num(_) => Ok(0),
+            Expr::Dice(dice) => {
+                self.check(Limit::Sides, usize::from(dice.sides))?;
+                Ok(usize::from(dice.count))
+            }
+            Expr::Neg(inner) => self.walk(inner, depth + 1),
+            Expr::Add(left, right)
+            | Expr::Sub(left, right)
+            | Expr::Mul(left, right)
+            | Expr::DivDown(left, right)
+            | Expr::DivUp(left, right) => {
+                let left = self.walk(left, depth + 1)?;
+                let right = self.walk(right, depth + 1)?;
+                Ok(left.saturating_add(right))
+            }
+            // `Expr` is non-exhaustive, anything new has no dice we know how to count
+            _ => Ok(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr as _;
+
+    use super::*;
+
+    fn check(limits: &DiceLimits, input: &str) -> Result<(), DiceRollError> {
+        let expr = Expr::from_str(input).expect("should parse expression");
+        limits.check_expr(&expr, 1)
+    }
+
+    #[test]
+    fn big_expressions_are_rejected() {
+        let limits = DiceLimits {
+            max_dice: 10,
+            max_sides: 20,
+            max_depth: 4,
+            max_output_len: 100,
+        };
+
+        check(&limits, "4d6+2d8-2").expect("should be allowed");
+
+        assert!(matches!(
+            check(&limits, "6d6+6d6"),
+            Err(DiceRollError::LimitExceeded {
+                limit: Limit::Dice,
+                value: 12,
+                max: 10
+            })
+        ));
+        assert!(matches!(
+            check(&limits, "1d100"),
+            Err(DiceRollError::LimitExceeded {
+                limit: Limit::Sides,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check(&limits, "1+(1+(1+(1+1)))"),
+            Err(DiceRollError::LimitExceeded {
+                limit: Limit::Depth,
+                value: 5,
+                max: 4
+            })
+        ));
+        // tyche throws redundant parens away
+        check(&limits, "((((((1))))))").expect("should be allowed");
+    }
+}
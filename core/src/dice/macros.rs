@@ -0,0 +1,207 @@
+//! saved dice expressions, e.g. `!ultron roll save attack 1d20+7`.
+//! macros belong to a [`User`] and are expanded before the expression is parsed,
+//! so `attack+2` becomes `(1d20+7)+2`.
+use std::{collections::BTreeMap, str::FromStr as _};
+
+use tyche::Expr;
+
+use crate::{
+    User,
+    dice::{DiceRollError, sheet::blank_variables},
+    error::Result,
+    store::Store,
+};
+
+/// subcommands of `roll` that can't be used as macro names
+const RESERVED_NAMES: &[&str] = &[
+    "save", "delete", "macros", "stats", "history", "luck", "luckiest", "verify", "session", "help",
+];
+
+/// macros by user name, then by macro name
+type MacroMap = BTreeMap<String, BTreeMap<String, String>>;
+
+/// persistent dice macros for each [`User`]
+#[derive(Debug, Clone, Default)]
+pub struct DiceMacros(Store<MacroMap>);
+
+impl From<Store<MacroMap>> for DiceMacros {
+    fn from(store: Store<MacroMap>) -> Self {
+        Self(store)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DiceMacroError {
+    #[error("`{0}` can't be used as a macro name")]
+    InvalidName(String),
+
+    #[error("invalid dice expression for macro: {0}")]
+    InvalidExpression(#[from] DiceRollError),
+
+    #[error("failed to save macro: {0}")]
+    Storage(#[from] crate::error::Error),
+}
+
+impl DiceMacros {
+    /// save a macro for `user`, returning the expression it replaced
+    pub async fn save(
+        &self,
+        user: &User,
+        name: &str,
+        expression: &str,
+    ) -> std::result::Result<Option<String>, DiceMacroError> {
+        if !is_valid_name(name) {
+            return Err(DiceMacroError::InvalidName(name.to_string()));
+        }
+
+        // make sure the macro can be rolled before saving it.
+        // `@name` comes from the roller's character sheet, so it can be anything for now
+        Expr::from_str(&blank_variables(expression)?).map_err(DiceRollError::from)?;
+
+        let previous = self
+            .0
+            .update(|macros| {
+                macros
+                    .entry(user.to_string())
+                    .or_default()
+                    .insert(name.to_string(), expression.to_string())
+            })
+            .await?;
+
+        Ok(previous)
+    }
+
+    /// delete a macro, returning its expression
+    pub async fn delete(&self, user: &User, name: &str) -> Result<Option<String>> {
+        self.0
+            .update(|macros| {
+                macros
+                    .get_mut(&user.to_string())
+                    .and_then(|user_macros| user_macros.remove(name))
+            })
+            .await
+    }
+
+    /// all of a user's macros, sorted by name
+    pub async fn list(&self, user: &User) -> Vec<(String, String)> {
+        self.0
+            .read(|macros| {
+                macros
+                    .get(&user.to_string())
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, expression)| (name.clone(), expression.clone()))
+                    .collect()
+            })
+            .await
+    }
+
+    /// replace any of the user's macro names in `input` with their expressions.
+    /// macros are not expanded recursively.
+    pub async fn expand(&self, user: &User, input: &str) -> String {
+        self.0
+            .read(|macros| match macros.get(&user.to_string()) {
+                Some(user_macros) => expand_macros(input, user_macros),
+                None => input.to_string(),
+            })
+            .await
+    }
+}
+
+/// macro names look like identifiers,
+/// and can't be confused with dice notation like `d20`
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_with_letter = chars.next().is_some_and(|c| c.is_ascii_alphabetic());
+
+    starts_with_letter
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED_NAMES.contains(&name)
+        && Expr::from_str(name).is_err()
+}
+
+fn expand_macros(input: &str, macros: &BTreeMap<String, String>) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut word = String::new();
+
+    let flush = |word: &mut String, output: &mut String| {
+        match macros.get(word.as_str()) {
+            Some(expression) => {
+                output.push('(');
+                output.push_str(expression);
+                output.push(')');
+            }
+            None => output.push_str(word),
+        }
+        word.clear();
+    };
+
+    for c in input.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut output);
+            output.push(c);
+        }
+    }
+    flush(&mut word, &mut output);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn macro_names_are_validated() {
+        assert!(is_valid_name("attack"));
+        assert!(is_valid_name("fire_bolt2"));
+        assert!(!is_valid_name("d20"));
+        assert!(!is_valid_name("2attack"));
+        assert!(!is_valid_name("save"));
+        assert!(!is_valid_name("sneak attack"));
+    }
+
+    #[tokio::test]
+    async fn macros_expand_per_user() {
+        let macros = DiceMacros::default();
+        let bob = User::from("bob");
+        let alice = User::from("alice");
+
+        macros
+            .save(&bob, "attack", "1d20+7")
+            .await
+            .expect("should save macro");
+        macros
+            .save(&bob, "damage", "2d6+4")
+            .await
+            .expect("should save macro");
+
+        assert_eq!(macros.expand(&bob, "attack+2").await, "(1d20+7)+2");
+        assert_eq!(macros.expand(&bob, "damage + 1d6").await, "(2d6+4) + 1d6");
+        assert_eq!(macros.expand(&bob, "attacks").await, "attacks");
+        assert_eq!(macros.expand(&alice, "attack+2").await, "attack+2");
+
+        macros
+            .delete(&bob, "attack")
+            .await
+            .expect("should delete macro");
+        assert_eq!(
+            macros.list(&bob).await,
+            vec![("damage".to_string(), "2d6+4".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_macros_are_rejected() {
+        let macros = DiceMacros::default();
+        let bob = User::from("bob");
+
+        let error = macros
+            .save(&bob, "attack", "1d20 +")
+            .await
+            .expect_err("should reject a bad expression");
+        assert!(matches!(error, DiceMacroError::InvalidExpression(_)));
+    }
+}
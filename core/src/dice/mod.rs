@@ -0,0 +1,861 @@
+//! a module for parsing and evaluating dice rolls.
+//! similar to other dice rolling libraries, this module
+use std::{fmt::Display, str::FromStr};
+
+use rmcp::schemars;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    card::{Card, CardField, Color},
+    dice::{
+        limits::{DiceLimits, Limit},
+        pool::RollSpec,
+        sheet::CharacterSheet,
+        stats::Target,
+    },
+};
+use tyche::{
+    Expr,
+    dice::{self, Roller},
+    expr::{Describe, Evaled},
+};
+
+pub mod audit;
+pub mod history;
+pub mod limits;
+pub mod macros;
+pub mod pool;
+pub mod rollers;
+pub mod sheet;
+pub mod stats;
+
+pub const HELP_MESSAGE: &str = r#"
+roll a d20: `d20`
+roll _2_ d20s: `2d20`
+roll a d6 and a d8: `d6 + d8`
+roll with advantage: `2d20K1` (k for "keep")
+roll with disadvantage: `2d20k1`
+keep the highest 3 of 4d6: `4d6K3`
+roll an ability score array: `6x 4d6K3`
+count successes in a dice pool: `8d10>=7`
+exploding dice: `4d6x`
+
+save a macro: `save attack 1d20+7`
+roll a macro: `attack` or `attack+2`
+list your macros: `macros`
+delete a macro: `delete attack`
+
+your last rolls: `history` or `history 10`
+your nat 20s and nat 1s: `luck`
+luckiest player this session: `luckiest`
+
+check a roll: `verify 12`
+show the audit commitment: `session`
+reveal the audit seed: `session end`
+
+use your character sheet: `1d20+@str+@prof`
+
+odds of a roll: `stats 2d20K1 >= 15`
+
+input is passed as is to the `caith` crate:
+https://docs.rs/caith/4.2.4/caith/#syntax
+"#;
+
+pub trait RollerImpl: tyche::dice::Roller + std::fmt::Debug + Clone + Send + Sync {}
+
+impl RollerImpl for dice::roller::FastRand {}
+impl RollerImpl for dice::roller::Max {}
+
+/// a cloneable dice roller that controls the RNG
+/// and keeps rolls within its [`DiceLimits`]
+#[derive(Debug, Clone)]
+pub struct DiceRoller<TInner> {
+    inner: TInner,
+    limits: DiceLimits,
+}
+
+impl Default for DiceRoller<dice::roller::FastRand> {
+    fn default() -> Self {
+        Self::with_default_rng()
+    }
+}
+
+impl<TInner> DiceRoller<TInner>
+where
+    TInner: RollerImpl,
+{
+    /// use different [`DiceLimits`], e.g. from the CLI
+    pub fn with_limits(self, limits: DiceLimits) -> Self {
+        Self { limits, ..self }
+    }
+
+    pub fn limits(&self) -> &DiceLimits {
+        &self.limits
+    }
+
+    pub fn roll_expr(mut self, expr: Expr) -> Result<Evaled<'static>, DiceRollError> {
+        self.limits.check_expr(&expr, 1)?;
+        Ok(expr.eval(&mut self.inner)?.into_owned())
+    }
+
+    pub fn roll_string(self, input: &str) -> Result<Evaled<'static>, DiceRollError> {
+        let expr = Expr::from_str(input)?;
+        self.roll_expr(expr)
+    }
+
+    /// roll `input` without using up the roller, e.g. to roll a bunch of times in a row.
+    /// understands the extra syntax in [`RollSpec`] on top of tyche's.
+    pub fn roll_dice(&mut self, input: &str) -> Result<DiceRoll, DiceRollError> {
+        let spec = RollSpec::from_str(input)?;
+        let expr = Expr::from_str(&spec.expression)?;
+        self.limits.check_expr(&expr, spec.repetitions as usize)?;
+
+        let repetitions = (0..spec.repetitions)
+            .map(|_| {
+                let repetition = Repetition::try_from(expr.eval(&mut self.inner)?)?;
+                Ok(match &spec.successes {
+                    Some(target) => repetition.count_successes(target),
+                    None => repetition,
+                })
+            })
+            .collect::<Result<Vec<_>, DiceRollError>>()?;
+        let dice_roll = DiceRoll::from(repetitions);
+
+        // exploding dice can roll more than the expression asked for
+        self.limits.check(Limit::Dice, dice_roll.dice.len())?;
+        self.limits
+            .check(Limit::OutputLength, dice_roll.to_string().chars().count())?;
+
+        Ok(dice_roll)
+    }
+
+    /// like [`DiceRoller::roll_dice`],
+    /// but `@name` is replaced with the value from `sheet` first
+    pub fn roll_with_sheet(
+        &mut self,
+        input: &str,
+        sheet: &CharacterSheet,
+    ) -> Result<DiceRoll, DiceRollError> {
+        let input = sheet::substitute_variables(input, sheet)?;
+        self.roll_dice(&input)
+    }
+}
+
+impl<TRoller> From<TRoller> for DiceRoller<TRoller>
+where
+    TRoller: RollerImpl,
+{
+    fn from(inner: TRoller) -> Self {
+        Self {
+            inner,
+            limits: DiceLimits::default(),
+        }
+    }
+}
+
+impl DiceRoller<dice::roller::FastRand> {
+    pub fn with_rng(seed: u64) -> Self {
+        Self::from(dice::roller::FastRand::with_seed(seed))
+    }
+
+    pub fn with_default_rng() -> Self {
+        Self::from(dice::roller::FastRand::default())
+    }
+}
+
+impl DiceRoller<dice::roller::Max> {
+    pub fn max() -> Self {
+        Self::from(dice::roller::Max)
+    }
+}
+
+impl DiceRoller<rollers::SecureRand> {
+    pub fn secure() -> Self {
+        Self::from(rollers::SecureRand)
+    }
+}
+
+impl DiceRoller<rollers::Min> {
+    pub fn min() -> Self {
+        Self::from(rollers::Min)
+    }
+}
+
+impl DiceRoller<rollers::Scripted> {
+    /// roll `faces` in order, see [`rollers::Scripted`]
+    pub fn scripted(faces: impl Into<std::sync::Arc<[u8]>>) -> Self {
+        Self::from(rollers::Scripted::new(faces))
+    }
+}
+
+pub fn roller<T>() -> T
+where
+    T: Roller + Default,
+{
+    Default::default()
+}
+
+#[cfg(test)]
+pub fn test_roller() -> dice::roller::Max {
+    roller()
+}
+
+/// A dice roll that represents a collection of dice
+/// that can be rolled together.
+/// Example: 2d6 + 3d8
+/// This would be a DiceRoll with 2 Die with 6 sides
+/// and 3 Die with 8 sides.
+/// Advantage and disadvantage can be represented by
+/// rolling 2 dice and taking the highest or lowest
+/// respectively.
+/// Example: 2d20h1 or 2d20l1
+/// This would be a DiceRoll with 2 Die with 20 sides
+/// and taking the highest or lowest respectively.
+///
+/// [`Evaled`] shows the individual rolls
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct DiceRoll {
+    #[schemars(description = "the evaluated expression of the dice roll")]
+    evaluated_expression: String,
+    #[schemars(description = "the total of the dice roll, summed over all repetitions")]
+    total: i32,
+    #[schemars(description = "every die that was rolled, in order")]
+    dice: Vec<RolledDie>,
+    #[schemars(
+        description = "how many dice met the target in a dice pool like `8d10>=7`, summed over all repetitions"
+    )]
+    successes: Option<u32>,
+    #[schemars(
+        description = "each roll when it's repeated like `6x 4d6k3`, otherwise just the one"
+    )]
+    repetitions: Vec<Repetition>,
+    #[schemars(description = "whether any kept d20 landed on a natural 20")]
+    #[serde(default)]
+    crit: bool,
+    #[schemars(description = "whether any kept d20 landed on a natural 1")]
+    #[serde(default)]
+    fumble: bool,
+}
+
+impl DiceRoll {
+    pub fn total(&self) -> i32 {
+        self.total
+    }
+
+    pub fn dice(&self) -> &[RolledDie] {
+        &self.dice
+    }
+
+    pub fn successes(&self) -> Option<u32> {
+        self.successes
+    }
+
+    pub fn repetitions(&self) -> &[Repetition] {
+        &self.repetitions
+    }
+
+    /// a natural 20 on any kept d20
+    pub fn is_crit(&self) -> bool {
+        self.crit
+    }
+
+    /// a natural 1 on any kept d20
+    pub fn is_fumble(&self) -> bool {
+        self.fumble
+    }
+}
+
+/// one roll of the expression in a [`DiceRoll`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Repetition {
+    #[schemars(description = "the evaluated expression of this roll")]
+    pub evaluated_expression: String,
+    #[schemars(description = "the total of this roll")]
+    pub total: i32,
+    #[schemars(description = "every die in this roll, in order")]
+    pub dice: Vec<RolledDie>,
+    #[schemars(
+        description = "the parts that were added up, e.g. `2d20kh1` and `+5` in `2d20kh1 + 5`"
+    )]
+    #[serde(default)]
+    pub terms: Vec<Term>,
+    #[schemars(description = "how many dice in this roll met the target, for dice pools")]
+    pub successes: Option<u32>,
+    #[schemars(description = "whether a kept d20 in this roll landed on a natural 20")]
+    #[serde(default)]
+    pub crit: bool,
+    #[schemars(description = "whether a kept d20 in this roll landed on a natural 1")]
+    #[serde(default)]
+    pub fumble: bool,
+}
+
+impl Repetition {
+    /// count the kept dice that meet `target`
+    fn count_successes(self, target: &Target) -> Self {
+        let successes = self
+            .dice
+            .iter()
+            .filter(|die| die.kept)
+            .filter(|die| {
+                target
+                    .comparison
+                    .matches(i32::from(die.value), target.value)
+            })
+            .count();
+
+        Self {
+            successes: Some(successes as u32),
+            ..self
+        }
+    }
+}
+
+impl Display for Repetition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "_{}_ = **{}**", self.evaluated_expression, self.total)?;
+        if let Some(successes) = self.successes {
+            write!(f, " ({} successes)", successes)?;
+        }
+        if self.crit {
+            write!(f, " 💥")?;
+        }
+        if self.fumble {
+            write!(f, " 💀")?;
+        }
+        Ok(())
+    }
+}
+
+/// a single die from a [`DiceRoll`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct RolledDie {
+    #[schemars(description = "how many sides the die has")]
+    pub sides: u8,
+    #[schemars(description = "the number the die landed on")]
+    pub value: u8,
+    #[schemars(description = "whether the die counts towards the total, e.g. not dropped by `k`")]
+    pub kept: bool,
+}
+
+impl RolledDie {
+    /// a kept natural 20 on a d20
+    pub fn is_nat20(&self) -> bool {
+        self.kept && self.sides == 20 && self.value == 20
+    }
+
+    /// a kept natural 1 on a d20
+    pub fn is_nat1(&self) -> bool {
+        self.kept && self.sides == 20 && self.value == 1
+    }
+}
+
+/// dropped dice are struck through, natural 20s are bold and natural 1s underlined
+impl Display for RolledDie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            die if !die.kept => write!(f, "~~{}~~", die.value),
+            die if die.is_nat20() => write!(f, "**{}**", die.value),
+            die if die.is_nat1() => write!(f, "__{}__", die.value),
+            die => write!(f, "{}", die.value),
+        }
+    }
+}
+
+/// one part of a [`Repetition`] that gets added or subtracted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Term {
+    #[schemars(description = "a group of dice, e.g. `4d6kh3`")]
+    Dice {
+        #[schemars(description = "the dice notation, including modifiers like `kh3`")]
+        notation: String,
+        #[schemars(description = "whether this term is subtracted")]
+        negative: bool,
+        #[schemars(description = "every die rolled for this term, including dropped ones")]
+        dice: Vec<RolledDie>,
+        #[schemars(description = "the sum of the kept dice, before the sign is applied")]
+        subtotal: i32,
+    },
+    #[schemars(description = "a flat number, e.g. the `-2` in `1d20 - 2`")]
+    Modifier {
+        #[schemars(description = "the number, already negative if it's subtracted")]
+        value: i32,
+    },
+    #[schemars(
+        description = "anything that isn't simply added or subtracted, like the multiplication in `(1d6 + 1) * 2`. parentheses that only group added terms, like `(1d6 + 1) + 2`, are flattened into separate terms"
+    )]
+    Expression {
+        #[schemars(description = "the evaluated expression")]
+        expression: String,
+        #[schemars(description = "whether this term is subtracted")]
+        negative: bool,
+        #[schemars(description = "every die rolled inside the expression")]
+        dice: Vec<RolledDie>,
+        #[schemars(description = "what the expression came to, before the sign is applied")]
+        value: i32,
+    },
+}
+
+/// split an expression into the [`Term`]s that are added up, left to right
+fn collect_terms(
+    evaled: &Evaled<'_>,
+    negative: bool,
+    terms: &mut Vec<Term>,
+) -> Result<(), DiceRollError> {
+    match evaled {
+        Evaled::Num(value) => terms.push(Term::Modifier {
+            value: match negative {
+                true => -*value,
+                false => *value,
+            },
+        }),
+        Evaled::Dice(rolled) => {
+            let mut dice = Vec::new();
+            collect_dice(evaled, &mut dice);
+            terms.push(Term::Dice {
+                notation: rolled.dice.to_string(),
+                negative,
+                subtotal: dice
+                    .iter()
+                    .filter(|die| die.kept)
+                    .map(|die| i32::from(die.value))
+                    .sum(),
+                dice,
+            });
+        }
+        Evaled::Neg(inner) => collect_terms(inner, !negative, terms)?,
+        Evaled::Add(left, right) => {
+            collect_terms(left, negative, terms)?;
+            collect_terms(right, negative, terms)?;
+        }
+        Evaled::Sub(left, right) => {
+            collect_terms(left, negative, terms)?;
+            collect_terms(right, !negative, terms)?;
+        }
+        // multiplication, division and anything newer in the non-exhaustive `Evaled`
+        _ => {
+            let mut dice = Vec::new();
+            collect_dice(evaled, &mut dice);
+            terms.push(Term::Expression {
+                expression: evaled.describe(None),
+                negative,
+                dice,
+                value: evaled.calc()?,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// flatten all the dice in an expression, left to right
+fn collect_dice(evaled: &Evaled<'_>, dice: &mut Vec<RolledDie>) {
+    match evaled {
+        Evaled::Num(_) => {}
+        Evaled::Dice(rolled) => dice.extend(rolled.rolls.iter().map(|roll| RolledDie {
+            sides: rolled.dice.sides,
+            value: roll.val,
+            kept: !roll.is_dropped(),
+        })),
+        Evaled::Neg(inner) => collect_dice(inner, dice),
+        Evaled::Add(left, right)
+        | Evaled::Sub(left, right)
+        | Evaled::Mul(left, right)
+        | Evaled::DivDown(left, right)
+        | Evaled::DivUp(left, right) => {
+            collect_dice(left, dice);
+            collect_dice(right, dice);
+        }
+        // `Evaled` is non-exhaustive, anything new has no dice we know how to find
+        _ => {}
+    }
+}
+
+impl TryFrom<Evaled<'_>> for Repetition {
+    type Error = DiceRollError;
+
+    fn try_from(evaled: Evaled<'_>) -> Result<Self, Self::Error> {
+        let total = evaled.calc()?;
+        let dice_limit = None;
+        let evaluated_expression = evaled.describe(dice_limit);
+        let mut dice = Vec::new();
+        collect_dice(&evaled, &mut dice);
+        let mut terms = Vec::new();
+        collect_terms(&evaled, false, &mut terms)?;
+        Ok(Self {
+            evaluated_expression,
+            total,
+            terms,
+            crit: dice.iter().any(RolledDie::is_nat20),
+            fumble: dice.iter().any(RolledDie::is_nat1),
+            dice,
+            successes: None,
+        })
+    }
+}
+
+impl TryFrom<Evaled<'_>> for DiceRoll {
+    type Error = DiceRollError;
+
+    fn try_from(evaled: Evaled<'_>) -> Result<Self, Self::Error> {
+        Ok(Self::from(vec![Repetition::try_from(evaled)?]))
+    }
+}
+
+impl From<Vec<Repetition>> for DiceRoll {
+    fn from(repetitions: Vec<Repetition>) -> Self {
+        let evaluated_expression = repetitions
+            .iter()
+            .map(|repetition| repetition.evaluated_expression.as_str())
+            .collect::<Vec<_>>()
+            .join("; ");
+        let successes = repetitions
+            .iter()
+            .map(|repetition| repetition.successes)
+            .sum::<Option<u32>>();
+
+        Self {
+            evaluated_expression,
+            total: repetitions.iter().map(|repetition| repetition.total).sum(),
+            dice: repetitions
+                .iter()
+                .flat_map(|repetition| repetition.dice.iter().copied())
+                .collect(),
+            successes,
+            crit: repetitions.iter().any(|repetition| repetition.crit),
+            fumble: repetitions.iter().any(|repetition| repetition.fumble),
+            repetitions,
+        }
+    }
+}
+
+impl Display for DiceRoll {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.repetitions.as_slice() {
+            [repetition] => write!(f, "{}", repetition),
+            repetitions => {
+                let lines = repetitions
+                    .iter()
+                    .enumerate()
+                    .map(|(index, repetition)| format!("{}. {}", index + 1, repetition))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                write!(f, "{}", lines)?;
+                if let Some(successes) = self.successes {
+                    write!(f, "\n**{} successes**", successes)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<&DiceRoll> for Card {
+    fn from(roll: &DiceRoll) -> Self {
+        let successes = roll
+            .successes
+            .map(|successes| CardField::inline("successes", format!("**{}**", successes)));
+
+        let (title, description, results) = match roll.repetitions.as_slice() {
+            [repetition] => (
+                match (roll.crit, roll.fumble) {
+                    (true, true) => "💥💀 crit and fumble?!",
+                    (true, false) => "💥 critical hit!",
+                    (false, true) => "💀 fumble!",
+                    (false, false) => "🎲 rolled",
+                }
+                .to_string(),
+                format!("_{}_", repetition.evaluated_expression),
+                CardField::inline("total", format!("**{}**", roll.total)),
+            ),
+            repetitions => (
+                format!("🎲 rolled {} times", repetitions.len()),
+                repetitions
+                    .iter()
+                    .enumerate()
+                    .map(|(index, repetition)| format!("{}. {}", index + 1, repetition))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+                CardField::inline(
+                    "totals",
+                    repetitions
+                        .iter()
+                        .map(|repetition| repetition.total.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            ),
+        };
+
+        // repeated rolls already show their dice line by line
+        let dice = match roll.repetitions.as_slice() {
+            [repetition] if !repetition.dice.is_empty() => Some(CardField::inline(
+                "dice",
+                repetition
+                    .dice
+                    .iter()
+                    .map(RolledDie::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )),
+            _ => None,
+        };
+
+        let color = match roll.crit && !roll.fumble {
+            true => Color::SUCCESS,
+            false => Color::ULTRON,
+        };
+
+        Card::builder()
+            .title(title)
+            .description(description)
+            .fields(
+                std::iter::once(results)
+                    .chain(dice)
+                    .chain(successes)
+                    .collect(),
+            )
+            .color(color)
+            .build()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum DiceRollResult {
+    Roll(DiceRoll),
+    Help(&'static str),
+}
+
+impl DiceRollResult {
+    pub fn from_str<TRoller>(
+        input: &str,
+        roller: DiceRoller<TRoller>,
+    ) -> Result<Self, DiceRollError>
+    where
+        TRoller: RollerImpl,
+    {
+        Self::from_str_with_sheet(input, roller, &CharacterSheet::new())
+    }
+
+    /// roll `input` with `@name` variables from a character sheet
+    pub fn from_str_with_sheet<TRoller>(
+        input: &str,
+        mut roller: DiceRoller<TRoller>,
+        sheet: &CharacterSheet,
+    ) -> Result<Self, DiceRollError>
+    where
+        TRoller: RollerImpl,
+    {
+        if input == "help" {
+            tracing::debug!("sending help message");
+            return Ok(DiceRollResult::Help(HELP_MESSAGE));
+        }
+
+        let dice_roll = roller.roll_with_sheet(input, sheet)?;
+
+        tracing::debug!("computed roll: {dice_roll}");
+        Ok(DiceRollResult::Roll(dice_roll))
+    }
+}
+
+impl Display for DiceRollResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiceRollResult::Roll(roll) => write!(f, "{}", roll),
+            DiceRollResult::Help(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DiceRollError {
+    #[error("failed to parse dice roll from input: {0}")]
+    Parse(#[from] tyche::parse::Error),
+
+    #[error("failed to evaluate dice roll: {0}")]
+    Eval(#[from] tyche::expr::EvalError),
+
+    #[error("failed to calculate dice roll: {0}")]
+    Calc(#[from] tyche::expr::CalcError),
+
+    #[error("'{0}' is not a number to roll against")]
+    InvalidTarget(String),
+
+    #[error("`@{0}` isn't on your character sheet. try `sheet set {0} 2`")]
+    UnknownVariable(String),
+
+    #[error("can only repeat a roll 1 to {max} times, not {requested}")]
+    InvalidRepetitions { requested: String, max: u32 },
+
+    #[error("dice pools count successes on plain dice like `8d10>=7`, not `{0}`")]
+    InvalidPool(String),
+
+    #[error("that roll has too many {limit} ({value}), the most allowed is {max}")]
+    LimitExceeded {
+        limit: Limit,
+        value: usize,
+        max: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// known good rolls
+    const GOOD_ROLLS: &[&str] = &[
+        // Copilot made all these lol
+        "d20",
+        "2d20",
+        "d6 + d8",
+        "2d20K1",
+        "2d20k1",
+        "4d6K3",
+        "4d6k3",
+        "1d4 + 1d6 + 1d8 + 1d10 + 1d12 + 1d20",
+        "3d6 + 2d8 - 1d4",
+        "5d10 * 2",
+        "(2d6 + 3) / 2",
+        "2d20kh1 + 1d4", // keep highest
+        "2d20kl1 + 1d4", // keep lowest
+        // from the tycho docs
+        "4d6rr<3 + 2d8 - 4",
+        "4d6 + 2d8 - 2",
+    ];
+
+    #[test]
+    fn simple_roll() {
+        let roller = DiceRoller::max();
+        for &roll in GOOD_ROLLS {
+            let result =
+                DiceRollResult::from_str(roll, roller.clone()).expect("failed to parse roll");
+            if let DiceRollResult::Roll(dice_roll) = result {
+                tracing::info!("roll: {roll} => {dice_roll}");
+            } else {
+                panic!("expected roll, got help");
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_rolls_and_dice_pools() {
+        let mut roller = DiceRoller::max();
+
+        let array = roller.roll_dice("6x 4d6K3").expect("should roll array");
+        assert_eq!(array.repetitions().len(), 6);
+        assert!(array.repetitions().iter().all(|rep| rep.total == 18));
+        assert_eq!(array.successes(), None);
+
+        let pool = roller.roll_dice("8d10>=7").expect("should roll pool");
+        assert_eq!(pool.successes(), Some(8));
+        assert_eq!(pool.total(), 80);
+
+        let pools = roller.roll_dice("2x 3d6<3").expect("should roll pools");
+        assert_eq!(pools.successes(), Some(0));
+    }
+
+    #[test]
+    fn crits_and_fumbles_are_flagged() {
+        let crit = DiceRoller::max()
+            .roll_dice("2d20k1+5")
+            .expect("should roll");
+        assert!(crit.is_crit());
+        assert!(!crit.is_fumble());
+
+        let card = Card::from(&crit);
+        assert_eq!(card.title.as_deref(), Some("💥 critical hit!"));
+        let dice = card
+            .fields
+            .iter()
+            .find(|field| field.name == "dice")
+            .expect("should show the dice");
+        assert!(dice.value.contains("~~20~~") && dice.value.contains("**20**"));
+
+        let json = serde_json::to_value(&crit).expect("should serialize");
+        assert_eq!(json["crit"], true);
+        assert_eq!(json["fumble"], false);
+
+        let fumble = DiceRoller::min().roll_dice("1d20+5").expect("should roll");
+        assert!(fumble.is_fumble());
+        assert!(fumble.to_string().ends_with("💀"));
+
+        // only d20s count
+        let d6 = DiceRoller::max().roll_dice("1d6").expect("should roll");
+        assert!(!d6.is_crit());
+    }
+
+    #[test]
+    fn rolls_are_split_into_terms() {
+        let roll = DiceRoller::max()
+            .roll_dice("2d20kh1 + 1d4 - 2")
+            .expect("should roll");
+
+        let [repetition] = roll.repetitions() else {
+            panic!("should roll once");
+        };
+        assert!(matches!(
+            repetition.terms.as_slice(),
+            [
+                Term::Dice {
+                    negative: false,
+                    subtotal: 20,
+                    ..
+                },
+                Term::Dice {
+                    negative: false,
+                    subtotal: 4,
+                    ..
+                },
+                Term::Modifier { value: -2 },
+            ]
+        ));
+
+        let Term::Dice { dice, .. } = &repetition.terms[0] else {
+            panic!("should be dice");
+        };
+        assert_eq!(dice.iter().filter(|die| die.kept).count(), 1);
+
+        let roll = DiceRoller::max()
+            .roll_dice("(1d6 + 1) * 2")
+            .expect("should roll");
+        assert!(matches!(
+            roll.repetitions()[0].terms.as_slice(),
+            [Term::Expression { value: 14, .. }]
+        ));
+
+        // tyche drops parentheses, so these are just added terms
+        let roll = DiceRoller::max()
+            .roll_dice("(1d6 + 1) + 2")
+            .expect("should roll");
+        assert!(matches!(
+            roll.repetitions()[0].terms.as_slice(),
+            [
+                Term::Dice { subtotal: 6, .. },
+                Term::Modifier { value: 1 },
+                Term::Modifier { value: 2 },
+            ]
+        ));
+    }
+
+    #[test]
+    fn rolls_respect_limits() {
+        let mut roller = DiceRoller::max().with_limits(DiceLimits {
+            max_dice: 10,
+            ..DiceLimits::default()
+        });
+
+        roller.roll_dice("10d6").expect("should be allowed");
+        assert!(matches!(
+            roller.roll_dice("3x 4d6"),
+            Err(DiceRollError::LimitExceeded {
+                limit: Limit::Dice,
+                value: 12,
+                max: 10,
+            })
+        ));
+        assert!(matches!(
+            roller.clone().roll_string("11d6"),
+            Err(DiceRollError::LimitExceeded { .. })
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! syntax that tyche doesn't understand, handled before the expression is parsed:
+//! repeated rolls like `6x 4d6k3` and success-counting pools like `8d10>=7`.
+use std::str::FromStr;
+
+use crate::dice::{
+    DiceRollError,
+    stats::{Comparison, Target},
+};
+
+/// the most times a single command can repeat a roll
+pub const MAX_REPETITIONS: u32 = 20;
+
+/// what to roll, how many times, and what counts as a success
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollSpec {
+    pub repetitions: u32,
+    /// the part tyche parses
+    pub expression: String,
+    /// count dice that meet this instead of just adding them up
+    pub successes: Option<Target>,
+}
+
+impl FromStr for RollSpec {
+    type Err = DiceRollError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (repetitions, rest) = split_repetitions(input.trim())?;
+        let (expression, successes) = split_successes(rest)?;
+
+        Ok(Self {
+            repetitions,
+            expression: expression.to_string(),
+            successes,
+        })
+    }
+}
+
+/// `6x 4d6k3` => (6, `4d6k3`)
+fn split_repetitions(input: &str) -> Result<(u32, &str), DiceRollError> {
+    let Some((count, rest)) = input
+        .split_once('x')
+        .filter(|(count, _)| !count.is_empty() && count.chars().all(|c| c.is_ascii_digit()))
+    else {
+        return Ok((1, input));
+    };
+
+    let invalid = || DiceRollError::InvalidRepetitions {
+        requested: count.to_string(),
+        max: MAX_REPETITIONS,
+    };
+    let repetitions: u32 = count.parse().map_err(|_| invalid())?;
+    if !(1..=MAX_REPETITIONS).contains(&repetitions) {
+        return Err(invalid());
+    }
+
+    Ok((repetitions, rest.trim()))
+}
+
+/// `8d10>=7` => (`8d10`, `>= 7`).
+/// the comparison has to follow plain dice like `8d10`, since with `1d20+5>=15`
+/// it's anyone's guess whether the target is for each die or for the total.
+/// modifiers with conditions like `4d6rr<3` or `4d6x>=5` are left for tyche.
+fn split_successes(input: &str) -> Result<(&str, Option<Target>), DiceRollError> {
+    let head = input.trim_end_matches(|c: char| c.is_ascii_digit());
+    let Ok(value) = input[head.len()..].parse() else {
+        return Ok((input, None));
+    };
+
+    let Some((operator, comparison)) = Comparison::OPERATORS
+        .iter()
+        .find(|(operator, _)| head.ends_with(operator))
+    else {
+        return Ok((input, None));
+    };
+
+    let expression = &head[..head.len() - operator.len()];
+    if !expression.ends_with(|c: char| c.is_ascii_digit() || c == ')') {
+        return Ok((input, None));
+    }
+    if !is_plain_dice(expression) {
+        return Err(DiceRollError::InvalidPool(expression.to_string()));
+    }
+
+    let target = Target {
+        comparison: *comparison,
+        value,
+    };
+    Ok((expression, Some(target)))
+}
+
+/// `8d10` or `d10`, nothing else
+fn is_plain_dice(expression: &str) -> bool {
+    let Some((count, sides)) = expression.split_once('d') else {
+        return false;
+    };
+    count.chars().all(|c| c.is_ascii_digit())
+        && !sides.is_empty()
+        && sides.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(input: &str) -> RollSpec {
+        input.parse().expect("should parse roll spec")
+    }
+
+    #[test]
+    fn roll_specs_are_parsed() {
+        assert_eq!(
+            spec("6x 4d6k3"),
+            RollSpec {
+                repetitions: 6,
+                expression: "4d6k3".to_string(),
+                successes: None,
+            }
+        );
+        assert_eq!(
+            spec("8d10>=7"),
+            RollSpec {
+                repetitions: 1,
+                expression: "8d10".to_string(),
+                successes: Some(Target {
+                    comparison: Comparison::AtLeast,
+                    value: 7,
+                }),
+            }
+        );
+        assert_eq!(spec("3x5d6x>5").repetitions, 3);
+    }
+
+    #[test]
+    fn tyche_modifiers_are_left_alone() {
+        for input in ["4d6rr<3", "4d6x>=5", "4d6x", "1d20+5", "2d20K1"] {
+            assert_eq!(spec(input).expression, input);
+            assert_eq!(spec(input).successes, None);
+        }
+    }
+
+    #[test]
+    fn pool_targets_need_plain_dice() {
+        assert_eq!(spec("d10>=7").expression, "d10");
+        for input in ["1d20+5>=15", "2d20K1>=15", "(2d6)>=4", "8d10-1>7"] {
+            assert!(
+                matches!(
+                    input.parse::<RollSpec>(),
+                    Err(DiceRollError::InvalidPool(_))
+                ),
+                "{input} shouldn't be a dice pool"
+            );
+        }
+    }
+
+    #[test]
+    fn repetitions_are_limited() {
+        assert!("0x d20".parse::<RollSpec>().is_err());
+        assert!("1000x d20".parse::<RollSpec>().is_err());
+    }
+}
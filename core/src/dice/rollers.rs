@@ -0,0 +1,354 @@
+//! [`RollerImpl`]s beyond the ones tyche ships with,
+//! and a [`RollerConfig`] to pick one at startup.
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use rand::{Rng as _, RngCore as _, SeedableRng as _};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use tyche::dice::{DieRoll, Roller, roller};
+
+use crate::dice::{DiceRoller, RollerImpl};
+
+/// rolls with the OS-seeded thread RNG, which is cryptographically secure.
+/// holds no state, so clones never repeat each other's rolls.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SecureRand;
+
+impl Roller for SecureRand {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        match sides {
+            0 => DieRoll::new(0),
+            sides => DieRoll::new(rand::rng().random_range(1..=sides)),
+        }
+    }
+}
+
+impl RollerImpl for SecureRand {}
+
+/// a cryptographically secure RNG with a known seed, so its rolls can be replayed.
+/// this is what [`SecureRand`] becomes for audited rolls, see [`crate::dice::audit`].
+///
+/// spelled out so anyone can replay it: ChaCha20 keyed with the little-endian seed
+/// padded with zeroes, and each die takes `next_u32`s until one lands below
+/// the largest multiple of `sides`, then is that modulo `sides`, plus one
+#[derive(Debug, Clone)]
+pub struct SeededSecureRand(Box<ChaCha20Rng>);
+
+impl SeededSecureRand {
+    /// recorded with audited rolls, in case this ever changes
+    pub const ALGORITHM: &str = "chacha20";
+
+    pub fn with_seed(seed: u64) -> Self {
+        let mut key = [0; 32];
+        key[..8].copy_from_slice(&seed.to_le_bytes());
+        Self(Box::new(ChaCha20Rng::from_seed(key)))
+    }
+}
+
+impl Roller for SeededSecureRand {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        let sides = u32::from(sides);
+        if sides == 0 {
+            return DieRoll::new(0);
+        }
+
+        // the leftover at the top would make the low faces a little more likely
+        let fair = u32::MAX - u32::MAX % sides;
+        loop {
+            let value = self.0.next_u32();
+            if value < fair {
+                // less than `sides`, which came from a u8
+                return DieRoll::new((value % sides) as u8 + 1);
+            }
+        }
+    }
+}
+
+impl RollerImpl for SeededSecureRand {}
+
+/// always rolls a 1, the opposite of tyche's [`roller::Max`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Min;
+
+impl Roller for Min {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        DieRoll::new(sides.min(1))
+    }
+}
+
+impl RollerImpl for Min {}
+
+/// rolls a predetermined sequence of faces, starting over when it runs out.
+/// clones share their place in the sequence, so a test can script a whole conversation.
+/// faces are clamped to the sides of the die, and an empty script rolls like [`roller::Max`].
+#[derive(Debug, Clone)]
+pub struct Scripted {
+    faces: Arc<[u8]>,
+    position: Arc<AtomicUsize>,
+}
+
+impl Scripted {
+    pub fn new(faces: impl Into<Arc<[u8]>>) -> Self {
+        Self {
+            faces: faces.into(),
+            position: Arc::default(),
+        }
+    }
+}
+
+impl Roller for Scripted {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        let position = self.position.fetch_add(1, Ordering::Relaxed);
+        let face = match self.faces.len() {
+            0 => sides,
+            len => self.faces[position % len],
+        };
+        DieRoll::new(face.clamp(sides.min(1), sides))
+    }
+}
+
+impl RollerImpl for Scripted {}
+
+/// which roller to use, e.g. from the CLI as `secure`, `fast:42` or `scripted:20,1,15`
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RollerConfig {
+    /// tyche's [`roller::FastRand`]
+    #[default]
+    Fast,
+    /// [`roller::FastRand`] with a seed, for repeatable rolls
+    Seeded {
+        seed: u64,
+    },
+    /// [`SecureRand`], for production
+    Secure,
+    Max,
+    Min,
+    /// [`Scripted`], for tests
+    Scripted {
+        faces: Vec<u8>,
+    },
+}
+
+impl RollerConfig {
+    /// the same kind of roller, but seeded so its rolls can be replayed.
+    /// `max` and `min` don't need a seed, and `scripted` ignores it
+    pub fn seeded(&self, seed: u64) -> ConfiguredRoller {
+        match self {
+            Self::Fast | Self::Seeded { .. } => {
+                ConfiguredRoller::Fast(roller::FastRand::with_seed(seed))
+            }
+            Self::Secure => ConfiguredRoller::SeededSecure(SeededSecureRand::with_seed(seed)),
+            config => ConfiguredRoller::from(config),
+        }
+    }
+
+    /// the RNG behind [`RollerConfig::seeded`], if it uses one
+    pub fn seeded_algorithm(&self) -> Option<&'static str> {
+        match self {
+            // what fastrand uses
+            Self::Fast | Self::Seeded { .. } => Some("wyrand"),
+            Self::Secure => Some(SeededSecureRand::ALGORITHM),
+            Self::Max | Self::Min | Self::Scripted { .. } => None,
+        }
+    }
+}
+
+impl From<u64> for RollerConfig {
+    fn from(seed: u64) -> Self {
+        Self::Seeded { seed }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "unknown dice roller `{0}`. try `fast`, `fast:<seed>`, `secure`, `max`, `min` or `scripted:<faces>`"
+)]
+pub struct UnknownRoller(String);
+
+impl FromStr for RollerConfig {
+    type Err = UnknownRoller;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let unknown = || UnknownRoller(input.to_string());
+
+        let config = match input.trim().split_once(':') {
+            None => match input.trim() {
+                "fast" => Self::Fast,
+                "secure" => Self::Secure,
+                "max" => Self::Max,
+                "min" => Self::Min,
+                _ => return Err(unknown()),
+            },
+            Some(("fast", seed)) => Self::Seeded {
+                seed: seed.trim().parse().map_err(|_| unknown())?,
+            },
+            Some(("scripted", faces)) => Self::Scripted {
+                faces: faces
+                    .split(',')
+                    .map(|face| face.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| unknown())?,
+            },
+            Some(_) => return Err(unknown()),
+        };
+
+        Ok(config)
+    }
+}
+
+impl Display for RollerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fast => write!(f, "fast"),
+            Self::Seeded { seed } => write!(f, "fast:{}", seed),
+            Self::Secure => write!(f, "secure"),
+            Self::Max => write!(f, "max"),
+            Self::Min => write!(f, "min"),
+            Self::Scripted { faces } => {
+                let faces = faces
+                    .iter()
+                    .map(u8::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                write!(f, "scripted:{}", faces)
+            }
+        }
+    }
+}
+
+/// whichever roller a [`RollerConfig`] asked for
+#[derive(Debug, Clone)]
+pub enum ConfiguredRoller {
+    Fast(roller::FastRand),
+    Secure(SecureRand),
+    SeededSecure(SeededSecureRand),
+    Max(roller::Max),
+    Min(Min),
+    Scripted(Scripted),
+}
+
+impl Roller for ConfiguredRoller {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        match self {
+            Self::Fast(inner) => inner.roll_die(sides),
+            Self::Secure(inner) => inner.roll_die(sides),
+            Self::SeededSecure(inner) => inner.roll_die(sides),
+            Self::Max(inner) => inner.roll_die(sides),
+            Self::Min(inner) => inner.roll_die(sides),
+            Self::Scripted(inner) => inner.roll_die(sides),
+        }
+    }
+}
+
+impl RollerImpl for ConfiguredRoller {}
+
+impl From<&RollerConfig> for ConfiguredRoller {
+    fn from(config: &RollerConfig) -> Self {
+        match config {
+            RollerConfig::Fast => Self::Fast(roller::FastRand::default()),
+            RollerConfig::Seeded { seed } => Self::Fast(roller::FastRand::with_seed(*seed)),
+            RollerConfig::Secure => Self::Secure(SecureRand),
+            RollerConfig::Max => Self::Max(roller::Max),
+            RollerConfig::Min => Self::Min(Min),
+            RollerConfig::Scripted { faces } => Self::Scripted(Scripted::new(faces.as_slice())),
+        }
+    }
+}
+
+impl From<&RollerConfig> for DiceRoller<ConfiguredRoller> {
+    fn from(config: &RollerConfig) -> Self {
+        DiceRoller::from(ConfiguredRoller::from(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faces<T: RollerImpl>(roller: DiceRoller<T>, input: &str) -> Vec<u8> {
+        let mut roller = roller;
+        roller
+            .roll_dice(input)
+            .expect("should roll")
+            .dice()
+            .iter()
+            .map(|die| die.value)
+            .collect()
+    }
+
+    #[test]
+    fn scripted_rolls_follow_the_script() {
+        let roller = DiceRoller::from(Scripted::new([20, 1, 15]));
+
+        // clones pick up where the last one left off, and faces are clamped
+        assert_eq!(faces(roller.clone(), "2d20"), vec![20, 1]);
+        assert_eq!(faces(roller.clone(), "2d6"), vec![6, 6]);
+        assert_eq!(faces(roller, "1d20"), vec![1]);
+    }
+
+    #[test]
+    fn min_and_secure_rolls_stay_on_the_die() {
+        assert_eq!(faces(DiceRoller::from(Min), "3d6"), vec![1, 1, 1]);
+        assert!(
+            faces(DiceRoller::from(SecureRand), "50d6")
+                .iter()
+                .all(|face| (1..=6).contains(face))
+        );
+    }
+
+    #[test]
+    fn seeded_secure_rolls_are_pinned() {
+        // published audit seeds have to keep producing these, whatever rand does
+        assert_eq!(
+            faces(DiceRoller::from(SeededSecureRand::with_seed(42)), "10d20"),
+            vec![12, 10, 7, 2, 7, 14, 1, 13, 12, 14]
+        );
+    }
+
+    #[test]
+    fn seeded_rollers_repeat_themselves() {
+        for config in [RollerConfig::Secure, RollerConfig::Fast] {
+            let first = faces(DiceRoller::from(config.seeded(7)), "20d20");
+            let second = faces(DiceRoller::from(config.seeded(7)), "20d20");
+            assert_eq!(first, second, "`{config}` should repeat with the same seed");
+        }
+        assert_eq!(
+            faces(DiceRoller::from(RollerConfig::Max.seeded(7)), "2d6"),
+            vec![6, 6]
+        );
+    }
+
+    #[test]
+    fn roller_configs_are_parsed() {
+        for input in [
+            "fast",
+            "fast:42",
+            "secure",
+            "max",
+            "min",
+            "scripted:20,1,15",
+        ] {
+            let config: RollerConfig = input.parse().expect("should parse roller");
+            assert_eq!(config.to_string(), input);
+        }
+
+        assert_eq!(
+            "scripted:20, 1".parse::<RollerConfig>().ok(),
+            Some(RollerConfig::Scripted { faces: vec![20, 1] })
+        );
+        assert!("loaded".parse::<RollerConfig>().is_err());
+        assert!("fast:lucky".parse::<RollerConfig>().is_err());
+
+        let config: RollerConfig =
+            serde_json::from_str(r#"{"kind":"seeded","seed":7}"#).expect("should deserialize");
+        assert_eq!(config, RollerConfig::from(7));
+    }
+}
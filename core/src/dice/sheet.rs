@@ -0,0 +1,313 @@
+//! character sheets: named modifiers that can be used in rolls,
+//! e.g. `!ultron sheet set str 3 prof 2` then `!ultron roll 1d20+@str+@prof`.
+//! sheets can also be imported from a Foundry VTT (dnd5e) actor export.
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::{
+    User,
+    card::{Card, CardField, Color},
+    dice::DiceRollError,
+    error::Result,
+    store::Store,
+};
+
+/// modifiers by name, e.g. `str` => 3
+pub type CharacterSheet = BTreeMap<String, i32>;
+
+/// D&D ability scores, as they're named in Foundry
+const ABILITIES: &[&str] = &["str", "dex", "con", "int", "wis", "cha"];
+
+/// persistent character sheets for each [`User`]
+#[derive(Debug, Clone, Default)]
+pub struct CharacterSheets(Store<BTreeMap<String, CharacterSheet>>);
+
+impl From<Store<BTreeMap<String, CharacterSheet>>> for CharacterSheets {
+    fn from(store: Store<BTreeMap<String, CharacterSheet>>) -> Self {
+        Self(store)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SheetError {
+    #[error("`{0}` can't be used as a variable name")]
+    InvalidName(String),
+
+    #[error("`{name}` needs a whole number, not `{value}`")]
+    InvalidValue { name: String, value: String },
+
+    #[error("`{0}` is missing a value")]
+    MissingValue(String),
+
+    #[error("that doesn't look like a Foundry actor: {0}")]
+    FoundryImport(String),
+
+    #[error("failed to save sheet: {0}")]
+    Storage(#[from] crate::error::Error),
+}
+
+impl CharacterSheets {
+    pub async fn get(&self, user: &User) -> CharacterSheet {
+        self.0
+            .read(|sheets| match sheets.get(&user.to_string()) {
+                Some(sheet) => sheet.clone(),
+                None => CharacterSheet::new(),
+            })
+            .await
+    }
+
+    /// set some variables on a user's sheet, returning the updated sheet
+    pub async fn set(&self, user: &User, variables: CharacterSheet) -> Result<CharacterSheet> {
+        self.0
+            .update(|sheets| {
+                let sheet = sheets.entry(user.to_string()).or_default();
+                sheet.extend(variables);
+                sheet.clone()
+            })
+            .await
+    }
+
+    /// remove variables from a user's sheet, returning the ones that existed
+    pub async fn unset(&self, user: &User, names: &[&str]) -> Result<Vec<String>> {
+        self.0
+            .update(|sheets| {
+                let Some(sheet) = sheets.get_mut(&user.to_string()) else {
+                    return Vec::new();
+                };
+                names
+                    .iter()
+                    .filter(|name| sheet.remove(**name).is_some())
+                    .map(|name| name.to_string())
+                    .collect()
+            })
+            .await
+    }
+
+    /// merge the modifiers from a Foundry VTT actor export into a user's sheet
+    pub async fn import_foundry(
+        &self,
+        user: &User,
+        actor_json: &str,
+    ) -> std::result::Result<CharacterSheet, SheetError> {
+        let variables = parse_foundry_actor(actor_json)?;
+        Ok(self.set(user, variables).await?)
+    }
+
+    /// replace `@name` in `input` with the value from the user's sheet
+    pub async fn substitute(
+        &self,
+        user: &User,
+        input: &str,
+    ) -> std::result::Result<String, DiceRollError> {
+        let sheet = self.get(user).await;
+        substitute_variables(input, &sheet)
+    }
+}
+
+/// show a sheet with signed modifiers, e.g. `str: +3`
+pub fn sheet_card(user: &User, sheet: &CharacterSheet) -> Card {
+    Card::builder()
+        .title(format!("📜 {}'s sheet", user))
+        .fields(
+            sheet
+                .iter()
+                .map(|(name, value)| {
+                    CardField::inline(format!("@{}", name), format!("{:+}", value))
+                })
+                .collect(),
+        )
+        .footer("roll with them like `1d20+@str`")
+        .color(Color::ULTRON)
+        .build()
+}
+
+/// variable names look like identifiers
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// parse `str 3 prof 2` into pairs of names and values
+pub fn parse_assignments(input: &str) -> std::result::Result<CharacterSheet, SheetError> {
+    let mut words = input.split_whitespace();
+    let mut variables = CharacterSheet::new();
+
+    while let Some(name) = words.next() {
+        let name = name.trim_start_matches('@');
+        if !is_valid_name(name) {
+            return Err(SheetError::InvalidName(name.to_string()));
+        }
+
+        let value = words
+            .next()
+            .ok_or_else(|| SheetError::MissingValue(name.to_string()))?;
+        let value = value.parse().map_err(|_| SheetError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+        })?;
+
+        variables.insert(name.to_string(), value);
+    }
+
+    Ok(variables)
+}
+
+/// replace every `@name` with `(value)`.
+/// values are wrapped in parens so negative modifiers work, e.g. `1d20+@str`.
+pub fn substitute_variables(
+    input: &str,
+    sheet: &CharacterSheet,
+) -> std::result::Result<String, DiceRollError> {
+    substitute_with(input, |name| sheet.get(name).copied())
+}
+
+/// replace every `@name` with `0`,
+/// e.g. to check that a macro parses before anyone has a sheet
+pub fn blank_variables(input: &str) -> std::result::Result<String, DiceRollError> {
+    substitute_with(input, |_| Some(0))
+}
+
+fn substitute_with(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<i32>,
+) -> std::result::Result<String, DiceRollError> {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '@' {
+            output.push(c);
+            continue;
+        }
+
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+
+        let value = lookup(&name).ok_or_else(|| DiceRollError::UnknownVariable(name.clone()))?;
+        output.push_str(&format!("({})", value));
+    }
+
+    Ok(output)
+}
+
+/// pull ability modifiers, proficiency bonus and level out of a dnd5e actor.
+/// older Foundry versions keep everything under `data` instead of `system`.
+fn parse_foundry_actor(actor_json: &str) -> std::result::Result<CharacterSheet, SheetError> {
+    let actor: Value = serde_json::from_str(actor_json)
+        .map_err(|error| SheetError::FoundryImport(error.to_string()))?;
+
+    let system = actor
+        .get("system")
+        .or_else(|| actor.get("data"))
+        .ok_or_else(|| SheetError::FoundryImport("missing `system`".to_string()))?;
+
+    let mut sheet = CharacterSheet::new();
+
+    for ability in ABILITIES {
+        let score = system
+            .pointer(&format!("/abilities/{}/value", ability))
+            .and_then(Value::as_i64);
+        if let Some(score) = score {
+            // the usual (score - 10) / 2, rounded down
+            let modifier = (score - 10).div_euclid(2);
+            sheet.insert(ability.to_string(), modifier as i32);
+        }
+    }
+
+    let level = system
+        .pointer("/details/level")
+        .and_then(Value::as_i64)
+        .map(|level| level as i32);
+    if let Some(level) = level {
+        sheet.insert("level".to_string(), level);
+    }
+
+    // newer versions derive proficiency from the level instead of storing it
+    let prof = system
+        .pointer("/attributes/prof")
+        .and_then(Value::as_i64)
+        .map(|prof| prof as i32)
+        .or(level.map(|level| 2 + (level - 1).max(0) / 4));
+    if let Some(prof) = prof {
+        sheet.insert("prof".to_string(), prof);
+    }
+
+    if sheet.is_empty() {
+        return Err(SheetError::FoundryImport(
+            "no abilities or level found".to_string(),
+        ));
+    }
+
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables_are_substituted() {
+        let sheet = parse_assignments("str 3 @prof 2 dex -1").expect("should parse sheet");
+
+        assert_eq!(
+            substitute_variables("1d20+@str+@prof", &sheet).expect("should substitute"),
+            "1d20+(3)+(2)"
+        );
+        assert_eq!(
+            substitute_variables("1d20+@dex", &sheet).expect("should substitute"),
+            "1d20+(-1)"
+        );
+        assert!(matches!(
+            substitute_variables("1d20+@wis", &sheet),
+            Err(DiceRollError::UnknownVariable(name)) if name == "wis"
+        ));
+    }
+
+    #[test]
+    fn bad_assignments_are_rejected() {
+        assert!(matches!(
+            parse_assignments("str"),
+            Err(SheetError::MissingValue(_))
+        ));
+        assert!(matches!(
+            parse_assignments("str three"),
+            Err(SheetError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse_assignments("3 str"),
+            Err(SheetError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn foundry_actors_can_be_imported() {
+        let actor = r#"{
+            "name": "Grog",
+            "type": "character",
+            "system": {
+                "abilities": {
+                    "str": { "value": 18 },
+                    "dex": { "value": 9 },
+                    "con": { "value": 16 },
+                    "int": { "value": 6 },
+                    "wis": { "value": 10 },
+                    "cha": { "value": 11 }
+                },
+                "details": { "level": 5 }
+            }
+        }"#;
+
+        let sheet = parse_foundry_actor(actor).expect("should import actor");
+        assert_eq!(sheet.get("str"), Some(&4));
+        assert_eq!(sheet.get("dex"), Some(&-1));
+        assert_eq!(sheet.get("int"), Some(&-2));
+        assert_eq!(sheet.get("level"), Some(&5));
+        assert_eq!(sheet.get("prof"), Some(&3));
+
+        assert!(parse_foundry_actor(r#"{ "name": "nobody" }"#).is_err());
+    }
+}
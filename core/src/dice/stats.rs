@@ -0,0 +1,462 @@
+//! probability distributions for dice expressions, e.g. `!ultron roll stats 2d20K1 >= 15`.
+//! small expressions are enumerated exactly,
+//! anything with too many outcomes (or rerolling or exploding dice) is sampled instead.
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use tyche::{
+    Expr,
+    dice::{DieRoll, Roller, modifier::Modifier, roller::FastRand},
+};
+
+use crate::{
+    card::{Card, CardField, CodeBlock, Color},
+    dice::{DiceRollError, limits::DiceLimits},
+};
+
+/// give up on exact enumeration after this many outcomes
+const MAX_EXACT_OUTCOMES: usize = 50_000;
+
+/// how many times to roll for a Monte Carlo estimate
+const MONTE_CARLO_SAMPLES: u32 = 20_000;
+
+/// histograms are bucketed to at most this many rows
+const HISTOGRAM_ROWS: i32 = 20;
+
+/// width of the longest histogram bar
+const HISTOGRAM_WIDTH: f64 = 24.0;
+
+/// how the distribution was computed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// every possible outcome was enumerated
+    Exact { outcomes: usize },
+    /// the expression was rolled a bunch of times
+    MonteCarlo { samples: u32 },
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Method::Exact { outcomes } => write!(f, "exact, {} outcomes", outcomes),
+            Method::MonteCarlo { samples } => write!(f, "monte carlo, {} samples", samples),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    AtLeast,
+    AtMost,
+    GreaterThan,
+    LessThan,
+    Equal,
+}
+
+impl Comparison {
+    /// longest operators first so `>=` isn't mistaken for `>`
+    pub(crate) const OPERATORS: [(&str, Comparison); 5] = [
+        (">=", Comparison::AtLeast),
+        ("<=", Comparison::AtMost),
+        (">", Comparison::GreaterThan),
+        ("<", Comparison::LessThan),
+        ("=", Comparison::Equal),
+    ];
+
+    pub fn matches(&self, total: i32, target: i32) -> bool {
+        match self {
+            Comparison::AtLeast => total >= target,
+            Comparison::AtMost => total <= target,
+            Comparison::GreaterThan => total > target,
+            Comparison::LessThan => total < target,
+            Comparison::Equal => total == target,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            Comparison::AtLeast => ">=",
+            Comparison::AtMost => "<=",
+            Comparison::GreaterThan => ">",
+            Comparison::LessThan => "<",
+            Comparison::Equal => "=",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+/// a total to beat, e.g. `>= 15`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub comparison: Comparison,
+    pub value: i32,
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.comparison, self.value)
+    }
+}
+
+/// split `4d6k3 >= 15` into the expression and the target.
+/// the operator has to come after whitespace,
+/// so modifiers like `4d6rr<3` are left alone.
+pub fn split_target(input: &str) -> Result<(&str, Option<Target>), DiceRollError> {
+    let found = Comparison::OPERATORS
+        .iter()
+        .filter_map(|(operator, comparison)| {
+            input
+                .rfind(&format!(" {}", operator))
+                .map(|index| (index, operator.len() + 1, *comparison))
+        })
+        // `>=` and `>` match at the same index, the longer one wins
+        .max_by_key(|(index, len, _)| (*index, *len));
+
+    let Some((index, len, comparison)) = found else {
+        return Ok((input.trim(), None));
+    };
+
+    let (expression, target) = input.split_at(index);
+    let target = target[len..].trim();
+    let value = target
+        .parse()
+        .map_err(|_| DiceRollError::InvalidTarget(target.to_string()))?;
+
+    Ok((expression.trim(), Some(Target { comparison, value })))
+}
+
+/// the distribution of totals for a dice expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollStats {
+    pub expression: String,
+    pub method: Method,
+    /// probability of each total
+    pub distribution: BTreeMap<i32, f64>,
+    pub target: Option<Target>,
+}
+
+impl RollStats {
+    /// parse `input` (optionally followed by a [`Target`]) and compute its distribution
+    pub fn compute(input: &str, limits: &DiceLimits) -> Result<Self, DiceRollError> {
+        let (expression, target) = split_target(input)?;
+        let expr = Expr::from_str(expression)?;
+        limits.check_expr(&expr, 1)?;
+
+        let (method, distribution) = match exact_distribution(&expr)? {
+            Some((outcomes, distribution)) => (Method::Exact { outcomes }, distribution),
+            None => (
+                Method::MonteCarlo {
+                    samples: MONTE_CARLO_SAMPLES,
+                },
+                sampled_distribution(&expr, &mut FastRand::default(), MONTE_CARLO_SAMPLES)?,
+            ),
+        };
+
+        Ok(Self {
+            expression: expression.to_string(),
+            method,
+            distribution,
+            target,
+        })
+    }
+
+    pub fn min(&self) -> i32 {
+        self.distribution.keys().next().copied().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> i32 {
+        self.distribution
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.distribution
+            .iter()
+            .map(|(total, probability)| f64::from(*total) * probability)
+            .sum()
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        self.distribution
+            .iter()
+            .map(|(total, probability)| (f64::from(*total) - mean).powi(2) * probability)
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// the chance of rolling a total that meets `target`
+    pub fn chance(&self, target: &Target) -> f64 {
+        self.distribution
+            .iter()
+            .filter(|(total, _)| target.comparison.matches(**total, target.value))
+            .map(|(_, probability)| probability)
+            .sum()
+    }
+
+    /// an ASCII bar chart of the distribution,
+    /// with neighbouring totals grouped together if there are lots of them
+    pub fn histogram(&self) -> String {
+        let (min, max) = (self.min(), self.max());
+        let bucket_size = ((max - min) / HISTOGRAM_ROWS + 1).max(1);
+
+        let mut buckets: BTreeMap<i32, f64> = BTreeMap::new();
+        for (total, probability) in &self.distribution {
+            let bucket = min + (total - min) / bucket_size * bucket_size;
+            *buckets.entry(bucket).or_default() += probability;
+        }
+
+        let label = |start: i32| match bucket_size {
+            1 => start.to_string(),
+            _ => format!("{}-{}", start, (start + bucket_size - 1).min(max)),
+        };
+        let label_width = buckets.keys().map(|start| label(*start).len()).max();
+        let label_width = label_width.unwrap_or_default();
+        let tallest = buckets.values().copied().fold(0.0, f64::max);
+
+        buckets
+            .iter()
+            .map(|(start, probability)| {
+                let bar = "█".repeat((probability / tallest * HISTOGRAM_WIDTH).round() as usize);
+                format!(
+                    "{:>width$} {:<bar_width$} {:>5.1}%",
+                    label(*start),
+                    bar,
+                    probability * 100.0,
+                    width = label_width,
+                    bar_width = HISTOGRAM_WIDTH as usize,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl From<&RollStats> for Card {
+    fn from(stats: &RollStats) -> Self {
+        let mut fields = vec![
+            CardField::inline("min", stats.min().to_string()),
+            CardField::inline("max", stats.max().to_string()),
+            CardField::inline("mean", format!("{:.2}", stats.mean())),
+            CardField::inline("std dev", format!("{:.2}", stats.std_dev())),
+        ];
+
+        if let Some(target) = &stats.target {
+            fields.push(CardField::inline(
+                format!("chance {}", target),
+                format!("**{:.1}%**", stats.chance(target) * 100.0),
+            ));
+        }
+
+        Card::builder()
+            .title(format!("📊 {}", stats.expression))
+            .fields(fields)
+            .code_blocks(vec![
+                CodeBlock::new(stats.histogram()).with_language("text"),
+            ])
+            .footer(stats.method.to_string())
+            .color(Color::INFO)
+            .build()
+    }
+}
+
+/// the number of distinct outcomes and the probability of each total
+type ExactDistribution = (usize, BTreeMap<i32, f64>);
+
+/// replays a fixed sequence of die values, then rolls 1s.
+/// records every die it's asked for so the next sequence can be worked out.
+#[derive(Debug, Default)]
+struct Enumerator {
+    prefix: Vec<u8>,
+    /// (value, sides) for each die rolled
+    trace: Vec<(u8, u8)>,
+}
+
+impl Roller for Enumerator {
+    fn roll_die(&mut self, sides: u8) -> DieRoll {
+        let val = self.prefix.get(self.trace.len()).copied().unwrap_or(1);
+        self.trace.push((val, sides));
+        DieRoll::new(val)
+    }
+}
+
+/// walk every sequence of die values like an odometer,
+/// weighting each total by the chance of rolling its sequence.
+/// returns `None` if there are too many outcomes.
+fn exact_distribution(expr: &Expr) -> Result<Option<ExactDistribution>, DiceRollError> {
+    // rolling 1s forever never ends a reroll like `rr<3`,
+    // and the number of dice isn't bounded anyway
+    if rolls_again(expr) {
+        return Ok(None);
+    }
+
+    let mut distribution = BTreeMap::new();
+    let mut prefix = Vec::new();
+
+    for outcomes in 1..=MAX_EXACT_OUTCOMES {
+        let mut enumerator = Enumerator {
+            prefix,
+            trace: Vec::new(),
+        };
+        let total = expr.eval(&mut enumerator)?.calc()?;
+        let probability: f64 = enumerator
+            .trace
+            .iter()
+            .map(|(_, sides)| 1.0 / f64::from(*sides))
+            .product();
+        *distribution.entry(total).or_default() += probability;
+
+        // bump the last die that isn't maxed out, and forget everything after it
+        let mut trace = enumerator.trace;
+        loop {
+            match trace.pop() {
+                Some((val, sides)) if val < sides => {
+                    prefix = trace.iter().map(|(val, _)| *val).collect();
+                    prefix.push(val + 1);
+                    break;
+                }
+                Some(_) => continue,
+                None => return Ok(Some((outcomes, distribution))),
+            }
+        }
+    }
+
+    tracing::debug!("too many outcomes for an exact distribution");
+    Ok(None)
+}
+
+/// whether any dice in `expr` reroll or explode
+fn rolls_again(expr: &Expr) -> bool {
+    match expr {
+        Expr::Dice(dice) => dice
+            .modifiers
+            .iter()
+            .any(|modifier| matches!(modifier, Modifier::Reroll { .. } | Modifier::Explode { .. })),
+        Expr::Neg(inner) => rolls_again(inner),
+        Expr::Add(left, right)
+        | Expr::Sub(left, right)
+        | Expr::Mul(left, right)
+        | Expr::DivDown(left, right)
+        | Expr::DivUp(left, right) => rolls_again(left) || rolls_again(right),
+        _ => false,
+    }
+}
+
+fn sampled_distribution(
+    expr: &Expr,
+    roller: &mut impl Roller,
+    samples: u32,
+) -> Result<BTreeMap<i32, f64>, DiceRollError> {
+    let mut counts: BTreeMap<i32, u32> = BTreeMap::new();
+    for _ in 0..samples {
+        let total = expr.eval(roller)?.calc()?;
+        *counts.entry(total).or_default() += 1;
+    }
+
+    Ok(counts
+        .into_iter()
+        .map(|(total, count)| (total, f64::from(count) / f64::from(samples)))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn targets_are_split_from_expressions() {
+        assert_eq!(split_target("4d6k3").expect("no target"), ("4d6k3", None));
+        assert_eq!(
+            split_target("4d6k3 >= 15").expect("should parse target"),
+            (
+                "4d6k3",
+                Some(Target {
+                    comparison: Comparison::AtLeast,
+                    value: 15
+                })
+            )
+        );
+        assert_eq!(
+            split_target("4d6rr<3 < 10").expect("should parse target"),
+            (
+                "4d6rr<3",
+                Some(Target {
+                    comparison: Comparison::LessThan,
+                    value: 10
+                })
+            )
+        );
+        assert!(split_target("d20 >= lots").is_err());
+    }
+
+    #[test]
+    fn exact_stats_for_2d6() {
+        let stats =
+            RollStats::compute("2d6 >= 7", &DiceLimits::default()).expect("should compute stats");
+
+        assert_eq!(stats.method, Method::Exact { outcomes: 36 });
+        assert_eq!(stats.min(), 2);
+        assert_eq!(stats.max(), 12);
+        assert_close(stats.mean(), 7.0);
+        assert_close(stats.std_dev(), (35.0_f64 / 6.0).sqrt());
+        let target = stats.target.expect("should have a target");
+        assert_close(stats.chance(&target), 21.0 / 36.0);
+    }
+
+    #[test]
+    fn advantage_versus_a_bonus() {
+        let advantage =
+            RollStats::compute("2d20K1", &DiceLimits::default()).expect("should compute stats");
+        let bonus =
+            RollStats::compute("d20+5", &DiceLimits::default()).expect("should compute stats");
+
+        // the eternal debate, settled
+        assert_close(advantage.mean(), 13.825);
+        assert_close(bonus.mean(), 15.5);
+    }
+
+    #[test]
+    fn big_expressions_are_sampled() {
+        let stats =
+            RollStats::compute("10d20", &DiceLimits::default()).expect("should compute stats");
+
+        assert_eq!(
+            stats.method,
+            Method::MonteCarlo {
+                samples: MONTE_CARLO_SAMPLES
+            }
+        );
+        assert!((stats.mean() - 105.0).abs() < 2.0);
+        assert!(stats.histogram().lines().count() <= HISTOGRAM_ROWS as usize + 1);
+    }
+
+    #[test]
+    fn rerolls_and_explosions_are_sampled() {
+        for input in ["4d6rr<3", "2d6x", "d20 + 1d4rr1"] {
+            let stats =
+                RollStats::compute(input, &DiceLimits::default()).expect("should compute stats");
+            assert_eq!(
+                stats.method,
+                Method::MonteCarlo {
+                    samples: MONTE_CARLO_SAMPLES
+                },
+                "{input} should be sampled"
+            );
+        }
+
+        let stats =
+            RollStats::compute("4d6rr<3", &DiceLimits::default()).expect("should compute stats");
+        assert!(stats.min() >= 12);
+    }
+}
@@ -19,6 +19,12 @@ pub enum Error {
         path: PathBuf,
     },
 
+    #[error("failed to write file {path:?}: {source}")]
+    FileWrite {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
     #[error("failed to parse TOML file {path:?}: {source}")]
     TomlFileParse {
         source: toml::de::Error,
@@ -28,6 +34,15 @@ pub enum Error {
     #[error("failed to parse TOML: {source}")]
     TomlParse { source: toml::de::Error },
 
+    #[error("failed to parse JSON file {path:?}: {source}")]
+    JsonFileParse {
+        source: serde_json::Error,
+        path: PathBuf,
+    },
+
+    #[error("failed to serialize JSON: {source}")]
+    JsonSerialize { source: serde_json::Error },
+
     #[error("failed to generate OpenAPI doc")]
     OpenApiDocGeneration,
 
@@ -10,7 +10,7 @@ use crate::{
     Channel, Response, User,
     chatbot::ChatInput,
     command::{CommandConsumer, CommandParseError},
-    dice::DiceRoller,
+    dice::{DiceRoller, rollers::RollerConfig},
     nlp::{AgentError, ChatAgent, response::MessageParts},
 };
 
@@ -22,9 +22,25 @@ pub enum EventError {
     CommandParse(#[from] CommandParseError),
     #[error("failed to parse dice roll from input: {0}")]
     DiceRollParse(#[from] crate::dice::DiceRollError),
+    #[error("dice macro error: {0}")]
+    DiceMacro(#[from] crate::dice::macros::DiceMacroError),
+    #[error("character sheet error: {0}")]
+    Sheet(#[from] crate::dice::sheet::SheetError),
+    #[error("initiative error: {0}")]
+    Initiative(#[from] crate::initiative::InitiativeError),
+    #[error("roll audit error: {0}")]
+    Audit(#[from] crate::dice::audit::AuditError),
+    #[error("copypasta error: {0}")]
+    Copypasta(#[from] crate::copypasta::CopypastaError),
 
     #[error("agent error: {0}")]
     Agent(#[from] Box<AgentError>),
+
+    #[error("storage error: {0}")]
+    Storage(#[from] crate::error::Error),
+
+    #[error("background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
@@ -49,6 +65,11 @@ pub struct Event {
     pub channel: Channel,
     #[builder(default)]
     pub timestamp: EventTimestamp,
+    /// the platform-qualified ID of `user`, if the platform vouches for it.
+    /// see [`ChatInput::identity`]
+    #[builder(into)]
+    #[serde(default)]
+    pub identity: Option<String>,
 }
 
 /// a wrapper around [`OffsetDateTime`] to represent event timestamps
@@ -85,6 +106,7 @@ impl Event {
             .channel(chat_input.channel)
             .content(MessageParts::raw(content))
             .event_type(event_type)
+            .maybe_identity(chat_input.identity.clone())
             .build();
 
         Ok(event)
@@ -95,6 +117,7 @@ impl Event {
 pub struct EventProcessor {
     events: EventLog,
     consumers: EventConsumers,
+    listeners: Vec<Arc<dyn EventListener>>,
 }
 
 /// a collection of [`EventConsumer`]s
@@ -134,6 +157,13 @@ pub trait EventConsumer: std::fmt::Debug + Send + Sync + 'static {
     }
 }
 
+/// gets a look at every event and its responses once they're processed,
+/// e.g. to mirror them somewhere else like [`crate::foundry::FoundryBridge`]
+#[async_trait::async_trait]
+pub trait EventListener: std::fmt::Debug + Send + Sync + 'static {
+    async fn on_responses(&self, event: &Event, responses: &[Response]);
+}
+
 #[cfg(test)]
 impl EventProcessor {
     pub async fn test() -> Self {
@@ -157,14 +187,16 @@ impl EventProcessor {
         Self {
             events,
             consumers: EventConsumers::default(),
+            listeners: Vec::new(),
         }
     }
 
-    pub fn with_rng<TAgent>(chat_agent: TAgent, seed: u64) -> Self
+    /// roll with whichever roller `rng` picks, e.g. a seed or a [`RollerConfig`]
+    pub fn with_rng<TAgent>(chat_agent: TAgent, rng: impl Into<RollerConfig>) -> Self
     where
         TAgent: ChatAgent + 'static,
     {
-        let dice_roller = DiceRoller::with_rng(seed);
+        let dice_roller = DiceRoller::from(&rng.into());
         let command_consumer = CommandConsumer::new(dice_roller);
         Self::new()
             .with_consumer(command_consumer)
@@ -184,6 +216,14 @@ impl EventProcessor {
         self
     }
 
+    pub fn with_listener<T>(mut self, listener: T) -> Self
+    where
+        T: EventListener + 'static,
+    {
+        self.listeners.push(Arc::new(listener));
+        self
+    }
+
     pub async fn process(&self, event: impl Into<Event>) -> Result<Vec<Response>, EventError> {
         let event = event.into();
         tracing::debug!(?event, "processing event");
@@ -193,18 +233,30 @@ impl EventProcessor {
         let event_results: Vec<EventResult> =
             Box::pin(self.consumers.propagate_event(&event).collect()).await;
 
-        let responses: Vec<Response> = event_results
-            .into_iter()
-            .filter_map(|response| match response {
-                Ok(resp) => Some(resp),
+        let mut responses = Vec::with_capacity(event_results.len());
+        let mut errors = Vec::new();
+        for result in event_results {
+            match result {
+                Ok(response) => responses.push(response),
                 Err(error) => {
                     tracing::error!(%error, "error processing event");
-                    None
+                    errors.push(error);
                 }
-            })
-            .collect();
+            }
+        }
+
+        for listener in &self.listeners {
+            listener.on_responses(&event, &responses).await;
+        }
 
-        Ok(responses)
+        // nobody else had anything to say, so the user should hear what went wrong
+        let ignored = responses
+            .iter()
+            .all(|response| matches!(response, Response::Ignored));
+        match errors.into_iter().next() {
+            Some(error) if ignored => Err(error),
+            _ => Ok(responses),
+        }
     }
 }
 
@@ -271,6 +323,49 @@ mod tests {
         assert_eq!(responses[0], Response::PlainChat("hello".to_string()));
     }
 
+    #[tokio::test]
+    async fn errors_reach_the_user() {
+        let chat_input = ChatInput::anonymous("!ultron roll 250d6", Channel::Debug);
+        let event = Event::new(&chat_input, EventType::Plain).expect("should parse chat input");
+        let processor =
+            EventProcessor::new().with_consumer(CommandConsumer::new(DiceRoller::max()));
+
+        let result = processor.process(event).await;
+        assert!(
+            matches!(
+                result,
+                Err(EventError::DiceRollParse(
+                    crate::dice::DiceRollError::LimitExceeded { .. }
+                ))
+            ),
+            "{result:?}"
+        );
+
+        // same name as an editor, but nothing to prove it
+        let chat_input = ChatInput::builder()
+            .user("alice")
+            .content("!ultron pasta add bob bob was here")
+            .channel(Channel::Debug)
+            .build();
+        let event = Event::new(&chat_input, EventType::Plain).expect("should parse chat input");
+        let processor = EventProcessor::new().with_consumer(
+            CommandConsumer::new(DiceRoller::max()).with_copypastas(
+                crate::copypasta::Copypastas::default().with_editors(["discord:alice"]),
+            ),
+        );
+
+        let result = processor.process(event).await;
+        assert!(
+            matches!(
+                result,
+                Err(EventError::Copypasta(
+                    crate::copypasta::CopypastaError::NotAnEditor(_)
+                ))
+            ),
+            "{result:?}"
+        );
+    }
+
     #[test]
     fn strip_prefix() {
         let chat_input: ChatInput = ChatInput::anonymous("!ultron hello", Channel::Debug);
@@ -0,0 +1,469 @@
+//! a bridge between #dnd and a Foundry VTT world's chat.
+//!
+//! Foundry doesn't have an API of its own, so this talks to a small module in the world
+//! that shares a token with Ultron and:
+//! - accepts `POST <url>/chat` with a [`FoundryChatMessage`] and posts it to the world's chat
+//! - sends every chat message and roll to Ultron's [`FoundryRoute`] as a [`FoundryChatMessage`]
+//!
+//! Ultron's responses in [`Channel::Dnd`] are forwarded to Foundry by [`FoundryBridge`],
+//! and Foundry chat is relayed to #dnd and processed like any other event.
+use std::time::Duration;
+
+use axum::{
+    extract::{Json, State},
+    http::{HeaderMap, header},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Channel, Response, User,
+    card::{Card, CardField, Color},
+    chatbot::{ChatBot, ChatInput},
+    event_processor::{Event, EventError, EventListener, EventType},
+    http_server::{
+        AppState, HttpRoute, OpenApiTag, ServerError, ServerResult, handle_event_response,
+    },
+};
+
+/// who Ultron's messages come from in Foundry.
+/// messages from this speaker aren't relayed back, so they don't echo forever.
+pub const ULTRON_SPEAKER: &str = "Ultron";
+
+/// how long to wait on the Foundry module before giving up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// a chat message going to or coming from Foundry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct FoundryChatMessage {
+    /// who said it, e.g. a player or their character
+    pub speaker: String,
+    /// the message, as markdown
+    pub content: String,
+    /// a heading shown above the message, like a roll's title
+    #[serde(default)]
+    pub flavor: Option<String>,
+    #[serde(default)]
+    pub roll: Option<FoundryRoll>,
+}
+
+/// a roll made in Foundry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct FoundryRoll {
+    /// e.g. `1d20 + 5`
+    pub formula: String,
+    pub total: i32,
+}
+
+impl From<&FoundryChatMessage> for Card {
+    fn from(message: &FoundryChatMessage) -> Self {
+        let (title, fields) = match &message.roll {
+            Some(roll) => (
+                format!("🎲 {} rolled in Foundry", message.speaker),
+                vec![
+                    CardField::inline("formula", format!("`{}`", roll.formula)),
+                    CardField::inline("total", format!("**{}**", roll.total)),
+                ],
+            ),
+            None => (format!("🗺️ {} in Foundry", message.speaker), vec![]),
+        };
+
+        Card::builder()
+            .title(title)
+            .maybe_description((!message.content.is_empty()).then(|| message.content.clone()))
+            .fields(fields)
+            .maybe_footer(message.flavor.clone())
+            .color(Color::INFO)
+            .build()
+    }
+}
+
+impl FoundryChatMessage {
+    /// something Ultron said, e.g. a roll card
+    fn from_response(response: &Response) -> Option<Self> {
+        let (flavor, content) = match response {
+            Response::PlainChat(message) => (None, message.clone()),
+            Response::Bot(message) => (None, message.render_without_thinking_parts()),
+            // the title goes in the flavor, where Foundry puts a roll's heading
+            Response::Card(card) => (
+                card.title.clone(),
+                Card {
+                    title: None,
+                    ..card.clone()
+                }
+                .to_markdown(),
+            ),
+            // reactions are for the message that set them off, which Foundry never saw
+            Response::Reaction(_) | Response::Ignored => return None,
+        };
+
+        Some(Self {
+            speaker: ULTRON_SPEAKER.to_string(),
+            content,
+            flavor,
+            roll: None,
+        })
+    }
+
+    /// what goes in the event log, so the language model knows what happened at the table
+    fn event_content(&self) -> String {
+        match &self.roll {
+            Some(roll) => format!(
+                "rolled `{}` = {} {}",
+                roll.formula, roll.total, self.content
+            ),
+            None => self.content.clone(),
+        }
+        .trim()
+        .to_string()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FoundryError {
+    #[error("the Foundry bridge isn't set up")]
+    NotConfigured,
+
+    #[error("wrong or missing Foundry bridge token")]
+    Unauthorized,
+
+    #[error("failed to reach Foundry: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("failed to serialize message for Foundry: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// talks to the Foundry module
+#[derive(Debug, Clone)]
+pub struct FoundryBridge {
+    client: reqwest::Client,
+    /// where the Foundry module listens, e.g. `http://localhost:30000/modules/ultron-bridge`
+    url: String,
+    /// shared with the Foundry module, both ways
+    token: String,
+}
+
+impl FoundryBridge {
+    pub fn new(url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.into(),
+            token: token.into(),
+        }
+    }
+
+    /// post a message to the world's chat
+    pub async fn send(&self, message: &FoundryChatMessage) -> Result<(), FoundryError> {
+        self.client
+            .post(format!("{}/chat", self.url.trim_end_matches('/')))
+            .bearer_auth(&self.token)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(message)?)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// check the `Authorization` header on a request from the Foundry module
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), FoundryError> {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token == Some(self.token.as_str()) {
+            true => Ok(()),
+            false => Err(FoundryError::Unauthorized),
+        }
+    }
+}
+
+/// forward Ultron's responses in #dnd to Foundry
+#[async_trait::async_trait]
+impl EventListener for FoundryBridge {
+    async fn on_responses(&self, event: &Event, responses: &[Response]) {
+        if event.channel != Channel::Dnd {
+            return;
+        }
+
+        let messages: Vec<_> = responses
+            .iter()
+            .filter_map(FoundryChatMessage::from_response)
+            .collect();
+        if messages.is_empty() {
+            return;
+        }
+
+        // Foundry can take up to REQUEST_TIMEOUT per message, don't hold up the event in the meantime
+        let bridge = self.clone();
+        tokio::spawn(async move {
+            for message in messages {
+                if let Err(error) = bridge.send(&message).await {
+                    tracing::warn!(%error, "failed to forward message to Foundry");
+                }
+            }
+        });
+    }
+}
+
+pub struct FoundryRoute;
+
+impl HttpRoute for FoundryRoute {
+    const PATH: &'static str = "/foundry/chat";
+}
+
+/// a chat message or roll from the Foundry module.
+/// it's relayed to #dnd, then processed like a message from Discord.
+#[utoipa::path(
+    post,
+    path = FoundryRoute::PATH,
+    request_body = FoundryChatMessage,
+    responses(
+        (status = OK, description = "relayed, with Ultron's responses"),
+        (status = UNAUTHORIZED, description = "wrong or missing bridge token"),
+        (status = NOT_FOUND, description = "the Foundry bridge isn't set up")
+    ),
+    tag = OpenApiTag::BotCommand.as_str(),
+)]
+pub async fn webhook_handler<TBot>(
+    State(state): State<AppState<TBot>>,
+    headers: HeaderMap,
+    Json(message): Json<FoundryChatMessage>,
+) -> ServerResult<Json<Vec<String>>>
+where
+    TBot: ChatBot + 'static,
+{
+    let bridge = state.foundry.as_ref().ok_or(FoundryError::NotConfigured)?;
+    bridge.authorize(&headers)?;
+
+    tracing::debug!(?message, "received message from Foundry");
+
+    let user = User::from(message.speaker.clone());
+    if user == User::Ultron {
+        return Ok(Json(vec![]));
+    }
+
+    state
+        .chat_bot
+        .send_card(Channel::Dnd, &Card::from(&message))
+        .await
+        .map_err(|error| ServerError::Internal(error.into()))?;
+
+    let chat_input = ChatInput::builder()
+        .user(user)
+        .content(message.event_content())
+        .channel(Channel::Dnd)
+        .build();
+    let event = Event::new(&chat_input, EventType::Plain)
+        .map_err(|error| Box::new(EventError::from(error)))?;
+
+    let responses = Box::pin(state.event_processor.process(event))
+        .await
+        .map_err(Box::new)?;
+
+    let mut results = Vec::with_capacity(responses.len());
+    for response in responses {
+        results.push(handle_event_response(state.chat_bot.as_ref(), Channel::Dnd, response).await?);
+    }
+
+    Ok(Json(results))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{Router, http::StatusCode, routing::post};
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        command::CommandConsumer, dice::DiceRoller, error::TestError,
+        event_processor::EventProcessor,
+    };
+
+    const TOKEN: &str = "hunter2";
+
+    type Received = Arc<Mutex<Vec<FoundryChatMessage>>>;
+
+    async fn receive(
+        State(received): State<Received>,
+        headers: HeaderMap,
+        Json(message): Json<FoundryChatMessage>,
+    ) -> StatusCode {
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok());
+        if token != Some(format!("Bearer {}", TOKEN).as_str()) {
+            return StatusCode::UNAUTHORIZED;
+        }
+
+        received.lock().await.push(message);
+        StatusCode::OK
+    }
+
+    /// stands in for the Foundry module, keeping whatever it's sent
+    async fn stand_in_foundry() -> (String, Received) {
+        let received = Received::default();
+
+        let router = Router::new()
+            .route("/chat", post(receive))
+            .with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("should bind a port");
+        let url = format!(
+            "http://{}",
+            listener.local_addr().expect("should have an address")
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        (url, received)
+    }
+
+    /// messages are forwarded in the background, so give them a moment to arrive
+    async fn wait_for(received: &Received, count: usize) -> Vec<FoundryChatMessage> {
+        for _ in 0..50 {
+            let messages = received.lock().await;
+            if messages.len() >= count {
+                return messages.clone();
+            }
+            drop(messages);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        received.lock().await.clone()
+    }
+
+    #[derive(Debug, Clone, Default)]
+    struct RecordingBot(Arc<Mutex<Vec<(Channel, String)>>>);
+
+    impl ChatBot for RecordingBot {
+        type Error = TestError;
+
+        async fn send_message(&self, channel: Channel, message: &str) -> Result<(), Self::Error> {
+            self.0.lock().await.push((channel, message.to_string()));
+            Ok(())
+        }
+    }
+
+    fn command(channel: Channel, content: &str) -> Event {
+        Event::builder()
+            .user(User::from("bob"))
+            .content(content.to_string())
+            .event_type(EventType::Command)
+            .channel(channel)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn dnd_responses_are_forwarded_to_foundry() {
+        let (url, received) = stand_in_foundry().await;
+        let processor = EventProcessor::new()
+            .with_consumer(CommandConsumer::new(DiceRoller::max()))
+            .with_listener(FoundryBridge::new(url, TOKEN));
+
+        processor
+            .process(command(Channel::Debug, "roll d20"))
+            .await
+            .expect("should process event");
+        assert!(received.lock().await.is_empty());
+
+        processor
+            .process(command(Channel::Dnd, "roll d20+2"))
+            .await
+            .expect("should process event");
+
+        let received = wait_for(&received, 1).await;
+        let [message] = received.as_slice() else {
+            panic!("should forward exactly one message, got {:?}", received);
+        };
+        assert_eq!(message.speaker, ULTRON_SPEAKER);
+        assert_eq!(message.flavor.as_deref(), Some("💥 critical hit!"));
+        assert!(message.content.contains("**22**"));
+    }
+
+    #[tokio::test]
+    async fn foundry_rolls_are_relayed_to_dnd() {
+        let (url, received) = stand_in_foundry().await;
+        let bridge = FoundryBridge::new(url, TOKEN);
+        let bot = RecordingBot::default();
+        let state = AppState::builder()
+            .event_processor(Arc::new(
+                EventProcessor::new()
+                    .with_consumer(CommandConsumer::new(DiceRoller::max()))
+                    .with_listener(bridge.clone()),
+            ))
+            .chat_bot(Arc::new(bot.clone()))
+            .foundry(bridge)
+            .build();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", TOKEN)
+                .parse()
+                .expect("should be a valid header"),
+        );
+        let roll = FoundryChatMessage {
+            speaker: "Gandalf".to_string(),
+            content: String::new(),
+            flavor: Some("Wisdom save".to_string()),
+            roll: Some(FoundryRoll {
+                formula: "1d20 + 5".to_string(),
+                total: 17,
+            }),
+        };
+
+        assert!(matches!(
+            webhook_handler(State(state.clone()), HeaderMap::new(), Json(roll.clone())).await,
+            Err(crate::http_server::ServerError::Foundry(
+                FoundryError::Unauthorized
+            ))
+        ));
+
+        let Json(results) = webhook_handler(State(state.clone()), headers.clone(), Json(roll))
+            .await
+            .expect("should relay roll");
+        assert!(results.is_empty(), "a roll isn't a command");
+        {
+            let sent = bot.0.lock().await;
+            let [(channel, message)] = sent.as_slice() else {
+                panic!("should relay exactly one message, got {:?}", sent);
+            };
+            assert_eq!(*channel, Channel::Dnd);
+            assert!(message.contains("Gandalf rolled in Foundry"));
+            assert!(message.contains("**17**"));
+        }
+
+        // commands from Foundry are answered in both places
+        let command = FoundryChatMessage {
+            speaker: "Gandalf".to_string(),
+            content: "!ultron echo you shall not pass".to_string(),
+            flavor: None,
+            roll: None,
+        };
+        let Json(results) = webhook_handler(State(state.clone()), headers.clone(), Json(command))
+            .await
+            .expect("should relay command");
+        assert_eq!(results, vec!["you shall not pass".to_string()]);
+        assert_eq!(wait_for(&received, 1).await.len(), 1);
+
+        // Ultron's own messages don't come back around
+        let echo = FoundryChatMessage {
+            speaker: ULTRON_SPEAKER.to_string(),
+            content: "you shall not pass".to_string(),
+            flavor: None,
+            roll: None,
+        };
+        let Json(results) = webhook_handler(State(state), headers, Json(echo))
+            .await
+            .expect("should ignore echo");
+        assert!(results.is_empty());
+        assert_eq!(bot.0.lock().await.len(), 3);
+    }
+}
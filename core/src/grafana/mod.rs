@@ -4,7 +4,11 @@ use serde_json::Value as Json;
 
 use crate::{
     Channel,
-    chatbot::ChatBot,
+    card::{Card, Color},
+    chatbot::{
+        ChatBot,
+        outgoing::{Mentions, OutgoingMessage},
+    },
     http_server::{AppState, HttpRoute, OpenApiTag},
 };
 
@@ -34,20 +38,33 @@ where
 {
     tracing::debug!("received grafana webhook: {:?}", payload);
 
-    let title = &payload.title;
-    let message = &payload.message;
-
-    let response = format!("# 🚨🚨🚨🚨 {title} 🚨🚨🚨🚨\n\n{message}");
-
     state
         .chat_bot
-        .send_message(Channel::Debug, &response)
+        .send(Channel::Debug, &alert_message(&payload))
         .await
         .map_err(|_| "failed to send message")?;
 
     Ok(())
 }
 
+/// an alert as an embed that doesn't ping anyone,
+/// since alert templates can say whatever they like
+fn alert_message(payload: &GrafanaAlertPayload) -> OutgoingMessage {
+    let color = match payload.status.as_str() {
+        "resolved" => Color::SUCCESS,
+        _ => Color::ULTRON,
+    };
+
+    let card = Card::builder()
+        .title(format!("🚨🚨🚨🚨 {} 🚨🚨🚨🚨", payload.title))
+        .description(payload.message.clone())
+        .footer(format!("{} · {}", payload.receiver, payload.status))
+        .color(color)
+        .build();
+
+    OutgoingMessage::from(card).with_mentions(Mentions::NONE)
+}
+
 /// represents a single alert in a Grafana alert payload.
 /// this is generated from a webhook:
 /// <https://grafana.com/docs/grafana/latest/alerting/configure-notifications/manage-contact-points/integrations/webhook-notifier/#body>
@@ -138,4 +155,21 @@ mod tests {
 
         assert_eq!(example, example_roundtrip);
     }
+
+    #[test]
+    fn grafana_alerts_are_embeds_without_pings() {
+        let example: GrafanaAlertPayload = serde_json::from_str(testdata::GRAFANA_EXAMPLE)
+            .expect("unable to deserialize grafana example");
+
+        let message = alert_message(&example);
+
+        assert_eq!(message.mentions, Mentions::NONE);
+        assert_eq!(message.embeds.len(), 1);
+        assert!(
+            message.embeds[0]
+                .title
+                .as_deref()
+                .is_some_and(|title| title.contains(&example.title))
+        );
+    }
 }
@@ -4,7 +4,7 @@ use std::sync::Arc;
 
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Path, State},
     http::StatusCode,
     response::{IntoResponse, Response as AxumResponse},
 };
@@ -18,11 +18,20 @@ use utoipa::{OpenApi, ToSchema};
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    Channel, Response,
-    chatbot::ChatBot,
+    Channel, Response, User,
+    chatbot::{ChatBot, outgoing::OutgoingMessage},
+    dice::{
+        history::RollHistory,
+        limits::DiceLimits,
+        macros::DiceMacros,
+        rollers::RollerConfig,
+        sheet::{CharacterSheet, CharacterSheets, SheetError},
+    },
     event_processor::{Event, EventError, EventProcessor, EventType},
+    foundry::{self, FoundryBridge, FoundryError},
     grafana,
     mcp::{UltronCommands, UltronMcp},
+    rate_limit::{RateLimiter, UsageReport},
 };
 
 mod trace_layer;
@@ -35,6 +44,7 @@ pub enum OpenApiTag {
     BotCommand,
     Telemetry,
     Meta,
+    Admin,
 }
 
 impl OpenApiTag {
@@ -64,18 +74,49 @@ pub enum ServerError {
 
     #[error("internal server error: {0}")]
     Internal(#[from] crate::error::Error),
+
+    #[error("unable to update character sheet: {0}")]
+    Sheet(#[from] SheetError),
+
+    #[error("Foundry bridge error: {0}")]
+    Foundry(#[from] FoundryError),
 }
 
 #[derive(Builder, Debug, Clone)]
 pub struct AppState<TBot> {
     pub event_processor: Arc<EventProcessor>,
     pub chat_bot: Arc<TBot>,
+    /// shared with the [`crate::command::CommandConsumer`] to report its counters
+    #[builder(default)]
+    pub rate_limiter: RateLimiter,
+    /// shared with the [`crate::command::CommandConsumer`] and the MCP server
+    #[builder(default)]
+    pub dice_macros: DiceMacros,
+    /// shared with the [`crate::command::CommandConsumer`] and the MCP server
+    #[builder(default)]
+    pub roll_history: RollHistory,
+    /// shared with the [`crate::command::CommandConsumer`] and the MCP server
+    #[builder(default)]
+    pub character_sheets: CharacterSheets,
+    /// the same caps the [`crate::command::CommandConsumer`] rolls with
+    #[builder(default)]
+    pub dice_limits: DiceLimits,
+    /// which dice roller the MCP server uses
+    #[builder(default)]
+    pub roller: RollerConfig,
+    /// relays Foundry VTT chat to #dnd, if it's set up
+    pub foundry: Option<FoundryBridge>,
 }
 
 impl<TBot> AppState<TBot> {
     pub fn make_ultron_commands_mcp(&self) -> StreamableHttpService<UltronCommands> {
         UltronMcp {
             event_processor: self.event_processor.clone(),
+            dice_macros: self.dice_macros.clone(),
+            roll_history: self.roll_history.clone(),
+            character_sheets: self.character_sheets.clone(),
+            dice_limits: self.dice_limits,
+            roller: self.roller.clone(),
         }
         .into()
     }
@@ -87,18 +128,21 @@ impl<TBot> AppState<TBot> {
         command,
         healthcheck,
         index,
-        api_doc
+        api_doc,
+        admin_limits,
+        import_foundry_sheet
     ),
     tags(
         (name = OpenApiTag::BotCommand.as_str(), description = "orders to submit to Ultron"),
         (name = OpenApiTag::Telemetry.as_str(), description = "figure out what's wrong with Ultron"),
         (name = OpenApiTag::Meta.as_str(), description = "meta information about Ultron"),
+        (name = OpenApiTag::Admin.as_str(), description = "keep an eye on Ultron"),
     )
 )]
 struct ApiDoc;
 
 /// Routes for the HTTP server.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, strum::Display, strum::IntoStaticStr)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, strum::IntoStaticStr)]
 #[strum(serialize_all = "snake_case")]
 pub enum Route {
     #[strum(to_string = "/command")]
@@ -116,6 +160,12 @@ pub enum Route {
     /// Model Context Protocol endpoint
     #[strum(to_string = "/mcp")]
     Mcp,
+    /// command usage counters
+    #[strum(to_string = "/admin/limits")]
+    AdminLimits,
+    /// import a Foundry VTT actor into a user's character sheet
+    #[strum(to_string = "/sheets/{user}/foundry")]
+    FoundrySheet,
 }
 
 impl Route {
@@ -124,6 +174,13 @@ impl Route {
     }
 }
 
+// strum's `Display` would try to interpolate `{user}` in paths like [`Route::FoundrySheet`]
+impl std::fmt::Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 pub trait HttpRoute {
     const PATH: &'static str;
 }
@@ -155,7 +212,10 @@ where
         .routes(routes!(command))
         .routes(routes!(api_doc))
         .routes(routes!(events))
+        .routes(routes!(admin_limits))
+        .routes(routes!(import_foundry_sheet))
         .routes(routes!(grafana::webhook_handler))
+        .routes(routes!(foundry::webhook_handler))
         .nest_service(Route::Mcp.as_str(), state.make_ultron_commands_mcp())
         .layer(TracingMiddleware::builder().build().make_layer())
         .with_state(state)
@@ -267,16 +327,19 @@ where
     Ok(Json(results))
 }
 
-async fn handle_event_response<TBot: ChatBot>(
+/// send a [`Response`] with [`ChatBot::send`], returning it as text.
+/// ignored responses aren't sent
+pub(crate) async fn handle_event_response<TBot: ChatBot>(
     bot: &TBot,
     channel: Channel,
     response: Response,
 ) -> Result<String, crate::error::Error> {
-    let body = match response {
-        Response::PlainChat(message) => message.clone(),
-        Response::Bot(bot_message) => bot_message.render_without_thinking_parts(),
-        Response::Ignored => "ignored".into(),
-    };
+    let message = OutgoingMessage::from(response);
+    if message.is_empty() {
+        return Ok("ignored".into());
+    }
+
+    let body = message.to_text();
 
     tracing::info!(
         ?channel,
@@ -284,7 +347,7 @@ async fn handle_event_response<TBot: ChatBot>(
         "sending message to `{channel:?}`: `{body}`"
     );
 
-    bot.send_message(channel, &body).await.map_err(Into::into)?;
+    bot.send(channel, &message).await.map_err(Into::into)?;
 
     Ok(body)
 }
@@ -305,6 +368,45 @@ async fn events<Bot>(State(state): State<AppState<Bot>>) -> Json<Vec<Event>> {
     Json(events)
 }
 
+/// today's command usage and rate limiting counters.
+#[utoipa::path(
+    get,
+    path = Route::AdminLimits.to_string(),
+    responses(
+        (status = OK, description = "usage counters", body = UsageReport)
+    ),
+    tag = OpenApiTag::Admin.as_str(),
+)]
+async fn admin_limits<Bot>(State(state): State<AppState<Bot>>) -> Json<UsageReport> {
+    Json(state.rate_limiter.report().await)
+}
+
+/// import a Foundry VTT actor export into a user's character sheet.
+/// Discord messages are too short for most exports, so this is the easy way in.
+#[utoipa::path(
+    post,
+    path = Route::FoundrySheet.to_string(),
+    params(("user" = String, Path, description = "the user who owns the sheet")),
+    request_body(content = String, description = "a Foundry VTT (dnd5e) actor export", content_type = "application/json"),
+    responses(
+        (status = OK, description = "the updated sheet", body = std::collections::BTreeMap<String, i32>),
+        (status = BAD_REQUEST, description = "not a Foundry actor")
+    ),
+    tag = OpenApiTag::BotCommand.as_str(),
+)]
+async fn import_foundry_sheet<Bot>(
+    State(state): State<AppState<Bot>>,
+    Path(user): Path<String>,
+    actor_json: String,
+) -> ServerResult<Json<CharacterSheet>> {
+    let sheet = state
+        .character_sheets
+        .import_foundry(&User::from(user), &actor_json)
+        .await?;
+
+    Ok(Json(sheet))
+}
+
 impl IntoResponse for ServerError {
     fn into_response(self) -> AxumResponse {
         tracing::warn!("error: {}", self);
@@ -315,6 +417,11 @@ impl IntoResponse for ServerError {
             ServerError::ChatBot(_) => StatusCode::INTERNAL_SERVER_ERROR,
             ServerError::OpenApiDocGeneration => StatusCode::INTERNAL_SERVER_ERROR,
             ServerError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServerError::Sheet(SheetError::Storage(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            ServerError::Sheet(_) => StatusCode::BAD_REQUEST,
+            ServerError::Foundry(FoundryError::NotConfigured) => StatusCode::NOT_FOUND,
+            ServerError::Foundry(FoundryError::Unauthorized) => StatusCode::UNAUTHORIZED,
+            ServerError::Foundry(_) => StatusCode::BAD_GATEWAY,
         };
         (status, self.to_string()).into_response()
     }
@@ -337,6 +444,13 @@ mod tests {
         }
     }
 
+    #[test]
+    fn routes_are_paths() {
+        assert_eq!(Route::Index.to_string(), "/");
+        assert_eq!(Route::AdminLimits.to_string(), "/admin/limits");
+        assert_eq!(Route::FoundrySheet.to_string(), "/sheets/{user}/foundry");
+    }
+
     #[tokio::test]
     async fn test_index() {
         let response = index().await;
@@ -348,6 +462,13 @@ mod tests {
         let state = AppState {
             event_processor: Arc::new(EventProcessor::test().await),
             chat_bot: Arc::new(TestBot),
+            rate_limiter: RateLimiter::default(),
+            dice_macros: DiceMacros::default(),
+            roll_history: RollHistory::default(),
+            character_sheets: CharacterSheets::default(),
+            dice_limits: DiceLimits::default(),
+            roller: RollerConfig::default(),
+            foundry: None,
         };
         let bot_input = BotInput {
             channel: Channel::Debug,
@@ -0,0 +1,420 @@
+//! an initiative tracker for combat, e.g.
+//! `!ultron init add Goblin 1d20+2`, `init roll`, `init next`.
+//! each channel has its own turn order.
+//! ties go to the higher modifier, then to whoever wins a reroll.
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Channel,
+    card::{Card, Color},
+    dice::{DiceRollError, DiceRoller, RollerImpl},
+    store::Store,
+};
+
+/// what you roll if you don't say
+pub const DEFAULT_INITIATIVE_ROLL: &str = "1d20";
+
+/// give up on breaking ties after this many rerolls
+const MAX_TIEBREAK_REROLLS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Combatant {
+    pub name: String,
+    /// what they roll for initiative, e.g. `1d20+2`
+    pub expression: String,
+    pub roll: Option<i32>,
+    /// the flat bonus in the roll, used to break ties
+    pub modifier: i32,
+    /// a reroll to break ties that the modifier couldn't
+    pub tiebreak: Option<i32>,
+}
+
+impl Combatant {
+    fn new(name: &str, expression: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            expression: expression.to_string(),
+            roll: None,
+            modifier: 0,
+            tiebreak: None,
+        }
+    }
+
+    fn roll<T: RollerImpl>(&mut self, roller: &mut DiceRoller<T>) -> Result<(), DiceRollError> {
+        let dice_roll = roller.roll_dice(&self.expression)?;
+        let dice_total: i32 = dice_roll
+            .dice()
+            .iter()
+            .filter(|die| die.kept)
+            .map(|die| i32::from(die.value))
+            .sum();
+
+        self.roll = Some(dice_roll.total());
+        self.modifier = dice_roll.total() - dice_total;
+        self.tiebreak = None;
+        Ok(())
+    }
+
+    /// everything that decides turn order, highest goes first
+    fn sort_key(&self) -> (Option<i32>, i32, Option<i32>) {
+        (self.roll, self.modifier, self.tiebreak)
+    }
+}
+
+/// the turn order in a channel
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Encounter {
+    pub combatants: Vec<Combatant>,
+    /// index of whoever's turn it is, once initiative is rolled
+    pub turn: Option<usize>,
+    pub round: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitiativeError {
+    #[error("nobody is in the fight yet. try `init add Goblin 1d20+2`")]
+    NoCombatants,
+
+    #[error("initiative hasn't been rolled yet. try `init roll`")]
+    NotRolled,
+
+    #[error("`{0}` isn't in the fight")]
+    UnknownCombatant(String),
+
+    #[error("`{0}` is already in the fight")]
+    DuplicateCombatant(String),
+
+    #[error("bad initiative roll: {0}")]
+    Dice(#[from] DiceRollError),
+
+    #[error("failed to save initiative: {0}")]
+    Storage(#[from] crate::error::Error),
+}
+
+impl Encounter {
+    /// add someone to the fight.
+    /// if initiative has already been rolled they roll right away.
+    pub fn add<T: RollerImpl>(
+        &mut self,
+        name: &str,
+        expression: &str,
+        roller: &mut DiceRoller<T>,
+    ) -> Result<(), InitiativeError> {
+        if self.position(name).is_some() {
+            return Err(InitiativeError::DuplicateCombatant(name.to_string()));
+        }
+
+        let mut combatant = Combatant::new(name, expression);
+        if self.turn.is_some() {
+            combatant.roll(roller)?;
+        } else {
+            // make sure the roll works before someone tries `init roll`
+            roller.roll_dice(expression)?;
+        }
+
+        self.keeping_turn(|encounter| {
+            encounter.combatants.push(combatant);
+            encounter.break_ties(roller)
+        })
+    }
+
+    /// remove someone from the fight, e.g. when they die
+    pub fn remove(&mut self, name: &str) -> Result<Combatant, InitiativeError> {
+        let index = self
+            .position(name)
+            .ok_or_else(|| InitiativeError::UnknownCombatant(name.to_string()))?;
+
+        let removed = self.combatants.remove(index);
+        self.turn = match self.turn {
+            _ if self.combatants.is_empty() => None,
+            Some(turn) if index < turn => Some(turn - 1),
+            // it was the last one's turn, so it wraps around like `advance`
+            Some(turn) if turn == self.combatants.len() => {
+                self.round += 1;
+                Some(0)
+            }
+            Some(turn) => Some(turn),
+            None => None,
+        };
+        Ok(removed)
+    }
+
+    /// roll initiative for everyone and start the first round
+    pub fn roll<T: RollerImpl>(
+        &mut self,
+        roller: &mut DiceRoller<T>,
+    ) -> Result<(), InitiativeError> {
+        if self.combatants.is_empty() {
+            return Err(InitiativeError::NoCombatants);
+        }
+
+        for combatant in &mut self.combatants {
+            combatant.roll(roller)?;
+        }
+        self.break_ties(roller)?;
+
+        self.turn = Some(0);
+        self.round = 1;
+        Ok(())
+    }
+
+    /// move on to the next turn, returning whoever's up
+    pub fn advance(&mut self) -> Result<&Combatant, InitiativeError> {
+        let turn = self.turn.ok_or(InitiativeError::NotRolled)?;
+
+        let next = (turn + 1) % self.combatants.len();
+        if next == 0 {
+            self.round += 1;
+        }
+        self.turn = Some(next);
+
+        Ok(&self.combatants[next])
+    }
+
+    pub fn current(&self) -> Option<&Combatant> {
+        self.turn.and_then(|turn| self.combatants.get(turn))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.combatants
+            .iter()
+            .position(|combatant| combatant.name.eq_ignore_ascii_case(name))
+    }
+
+    fn sort(&mut self) {
+        self.combatants
+            .sort_by_key(|combatant| std::cmp::Reverse(combatant.sort_key()));
+    }
+
+    /// reroll for anyone tied on both roll and modifier until the order is settled.
+    /// if the dice refuse to cooperate the order they were added in wins.
+    fn break_ties<T: RollerImpl>(
+        &mut self,
+        roller: &mut DiceRoller<T>,
+    ) -> Result<(), InitiativeError> {
+        for _ in 0..MAX_TIEBREAK_REROLLS {
+            self.sort();
+
+            let tied: Vec<usize> = (0..self.combatants.len())
+                .filter(|&index| {
+                    let key = self.combatants[index].sort_key();
+                    key.0.is_some()
+                        && self
+                            .combatants
+                            .iter()
+                            .enumerate()
+                            .any(|(other, combatant)| other != index && combatant.sort_key() == key)
+                })
+                .collect();
+
+            if tied.is_empty() {
+                return Ok(());
+            }
+
+            for index in tied {
+                let tiebreak = roller.roll_dice(DEFAULT_INITIATIVE_ROLL)?.total();
+                self.combatants[index].tiebreak = Some(tiebreak);
+            }
+        }
+
+        self.sort();
+        Ok(())
+    }
+
+    /// run `f`, then point `turn` back at whoever had it before the order changed
+    fn keeping_turn(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<(), InitiativeError>,
+    ) -> Result<(), InitiativeError> {
+        let current = self.current().map(|combatant| combatant.name.clone());
+        f(self)?;
+        if let Some(current) = current {
+            self.turn = self.position(&current);
+        }
+        Ok(())
+    }
+}
+
+impl From<&Encounter> for Card {
+    fn from(encounter: &Encounter) -> Self {
+        let order = encounter
+            .combatants
+            .iter()
+            .enumerate()
+            .map(|(index, combatant)| {
+                let marker = match encounter.turn == Some(index) {
+                    true => "▶️ ",
+                    false => "",
+                };
+                let roll = match combatant.roll {
+                    Some(roll) => format!("{} ({:+})", roll, combatant.modifier),
+                    None => format!("`{}`", combatant.expression),
+                };
+                format!("{}. {}**{}** {}", index + 1, marker, combatant.name, roll)
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let (title, footer) = match encounter.turn {
+            Some(_) => (
+                format!("⚔️ initiative, round {}", encounter.round),
+                "`init next` to end the turn",
+            ),
+            None => (
+                "⚔️ initiative".to_string(),
+                "`init roll` when everyone's ready",
+            ),
+        };
+
+        Card::builder()
+            .title(title)
+            .description(order)
+            .footer(footer)
+            .color(Color::ULTRON)
+            .build()
+    }
+}
+
+/// persistent turn order for each [`Channel`]
+#[derive(Debug, Clone, Default)]
+pub struct Initiative(Store<HashMap<Channel, Encounter>>);
+
+impl From<Store<HashMap<Channel, Encounter>>> for Initiative {
+    fn from(store: Store<HashMap<Channel, Encounter>>) -> Self {
+        Self(store)
+    }
+}
+
+impl Initiative {
+    pub async fn get(&self, channel: Channel) -> Encounter {
+        self.0
+            .read(|encounters| match encounters.get(&channel) {
+                Some(encounter) => encounter.clone(),
+                None => Encounter::default(),
+            })
+            .await
+    }
+
+    /// change the encounter in `channel`, returning it afterwards.
+    /// nothing changes if `f` fails.
+    pub async fn update(
+        &self,
+        channel: Channel,
+        f: impl FnOnce(&mut Encounter) -> Result<(), InitiativeError>,
+    ) -> Result<Encounter, InitiativeError> {
+        self.0
+            .update(|encounters| {
+                let encounter = encounters.entry(channel).or_default();
+                let mut updated = encounter.clone();
+                f(&mut updated)?;
+                *encounter = updated.clone();
+                Ok(updated)
+            })
+            .await?
+    }
+
+    /// end the fight in `channel`
+    pub async fn clear(&self, channel: Channel) -> crate::error::Result<()> {
+        self.0
+            .update(|encounters| {
+                encounters.remove(&channel);
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ties_are_broken_by_modifier() {
+        let mut roller = DiceRoller::max();
+        let mut encounter = Encounter::default();
+
+        encounter
+            .add("Goblin", "1d20+2", &mut roller)
+            .expect("should add goblin");
+        encounter
+            .add("Bob", "1d18+4", &mut roller)
+            .expect("should add bob");
+        encounter
+            .add("Alice", "1d20+5", &mut roller)
+            .expect("should add alice");
+        encounter.roll(&mut roller).expect("should roll initiative");
+
+        let order: Vec<&str> = encounter
+            .combatants
+            .iter()
+            .map(|combatant| combatant.name.as_str())
+            .collect();
+        // bob and the goblin both rolled 22, but bob has the better modifier
+        assert_eq!(order, vec!["Alice", "Bob", "Goblin"]);
+    }
+
+    #[test]
+    fn turns_wrap_into_new_rounds() {
+        let mut roller = DiceRoller::max();
+        let mut encounter = Encounter::default();
+
+        encounter
+            .add("Goblin", "1d20+2", &mut roller)
+            .expect("should add goblin");
+        encounter
+            .add("Bob", "1d20+4", &mut roller)
+            .expect("should add bob");
+
+        assert!(matches!(
+            encounter.advance(),
+            Err(InitiativeError::NotRolled)
+        ));
+
+        encounter.roll(&mut roller).expect("should roll initiative");
+        assert_eq!(encounter.round, 1);
+        assert_eq!(encounter.current().map(|c| c.name.as_str()), Some("Bob"));
+
+        let next = encounter.advance().expect("should advance");
+        assert_eq!(next.name, "Goblin");
+        let next = encounter.advance().expect("should advance");
+        assert_eq!(next.name, "Bob");
+        assert_eq!(encounter.round, 2);
+
+        // joining mid-fight doesn't steal the turn
+        encounter
+            .add("Carol", "1d20+10", &mut roller)
+            .expect("should add carol");
+        assert_eq!(encounter.current().map(|c| c.name.as_str()), Some("Bob"));
+
+        encounter.remove("Bob").expect("should remove bob");
+        assert_eq!(encounter.current().map(|c| c.name.as_str()), Some("Goblin"));
+
+        // the goblin was last in the order, so its death starts the next round
+        encounter.remove("Goblin").expect("should remove goblin");
+        assert_eq!(encounter.current().map(|c| c.name.as_str()), Some("Carol"));
+        assert_eq!(encounter.round, 3);
+    }
+
+    #[test]
+    fn tied_modifiers_are_rerolled() {
+        let mut roller = DiceRoller::with_rng(42);
+        let mut encounter = Encounter::default();
+
+        encounter
+            .add("Goblin", "10", &mut roller)
+            .expect("should add goblin");
+        encounter
+            .add("Kobold", "10", &mut roller)
+            .expect("should add kobold");
+        encounter.roll(&mut roller).expect("should roll initiative");
+
+        let tiebreaks: Vec<Option<i32>> = encounter
+            .combatants
+            .iter()
+            .map(|combatant| combatant.tiebreak)
+            .collect();
+        assert!(tiebreaks.iter().all(Option::is_some));
+        assert!(tiebreaks[0] >= tiebreaks[1]);
+    }
+}
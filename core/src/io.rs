@@ -1,7 +1,7 @@
 use std::path::Path;
 
-use serde::de::DeserializeOwned;
-use tokio::fs;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::{fs, io::AsyncWriteExt as _};
 
 use crate::error::{Error, Result};
 
@@ -24,6 +24,114 @@ pub async fn read_toml_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Resu
     })
 }
 
+pub async fn file_exists(path: impl AsRef<Path>) -> Result<bool> {
+    let path = path.as_ref();
+    fs::try_exists(path)
+        .await
+        .map_err(|source| Error::FileRead {
+            source,
+            path: path.to_path_buf(),
+        })
+}
+
+pub async fn read_json_file<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let contents = read_file_to_string(path).await?;
+    serde_json::from_str(&contents).map_err(|source| Error::JsonFileParse {
+        source,
+        path: path.to_path_buf(),
+    })
+}
+
+/// write `value` to `path` as pretty JSON,
+/// creating parent directories as needed.
+pub async fn write_json_file<T: Serialize>(path: impl AsRef<Path>, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    let contents =
+        serde_json::to_string_pretty(value).map_err(|source| Error::JsonSerialize { source })?;
+
+    let write_error = |source| Error::FileWrite {
+        source,
+        path: path.to_path_buf(),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.map_err(write_error)?;
+    }
+
+    fs::write(path, contents).await.map_err(write_error)
+}
+
+/// read a file with one JSON value per line, skipping blank lines
+pub async fn read_json_lines<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Vec<T>> {
+    let path = path.as_ref();
+    let contents = read_file_to_string(path).await?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|source| Error::JsonFileParse {
+                source,
+                path: path.to_path_buf(),
+            })
+        })
+        .collect()
+}
+
+/// write `values` to `path` as one JSON value per line,
+/// creating parent directories as needed.
+pub async fn write_json_lines<T: Serialize>(path: impl AsRef<Path>, values: &[T]) -> Result<()> {
+    let path = path.as_ref();
+    let mut contents = String::new();
+    for value in values {
+        contents.push_str(&json_line(value)?);
+    }
+
+    let write_error = |source| Error::FileWrite {
+        source,
+        path: path.to_path_buf(),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.map_err(write_error)?;
+    }
+
+    fs::write(path, contents).await.map_err(write_error)
+}
+
+/// add `value` to the end of `path` as a line of JSON,
+/// creating the file and its parent directories as needed.
+pub async fn append_json_line<T: Serialize>(path: impl AsRef<Path>, value: &T) -> Result<()> {
+    let path = path.as_ref();
+    let line = json_line(value)?;
+
+    let write_error = |source| Error::FileWrite {
+        source,
+        path: path.to_path_buf(),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await.map_err(write_error)?;
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(write_error)?;
+    file.write_all(line.as_bytes()).await.map_err(write_error)?;
+    // tokio writes in the background, make sure the line is there before returning
+    file.flush().await.map_err(write_error)
+}
+
+fn json_line<T: Serialize>(value: &T) -> Result<String> {
+    let mut line =
+        serde_json::to_string(value).map_err(|source| Error::JsonSerialize { source })?;
+    line.push('\n');
+    Ok(line)
+}
+
 pub fn parse_toml_str<T: DeserializeOwned>(contents: &str) -> Result<T> {
     toml::from_str(contents).map_err(|source| Error::TomlParse { source })
 }
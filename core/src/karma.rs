@@ -0,0 +1,254 @@
+//! karma tracking from plain chat.
+//! say `thing++` or `thing--` in any message to adjust the score of `thing`.
+use std::{collections::BTreeMap, time::Duration};
+
+use crate::{
+    Response, User,
+    cooldown::Cooldown,
+    event_processor::{Event, EventConsumer, EventError, EventType},
+    store::Store,
+};
+
+/// how long a user has to wait before changing the karma of the same thing again
+pub const DEFAULT_KARMA_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// how many entries to show on the leaderboard
+const LEADERBOARD_SIZE: usize = 10;
+
+/// persistent karma scores, keyed by normalized name
+#[derive(Debug, Clone, Default)]
+pub struct KarmaStore(Store<BTreeMap<String, i64>>);
+
+impl From<Store<BTreeMap<String, i64>>> for KarmaStore {
+    fn from(store: Store<BTreeMap<String, i64>>) -> Self {
+        Self(store)
+    }
+}
+
+impl KarmaStore {
+    pub async fn get(&self, thing: &str) -> i64 {
+        let thing = normalize(thing);
+        self.0
+            .read(|scores| scores.get(&thing).copied().unwrap_or(0))
+            .await
+    }
+
+    /// apply `delta` to the score of `thing`, returning the new score
+    pub async fn adjust(&self, thing: &str, delta: i64) -> crate::error::Result<i64> {
+        let thing = normalize(thing);
+        self.0
+            .update(|scores| {
+                let score = scores.entry(thing).or_insert(0);
+                *score += delta;
+                *score
+            })
+            .await
+    }
+
+    /// the highest scores, best first
+    pub async fn leaderboard(&self) -> Vec<(String, i64)> {
+        self.0
+            .read(|scores| {
+                let mut entries: Vec<(String, i64)> = scores
+                    .iter()
+                    .map(|(thing, score)| (thing.clone(), *score))
+                    .collect();
+                entries.sort_by(|(a_thing, a), (b_thing, b)| b.cmp(a).then(a_thing.cmp(b_thing)));
+                entries.truncate(LEADERBOARD_SIZE);
+                entries
+            })
+            .await
+    }
+}
+
+fn normalize(thing: &str) -> String {
+    thing.trim().trim_start_matches('@').to_lowercase()
+}
+
+/// a single `thing++` or `thing--` found in a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KarmaChange {
+    pub target: String,
+    pub delta: i64,
+}
+
+/// find all the karma changes in a message
+pub fn parse_karma_changes(message: &str) -> Vec<KarmaChange> {
+    message
+        .split_whitespace()
+        .filter_map(|word| {
+            // allow trailing punctuation, e.g. `rust++!`
+            let word = word.trim_end_matches(['!', '?', ',', '.', ';']);
+
+            let (target, delta) = if let Some(target) = word.strip_suffix("++") {
+                (target, 1)
+            } else if let Some(target) = word.strip_suffix("--") {
+                (target, -1)
+            } else {
+                return None;
+            };
+
+            let target = normalize(target);
+            let is_valid = !target.is_empty()
+                && target
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+            is_valid.then_some(KarmaChange { target, delta })
+        })
+        .collect()
+}
+
+/// watches [`EventType::Plain`] messages for `thing++` and `thing--`
+/// and updates the [`KarmaStore`].
+///
+/// rules to keep things civil:
+/// - you can't change your own karma
+/// - you can only change the karma of the same thing once per cooldown
+#[derive(Debug, Clone)]
+pub struct KarmaConsumer {
+    store: KarmaStore,
+    cooldown: Cooldown<(String, String)>,
+}
+
+impl KarmaConsumer {
+    pub fn new(store: KarmaStore) -> Self {
+        Self {
+            store,
+            cooldown: Cooldown::new(DEFAULT_KARMA_COOLDOWN),
+        }
+    }
+
+    pub fn with_cooldown(self, cooldown: Duration) -> Self {
+        Self {
+            cooldown: Cooldown::new(cooldown),
+            ..self
+        }
+    }
+
+    async fn apply(&self, user: &str, change: KarmaChange) -> Result<String, EventError> {
+        let KarmaChange { target, delta } = change;
+
+        if target == normalize(user) {
+            return Ok(format!("nice try {user}. no self-karma 🙅"));
+        }
+
+        if let Err(remaining) = self.cooldown.try_use((normalize(user), target.clone())) {
+            return Ok(format!(
+                "slow down {user}. `{target}` karma is on cooldown for {}s ⏳",
+                remaining.as_secs().max(1)
+            ));
+        }
+
+        let score = self.store.adjust(&target, delta).await?;
+        let emoji = if delta > 0 { "📈" } else { "📉" };
+
+        Ok(format!("`{target}` now has {score} karma {emoji}"))
+    }
+}
+
+#[async_trait::async_trait]
+impl EventConsumer for KarmaConsumer {
+    async fn consume_event(&self, event: &Event) -> Result<Response, EventError> {
+        // only known users can hand out karma
+        let User::Normal(user) = &event.user else {
+            return Ok(Response::Ignored);
+        };
+
+        let changes = parse_karma_changes(&event.content.render_without_thinking_parts());
+        if changes.is_empty() {
+            return Ok(Response::Ignored);
+        }
+
+        let mut replies = Vec::with_capacity(changes.len());
+        for change in changes {
+            replies.push(self.apply(user, change).await?);
+        }
+
+        Ok(Response::PlainChat(replies.join("\n")))
+    }
+
+    fn should_consume_event(&self, event: &Event) -> bool {
+        matches!(event.event_type, EventType::Plain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Channel;
+
+    use super::*;
+
+    fn plain_event(user: &str, content: &str) -> Event {
+        Event::builder()
+            .user(User::from(user))
+            .content(content.to_string())
+            .event_type(EventType::Plain)
+            .channel(Channel::Debug)
+            .build()
+    }
+
+    #[test]
+    fn parse_karma_changes_finds_all() {
+        let changes = parse_karma_changes("rust++ and go-- but not c+ or ++ or --, Linux++!");
+        assert_eq!(
+            changes,
+            vec![
+                KarmaChange {
+                    target: "rust".to_string(),
+                    delta: 1
+                },
+                KarmaChange {
+                    target: "go".to_string(),
+                    delta: -1
+                },
+                KarmaChange {
+                    target: "linux".to_string(),
+                    delta: 1
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn karma_consumer_rejects_self_karma_and_cooldowns() {
+        let store = KarmaStore::default();
+        let consumer = KarmaConsumer::new(store.clone());
+
+        let response = consumer
+            .consume_event(&plain_event("bob", "bob++"))
+            .await
+            .expect("should not error");
+        assert_eq!(
+            response,
+            Response::PlainChat("nice try bob. no self-karma 🙅".to_string())
+        );
+        assert_eq!(store.get("bob").await, 0);
+
+        consumer
+            .consume_event(&plain_event("bob", "rust++"))
+            .await
+            .expect("should not error");
+        consumer
+            .consume_event(&plain_event("bob", "rust++"))
+            .await
+            .expect("should not error");
+        assert_eq!(store.get("rust").await, 1);
+
+        consumer
+            .consume_event(&plain_event("alice", "rust++"))
+            .await
+            .expect("should not error");
+        assert_eq!(store.get("Rust").await, 2);
+    }
+
+    #[tokio::test]
+    async fn karma_consumer_ignores_plain_chat() {
+        let consumer = KarmaConsumer::new(KarmaStore::default());
+        let response = consumer
+            .consume_event(&plain_event("bob", "just chatting"))
+            .await
+            .expect("should not error");
+        assert_eq!(response, Response::Ignored);
+    }
+}
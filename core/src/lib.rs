@@ -1,18 +1,28 @@
 use serde::{Deserialize, Serialize};
 
-use crate::nlp::response::MessageParts;
+use crate::{card::Card, nlp::response::MessageParts};
 
+pub mod auto_response;
+pub mod card;
 pub mod chatbot;
 pub mod command;
+pub mod cooldown;
 pub mod copypasta;
+pub mod custom_command;
 pub mod dice;
 pub mod error;
 pub mod event_processor;
+pub mod foundry;
 pub mod grafana;
 pub mod http_server;
+pub mod initiative;
 pub mod io;
+pub mod karma;
 pub mod mcp;
 pub mod nlp;
+pub mod rate_limit;
+pub mod reload;
+pub mod store;
 
 const DEFAULT_COMMAND_PREFIX: &str = "!ultron";
 
@@ -25,6 +35,8 @@ const DEFAULT_COMMAND_PREFIX: &str = "!ultron";
     Hash,
     strum::Display,
     strum::IntoStaticStr,
+    strum::EnumString,
+    strum::EnumIter,
     Serialize,
     Deserialize,
     utoipa::ToSchema,
@@ -49,10 +61,42 @@ pub enum Response {
     /// that may contain additional metadata.
     /// see [`event_processor::BotMessage`].
     Bot(MessageParts),
+    /// a structured response, e.g. from a command,
+    /// that chat adapters can render natively
+    Card(Card),
+    /// react to the message with an emoji,
+    /// or just say the emoji where reactions aren't a thing
+    Reaction(String),
     /// the [`crate::event_processor::EventConsumer`] ignored the event
     Ignored,
 }
 
+impl Response {
+    /// the response as plain text,
+    /// e.g. to feed into the next stage of a command pipeline
+    pub fn to_text(&self) -> String {
+        match self {
+            Response::PlainChat(message) => message.clone(),
+            Response::Bot(message) => message.render_without_thinking_parts(),
+            Response::Card(card) => card.to_plain_text(),
+            Response::Reaction(emoji) => emoji.clone(),
+            Response::Ignored => String::new(),
+        }
+    }
+}
+
+impl From<String> for Response {
+    fn from(message: String) -> Self {
+        Response::PlainChat(message)
+    }
+}
+
+impl From<Card> for Response {
+    fn from(card: Card) -> Self {
+        Response::Card(card)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema, strum::Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum User {
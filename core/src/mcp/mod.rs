@@ -2,7 +2,10 @@ use std::{borrow::Cow, sync::Arc};
 
 use rmcp::{
     ServerHandler,
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
+    handler::server::{
+        router::tool::ToolRouter,
+        wrapper::{Json, Parameters},
+    },
     model::{ServerCapabilities, ServerInfo, *},
     schemars, tool, tool_handler, tool_router,
     transport::{
@@ -12,33 +15,49 @@ use rmcp::{
 
 use crate::User;
 use crate::{
-    dice::{DiceRoll, DiceRollError},
+    Channel,
+    dice::{
+        DiceRoll, DiceRollError, DiceRoller,
+        history::RollHistory,
+        limits::DiceLimits,
+        macros::DiceMacros,
+        rollers::{ConfiguredRoller, RollerConfig},
+        sheet::CharacterSheets,
+    },
     event_processor::EventProcessor,
 };
 
 pub mod client;
 
+/// state shared between the MCP server and the rest of Ultron
 #[derive(Debug, Clone)]
 pub struct UltronMcp {
     pub event_processor: Arc<EventProcessor>,
+    pub dice_macros: DiceMacros,
+    pub roll_history: RollHistory,
+    pub character_sheets: CharacterSheets,
+    pub dice_limits: DiceLimits,
+    pub roller: RollerConfig,
 }
 
 pub struct UltronCommands {
     event_processor: Arc<EventProcessor>,
-    dice_roller: crate::dice::DiceRoller<tyche::dice::roller::FastRand>,
+    dice_roller: DiceRoller<ConfiguredRoller>,
+    dice_macros: DiceMacros,
+    roll_history: RollHistory,
+    character_sheets: CharacterSheets,
     tool_router: ToolRouter<Self>,
 }
 
 impl From<UltronMcp> for StreamableHttpService<UltronCommands> {
-    fn from(UltronMcp { event_processor }: UltronMcp) -> Self {
-        build(event_processor)
+    fn from(mcp: UltronMcp) -> Self {
+        build(mcp)
     }
 }
 
-pub fn build(event_processor: Arc<EventProcessor>) -> StreamableHttpService<UltronCommands> {
-    let event_processor = event_processor.clone();
+pub fn build(mcp: UltronMcp) -> StreamableHttpService<UltronCommands> {
     StreamableHttpService::new(
-        move || Ok(UltronCommands::new(event_processor.clone())),
+        move || Ok(UltronCommands::new(mcp.clone())),
         LocalSessionManager::default().into(),
         Default::default(),
     )
@@ -51,15 +70,46 @@ pub struct DiceRollRequest {
     #[schemars(example = "1d20+5")]
     #[schemars(example = "2d10")]
     #[schemars(example = "4d6+2d8-2")]
+    #[schemars(example = "6x 4d6k3")]
+    #[schemars(example = "8d10>=7")]
     expression: String,
+    #[schemars(
+        description = "the user whose saved dice macros (e.g. `attack+2`) and character sheet variables (e.g. `1d20+@str`) should be used"
+    )]
+    #[serde(default)]
+    user: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+pub struct RollHistoryRequest {
+    #[schemars(description = "the user whose rolls to look up")]
+    user: String,
+    #[schemars(description = "how many of the most recent rolls to return, defaults to 10")]
+    #[serde(default)]
+    limit: Option<usize>,
 }
 
+/// how many rolls `roll_history` returns if the agent doesn't say
+const DEFAULT_ROLL_HISTORY_LIMIT: usize = 10;
+
 #[tool_router]
 impl UltronCommands {
-    pub fn new(event_processor: Arc<EventProcessor>) -> Self {
+    pub fn new(
+        UltronMcp {
+            event_processor,
+            dice_macros,
+            roll_history,
+            character_sheets,
+            dice_limits,
+            roller,
+        }: UltronMcp,
+    ) -> Self {
         Self {
             event_processor,
-            dice_roller: crate::dice::DiceRoller::default(),
+            dice_roller: DiceRoller::from(&roller).with_limits(dice_limits),
+            dice_macros,
+            roll_history,
+            character_sheets,
             tool_router: Self::tool_router(),
         }
     }
@@ -81,21 +131,58 @@ impl UltronCommands {
             .join("\n")
     }
 
-    #[tool(description = "roll dice given Foundry VTT/tyche expression")]
+    #[tool(
+        description = "roll dice given Foundry VTT/tyche expression. the result breaks the roll down into terms, with each die's face and whether it was kept or dropped"
+    )]
     pub async fn roll_dice(
         &self,
-        Parameters(DiceRollRequest { expression }): Parameters<DiceRollRequest>,
-    ) -> Result<CallToolResult, rmcp::ErrorData> {
-        tracing::debug!(expression, "rolling dice");
+        Parameters(DiceRollRequest { expression, user }): Parameters<DiceRollRequest>,
+    ) -> Result<Json<DiceRoll>, rmcp::ErrorData> {
+        tracing::debug!(expression, ?user, "rolling dice");
+
+        let expression = match user.map(User::from) {
+            Some(user) => {
+                let expression = self.dice_macros.expand(&user, &expression).await;
+                self.character_sheets
+                    .substitute(&user, &expression)
+                    .await
+                    .map_err(ErrorData::from)?
+            }
+            None => expression,
+        };
 
         let dice_roll: DiceRoll = self
             .dice_roller
             .clone()
-            .roll_string(&expression)
-            .and_then(|evaled| evaled.try_into())
+            .roll_dice(&expression)
             .map_err(ErrorData::from)?;
 
-        Ok(CallToolResult::success(vec![Content::json(dice_roll)?]))
+        Ok(Json(dice_roll))
+    }
+
+    #[tool(description = "get a user's most recent dice rolls, newest first, and their luck")]
+    pub async fn roll_history(
+        &self,
+        Parameters(RollHistoryRequest { user, limit }): Parameters<RollHistoryRequest>,
+    ) -> Result<CallToolResult, rmcp::ErrorData> {
+        let user = User::from(user);
+        let limit = limit.unwrap_or(DEFAULT_ROLL_HISTORY_LIMIT);
+
+        let rolls = self.roll_history.recent(&user, limit).await;
+        let luck = self.roll_history.luck(&user).await;
+
+        Ok(CallToolResult::success(vec![
+            Content::json(rolls)?,
+            Content::json(luck)?,
+        ]))
+    }
+
+    #[tool(
+        description = "how lucky each player has been in the current D&D session, luckiest first"
+    )]
+    pub async fn session_luck(&self) -> Result<CallToolResult, rmcp::ErrorData> {
+        let luck = self.roll_history.session_luck(Channel::Dnd).await;
+        Ok(CallToolResult::success(vec![Content::json(luck)?]))
     }
 }
 
@@ -109,6 +196,11 @@ impl From<DiceRollError> for rmcp::ErrorData {
             DiceRollError::Parse(_) => ErrorCode::INVALID_REQUEST,
             DiceRollError::Eval(_) => ErrorCode::INVALID_PARAMS,
             DiceRollError::Calc(_) => ErrorCode::INVALID_PARAMS,
+            DiceRollError::InvalidTarget(_) => ErrorCode::INVALID_PARAMS,
+            DiceRollError::UnknownVariable(_) => ErrorCode::INVALID_PARAMS,
+            DiceRollError::InvalidRepetitions { .. } => ErrorCode::INVALID_PARAMS,
+            DiceRollError::InvalidPool(_) => ErrorCode::INVALID_PARAMS,
+            DiceRollError::LimitExceeded { .. } => ErrorCode::INVALID_PARAMS,
         };
 
         let message: Cow<'_, str> = error.to_string().into();
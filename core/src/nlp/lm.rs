@@ -151,6 +151,7 @@ impl LanguageModel {
 
                 let event = Event {
                     user: User::Ultron,
+                    identity: None,
                     ..event
                 };
 
@@ -7,10 +7,11 @@ use tokio::sync::RwLock;
 use tracing::instrument;
 
 use crate::{
-    Response,
+    Response, User,
     event_processor::{Event, EventConsumer, EventError, EventType},
     io::read_file_to_string,
     nlp::lm::{LanguageModel, ModelName},
+    reload::Reload,
 };
 
 pub mod lm;
@@ -47,6 +48,7 @@ impl ChatAgent for EchoAgent {
 
         Ok(Event {
             user: crate::User::Ultron,
+            identity: None,
             ..event
         })
     }
@@ -110,6 +112,13 @@ impl ChatHistory {
         history.extend([event]);
     }
 
+    /// swap out the system prompt, keeping the rest of the conversation
+    pub async fn set_system_prompt(&self, system_prompt: Event) {
+        let mut history = self.0.write().await;
+        history.retain(|event| event.user != User::System);
+        history.insert(0, system_prompt);
+    }
+
     /// return a read-only snapshot of the chat history
     #[instrument(skip(self))]
     pub async fn read(&self) -> Vec<Event> {
@@ -154,14 +163,30 @@ impl LmChatAgent {
         // let mcp = McpClient::new(&mcp_uri).await?;
         let language_model = LanguageModel::ollama(&llm_uri, llm_model)?;
 
-        let system_prompt = Event::builder()
-            .channel(crate::Channel::Debug)
-            .user(crate::User::System)
-            .content(system_prompt)
-            .event_type(EventType::LanguageModel)
-            .build();
+        Ok(Self::new(
+            language_model,
+            [system_prompt_event(system_prompt)],
+        ))
+    }
+}
+
+fn system_prompt_event(system_prompt: impl Into<String>) -> Event {
+    Event::builder()
+        .channel(crate::Channel::Debug)
+        .user(User::System)
+        .content(system_prompt.into())
+        .event_type(EventType::LanguageModel)
+        .build()
+}
 
-        Ok(Self::new(language_model, [system_prompt]))
+/// reloads the system prompt, e.g. from `--system-prompt`
+#[async_trait::async_trait]
+impl Reload for LmChatAgent {
+    async fn reload(&self, contents: &str) -> crate::error::Result<()> {
+        self.chat_history
+            .set_system_prompt(system_prompt_event(contents))
+            .await;
+        Ok(())
     }
 }
 
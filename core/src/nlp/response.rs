@@ -28,6 +28,10 @@ impl MessageParts {
         MessageParts { parts: vec![part] }
     }
 
+    pub fn parts(&self) -> &[MessagePart] {
+        &self.parts
+    }
+
     /// render the message without any thinking parts
     pub fn render_without_thinking_parts(&self) -> String {
         self.parts
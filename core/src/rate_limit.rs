@@ -0,0 +1,332 @@
+//! per-command cooldowns and daily quotas.
+//! limits are declared as properties on [`Command`] variants,
+//! see [`CommandDiscriminants::limits`].
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use strum::{EnumProperty as _, IntoEnumIterator as _};
+use time::{Date, OffsetDateTime};
+use tokio::sync::Mutex;
+
+use crate::{
+    Channel, User,
+    command::{Command, CommandDiscriminants},
+    cooldown::Cooldown,
+};
+
+/// how often a command may be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommandLimits {
+    /// how long a user has to wait between uses
+    pub user_cooldown: Option<Duration>,
+    /// how long a channel has to wait between uses
+    pub channel_cooldown: Option<Duration>,
+    /// how many times a user can use the command per (UTC) day
+    pub daily_quota: Option<u32>,
+}
+
+impl CommandDiscriminants {
+    /// the limits declared with `props(...)` on the [`Command`] variant
+    pub fn limits(&self) -> CommandLimits {
+        let secs = |prop| {
+            self.get_str(prop)
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+        };
+
+        CommandLimits {
+            user_cooldown: secs("user_cooldown_secs"),
+            channel_cooldown: secs("channel_cooldown_secs"),
+            daily_quota: self
+                .get_str("daily_quota")
+                .and_then(|quota| quota.parse().ok()),
+        }
+    }
+}
+
+/// why a command was refused
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum RateLimited {
+    #[error(
+        "slow down {user}. `{command}` is cooling down for {}s. even my patience has limits ⏳",
+        .remaining.as_secs().max(1)
+    )]
+    Cooldown {
+        user: User,
+        command: CommandDiscriminants,
+        remaining: Duration,
+    },
+
+    #[error("{user} has used all {quota} of today's `{command}`. come back tomorrow, meatbag 🤖")]
+    Quota {
+        user: User,
+        command: CommandDiscriminants,
+        quota: u32,
+    },
+}
+
+/// how much a user has used a command today
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct CommandUsage {
+    pub command: String,
+    pub user: String,
+    /// successful uses
+    pub count: u32,
+    /// uses refused because of a cooldown or quota
+    pub throttled: u32,
+    pub daily_quota: Option<u32>,
+}
+
+/// a snapshot of today's usage counters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct UsageReport {
+    pub date: Date,
+    pub usage: Vec<CommandUsage>,
+}
+
+#[derive(Debug)]
+struct DailyUsage {
+    date: Date,
+    counts: HashMap<(CommandDiscriminants, String), (u32, u32)>,
+}
+
+impl DailyUsage {
+    fn today() -> Self {
+        Self {
+            date: OffsetDateTime::now_utc().date(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// start over if the day has changed
+    fn roll_over(&mut self) {
+        let today = OffsetDateTime::now_utc().date();
+        if self.date != today {
+            *self = Self::today();
+        }
+    }
+}
+
+/// enforces [`CommandLimits`] in the [`crate::command::CommandConsumer`].
+/// clones share the same state.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    user_cooldowns: Arc<HashMap<CommandDiscriminants, Cooldown<String>>>,
+    channel_cooldowns: Arc<HashMap<CommandDiscriminants, Cooldown<Channel>>>,
+    usage: Arc<Mutex<DailyUsage>>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        let user_cooldowns = CommandDiscriminants::iter()
+            .filter_map(|command| {
+                command
+                    .limits()
+                    .user_cooldown
+                    .map(|duration| (command, Cooldown::new(duration)))
+            })
+            .collect();
+
+        let channel_cooldowns = CommandDiscriminants::iter()
+            .filter_map(|command| {
+                command
+                    .limits()
+                    .channel_cooldown
+                    .map(|duration| (command, Cooldown::new(duration)))
+            })
+            .collect();
+
+        Self {
+            user_cooldowns: Arc::new(user_cooldowns),
+            channel_cooldowns: Arc::new(channel_cooldowns),
+            usage: Arc::new(Mutex::new(DailyUsage::today())),
+        }
+    }
+
+    /// record a use of `command`, or refuse it if it's over its limits
+    pub async fn check(
+        &self,
+        command: &Command,
+        user: &User,
+        channel: Channel,
+    ) -> Result<(), RateLimited> {
+        let command = CommandDiscriminants::from(command);
+        let limits = command.limits();
+        let user_key = user.to_string();
+
+        let mut usage = self.usage.lock().await;
+        usage.roll_over();
+        let (count, throttled) = usage
+            .counts
+            .entry((command, user_key.clone()))
+            .or_insert((0, 0));
+
+        let result = self.check_limits(command, limits, *count, user, user_key, channel);
+
+        match result {
+            Ok(()) => *count += 1,
+            Err(_) => *throttled += 1,
+        }
+
+        result
+    }
+
+    fn check_limits(
+        &self,
+        command: CommandDiscriminants,
+        limits: CommandLimits,
+        count: u32,
+        user: &User,
+        user_key: String,
+        channel: Channel,
+    ) -> Result<(), RateLimited> {
+        if let Some(quota) = limits.daily_quota
+            && count >= quota
+        {
+            return Err(RateLimited::Quota {
+                user: user.clone(),
+                command,
+                quota,
+            });
+        }
+
+        let cooldown_error = |remaining| RateLimited::Cooldown {
+            user: user.clone(),
+            command,
+            remaining,
+        };
+
+        let user_cooldown = self.user_cooldowns.get(&command);
+        let channel_cooldown = self.channel_cooldowns.get(&command);
+
+        // check both before using either, so a refusal doesn't cost the user their turn
+        let remaining = [
+            user_cooldown.and_then(|cooldown| cooldown.remaining(&user_key)),
+            channel_cooldown.and_then(|cooldown| cooldown.remaining(&channel)),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        if let Some(remaining) = remaining {
+            return Err(cooldown_error(remaining));
+        }
+
+        if let Some(cooldown) = user_cooldown {
+            cooldown.mark_used(user_key);
+        }
+        if let Some(cooldown) = channel_cooldown {
+            cooldown.mark_used(channel);
+        }
+
+        Ok(())
+    }
+
+    /// today's usage counters
+    pub async fn report(&self) -> UsageReport {
+        let mut usage = self.usage.lock().await;
+        usage.roll_over();
+
+        let mut entries: Vec<CommandUsage> = usage
+            .counts
+            .iter()
+            .map(|((command, user), (count, throttled))| CommandUsage {
+                command: command.to_string(),
+                user: user.clone(),
+                count: *count,
+                throttled: *throttled,
+                daily_quota: command.limits().daily_quota,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.command.cmp(&b.command).then(a.user.cmp(&b.user)));
+
+        UsageReport {
+            date: usage.date,
+            usage: entries,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn declared_limits_are_valid() {
+        for command in CommandDiscriminants::iter() {
+            for prop in ["user_cooldown_secs", "channel_cooldown_secs", "daily_quota"] {
+                if let Some(value) = command.get_str(prop) {
+                    assert!(
+                        value.parse::<u64>().is_ok(),
+                        "`{command}` has an invalid `{prop}`: {value}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_enforces_cooldowns_and_counts_usage() {
+        let limiter = RateLimiter::new();
+        let bob = User::from("bob");
+        let alice = User::from("alice");
+        let pasta = Command::Copypasta("rust".to_string());
+
+        assert!(
+            CommandDiscriminants::Copypasta
+                .limits()
+                .user_cooldown
+                .is_some()
+        );
+
+        limiter
+            .check(&pasta, &bob, Channel::Debug)
+            .await
+            .expect("first use should be allowed");
+        let error = limiter
+            .check(&pasta, &bob, Channel::Dnd)
+            .await
+            .expect_err("second use should be on cooldown");
+        assert!(matches!(error, RateLimited::Cooldown { .. }));
+
+        limiter
+            .check(&pasta, &alice, Channel::Dnd)
+            .await
+            .expect("other users have their own cooldown");
+
+        let report = limiter.report().await;
+        let bob_usage = report
+            .usage
+            .iter()
+            .find(|usage| usage.user == "bob")
+            .expect("bob should have used pasta");
+        assert_eq!(bob_usage.count, 1);
+        assert_eq!(bob_usage.throttled, 1);
+    }
+
+    #[tokio::test]
+    async fn channel_cooldowns_dont_use_up_user_cooldowns() {
+        let limiter = RateLimiter::new();
+        let pasta = Command::Copypasta("rust".to_string());
+
+        limiter
+            .check(&pasta, &User::from("bob"), Channel::Debug)
+            .await
+            .expect("first use should be allowed");
+        let error = limiter
+            .check(&pasta, &User::from("alice"), Channel::Debug)
+            .await
+            .expect_err("the channel should be on cooldown");
+        assert!(matches!(error, RateLimited::Cooldown { .. }));
+
+        limiter
+            .check(&pasta, &User::from("alice"), Channel::Dnd)
+            .await
+            .expect("alice hasn't actually used pasta yet");
+    }
+}
@@ -0,0 +1,183 @@
+//! reload files from disk when they change, so edits on the server apply without a restart.
+//!
+//! a [`FileWatcher`] polls a file and hands new contents to something that can [`Reload`].
+//! if that fails, the old version is kept and the error goes to [`crate::Channel::Debug`].
+use std::{path::PathBuf, time::Duration};
+
+use crate::{chatbot::ChatBot, error::Result, io::read_file_to_string};
+
+/// something that can be rebuilt from the contents of a file,
+/// e.g. [`crate::copypasta::Copypastas`] or a system prompt
+#[async_trait::async_trait]
+pub trait Reload: Send + Sync + 'static {
+    /// replace the current version with `contents`.
+    /// on error the current version must be left alone
+    async fn reload(&self, contents: &str) -> Result<()>;
+}
+
+/// how often [`FileWatcher::watch`] looks at the file
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// watches a file and [`Reload`]s its target whenever the contents change
+#[derive(Debug)]
+pub struct FileWatcher<TTarget> {
+    path: PathBuf,
+    target: TTarget,
+    /// the last contents handed to the target, whether they loaded or not
+    contents: Option<String>,
+    /// so a missing file is only reported once
+    unreadable: bool,
+}
+
+impl<TTarget> FileWatcher<TTarget>
+where
+    TTarget: Reload,
+{
+    pub fn new(path: impl Into<PathBuf>, target: TTarget) -> Self {
+        Self {
+            path: path.into(),
+            target,
+            contents: None,
+            unreadable: false,
+        }
+    }
+
+    /// check the file forever. the first check loads it right away
+    pub async fn watch<TBot>(mut self, bot: TBot, interval: Duration)
+    where
+        TBot: ChatBot,
+    {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            self.check(&bot).await;
+        }
+    }
+
+    /// reload the target if the file changed since the last check
+    pub async fn check<TBot>(&mut self, bot: &TBot)
+    where
+        TBot: ChatBot,
+    {
+        let path = self.path.display().to_string();
+
+        let contents = match read_file_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(error) => {
+                if !self.unreadable {
+                    self.unreadable = true;
+                    report(
+                        bot,
+                        format!("can't read `{path}`, keeping the old one: {error}"),
+                    )
+                    .await;
+                }
+                return;
+            }
+        };
+        self.unreadable = false;
+
+        if self.contents.as_ref() == Some(&contents) {
+            return;
+        }
+
+        match self.target.reload(&contents).await {
+            Ok(()) => tracing::info!(%path, "reloaded file"),
+            Err(error) => {
+                report(
+                    bot,
+                    format!("can't reload `{path}`, keeping the old one: {error}"),
+                )
+                .await;
+            }
+        }
+
+        // remember broken contents too, so they're only reported once
+        self.contents = Some(contents);
+    }
+}
+
+async fn report<TBot>(bot: &TBot, message: String)
+where
+    TBot: ChatBot,
+{
+    tracing::error!(%message, "file reload failed");
+
+    if let Err(error) = bot.debug(&message).await {
+        let error: crate::error::Error = error.into();
+        tracing::error!(%error, "unable to report file reload failure");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::{Channel, copypasta::Copypastas, error::TestError};
+
+    #[derive(Debug, Clone, Default)]
+    struct RecordingBot(Arc<Mutex<Vec<(Channel, String)>>>);
+
+    impl ChatBot for RecordingBot {
+        type Error = TestError;
+
+        async fn send_message(
+            &self,
+            channel: Channel,
+            message: &str,
+        ) -> std::result::Result<(), Self::Error> {
+            self.0.lock().await.push((channel, message.to_string()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn broken_files_keep_the_old_version() {
+        let path = std::env::temp_dir().join(format!("ultron-reload-{}.toml", std::process::id()));
+        let bot = RecordingBot::default();
+        let copypastas = Copypastas::default();
+        let mut watcher = FileWatcher::new(&path, copypastas.clone());
+
+        watcher.check(&bot).await;
+        assert_eq!(bot.0.lock().await.len(), 1, "a missing file is reported");
+        watcher.check(&bot).await;
+        assert_eq!(bot.0.lock().await.len(), 1, "but only once");
+
+        tokio::fs::write(&path, r#"hello = "hello from disk""#)
+            .await
+            .expect("should write pasta file");
+        watcher.check(&bot).await;
+        assert_eq!(
+            copypastas.copy_pasta("hello").await.as_deref(),
+            Some("hello from disk")
+        );
+
+        tokio::fs::write(&path, r#"hello = "oops"#)
+            .await
+            .expect("should write pasta file");
+        watcher.check(&bot).await;
+        watcher.check(&bot).await;
+        assert_eq!(
+            copypastas.copy_pasta("hello").await.as_deref(),
+            Some("hello from disk")
+        );
+
+        let messages = bot.0.lock().await.clone();
+        assert_eq!(messages.len(), 2);
+        assert!(
+            messages
+                .iter()
+                .all(|(channel, _)| *channel == Channel::Debug)
+        );
+        assert!(messages[1].1.starts_with("can't reload"));
+
+        tokio::fs::remove_file(&path)
+            .await
+            .expect("should clean up pasta file");
+    }
+}
@@ -0,0 +1,113 @@
+//! a tiny persistence layer.
+//! state is kept in memory and written to a JSON file
+//! every time it changes.
+//! good enough for a chat bot, not good enough for a bank.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::sync::RwLock;
+
+use crate::{
+    error::Result,
+    io::{file_exists, read_json_file, write_json_file},
+};
+
+/// a cloneable handle to some state that may be backed by a JSON file.
+/// clones share the same state.
+#[derive(Debug, Clone)]
+pub struct Store<T> {
+    path: Option<PathBuf>,
+    data: Arc<RwLock<T>>,
+}
+
+impl<T> Store<T>
+where
+    T: Serialize + DeserializeOwned + Default + Send + Sync,
+{
+    /// a store that only lives in memory.
+    /// useful for tests and for running without a data directory.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            data: Arc::new(RwLock::new(T::default())),
+        }
+    }
+
+    /// open a store backed by the file at `path`.
+    /// if the file doesn't exist yet it is created with the default state.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let data = if file_exists(&path).await? {
+            read_json_file(&path).await?
+        } else {
+            tracing::info!(?path, "store file not found, creating a new one");
+            let data = T::default();
+            write_json_file(&path, &data).await?;
+            data
+        };
+
+        Ok(Self {
+            path: Some(path),
+            data: Arc::new(RwLock::new(data)),
+        })
+    }
+
+    /// read the current state
+    pub async fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        let data = self.data.read().await;
+        f(&data)
+    }
+
+    /// modify the state and persist it to disk, if this store is backed by a file.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R> {
+        let mut data = self.data.write().await;
+        let result = f(&mut data);
+
+        if let Some(path) = &self.path {
+            write_json_file(path, &*data).await?;
+        }
+
+        Ok(result)
+    }
+}
+
+impl<T> Default for Store<T>
+where
+    T: Serialize + DeserializeOwned + Default + Send + Sync,
+{
+    fn default() -> Self {
+        Self::in_memory()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn store_round_trips_through_disk() {
+        let path =
+            std::env::temp_dir().join(format!("ultron_store_test_{}.json", std::process::id()));
+        let _ = tokio::fs::remove_file(&path).await;
+
+        let store: Store<BTreeMap<String, i64>> =
+            Store::open(&path).await.expect("should create store");
+        store
+            .update(|map| map.insert("rust".to_string(), 42))
+            .await
+            .expect("should update store");
+
+        let reopened: Store<BTreeMap<String, i64>> =
+            Store::open(&path).await.expect("should reopen store");
+        let value = reopened.read(|map| map.get("rust").copied()).await;
+        assert_eq!(value, Some(42));
+
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}
@@ -1,24 +1,57 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
+use tokio::io::BufReader;
 use tracing_subscriber::EnvFilter;
 use ultron_core::{
-    chatbot::ChatBot,
+    Channel,
+    auto_response::AutoResponder,
+    chatbot::{
+        ChatBot,
+        terminal::{Repl, TerminalBot},
+    },
     command::CommandConsumer,
+    copypasta::Copypastas,
+    custom_command::CustomCommands,
     dice::DiceRoller,
+    dice::{
+        audit::RollAudit, history::RollHistory, limits::DiceLimits, macros::DiceMacros,
+        rollers::RollerConfig, sheet::CharacterSheets,
+    },
     event_processor::EventProcessor,
+    foundry::FoundryBridge,
     http_server::{self, AppState},
+    initiative::Initiative,
     io::read_file_to_string,
+    karma::{KarmaConsumer, KarmaStore},
     nlp::{ChatAgentConfig, LmChatAgent},
+    rate_limit::RateLimiter,
+    reload::{DEFAULT_RELOAD_INTERVAL, FileWatcher, Reload},
+    store::Store,
 };
 use ultron_discord::DiscordBotConfig;
+use ultron_irc::IrcBotConfig;
+use ultron_matrix::{MatrixAuth, MatrixBotConfig};
 
 #[derive(Clone, serde::Deserialize)]
 pub struct Secrets {
     pub discord_app_id: u64,
     pub discord_public_key: String,
     pub discord_token: String,
+    /// shared with the Foundry VTT module, see `--foundry-url`
+    #[serde(default)]
+    pub foundry_token: Option<String>,
+    /// the password for `--irc-server`, if it has one
+    #[serde(default)]
+    pub irc_password: Option<String>,
+    /// for `--matrix-homeserver`, or use `matrix_password`
+    #[serde(default)]
+    pub matrix_access_token: Option<String>,
+    /// for `--matrix-user`
+    #[serde(default)]
+    pub matrix_password: Option<String>,
 }
 
 /// CLI args
@@ -42,12 +75,125 @@ pub struct Cli {
     #[arg(short, long)]
     pub mcp_port: u16,
 
-    /// path to the secrets file
-    #[arg(short, long)]
-    pub secrets: PathBuf,
+    /// path to the secrets file, not needed with `--terminal`
+    #[arg(short, long, required_unless_present = "terminal")]
+    pub secrets: Option<PathBuf>,
+
+    /// chat with Ultron in the terminal instead of on Discord, see `/help` once it's running
+    #[arg(long)]
+    pub terminal: bool,
 
+    /// reloaded when it changes
     #[arg(long, default_value = "./prompts/ultron.md")]
     pub system_prompt: PathBuf,
+
+    /// a TOML file of extra copypastas, reloaded when it changes.
+    /// these win over the bundled ones, but not over `pasta edit`s
+    #[arg(long)]
+    pub copypasta_file: Option<PathBuf>,
+
+    /// a TOML file of keyword-triggered auto-responses, e.g. `./assets/auto_responses.toml`
+    #[arg(long)]
+    pub auto_responses: Option<PathBuf>,
+
+    /// how often to check `--system-prompt` and `--copypasta-file` for changes, in seconds
+    #[arg(
+        long,
+        default_value_t = DEFAULT_RELOAD_INTERVAL.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub reload_interval_secs: u64,
+
+    /// directory to keep persistent state in, e.g. karma scores
+    #[arg(long, default_value = "./data")]
+    pub data_dir: PathBuf,
+
+    #[command(flatten)]
+    pub dice_limits: DiceLimitArgs,
+
+    /// which dice roller to use: `secure`, `fast`, `fast:<seed>`, `max`, `min` or `scripted:<faces>`
+    #[arg(long, default_value = "secure")]
+    pub dice_roller: RollerConfig,
+
+    /// where the Foundry VTT bridge module listens, to mirror #dnd into a Foundry world.
+    /// needs `foundry_token` in the secrets file
+    #[arg(long)]
+    pub foundry_url: Option<String>,
+
+    /// a user allowed to `pasta add`, `pasta edit` and `pasta remove`, can be repeated.
+    /// these are platform IDs, like `discord:1234` or `matrix:@bob:example.org`,
+    /// since anyone can take a name on IRC or Foundry
+    #[arg(long = "pasta-editor")]
+    pub pasta_editors: Vec<String>,
+
+    /// an IRC server to hang out on as well as Discord, as `host:port`.
+    /// needs `irc_password` in the secrets file if the server has a password
+    #[arg(long)]
+    pub irc_server: Option<String>,
+
+    #[arg(long, default_value = ultron_irc::DEFAULT_NICKNAME)]
+    pub irc_nickname: String,
+
+    /// which IRC channel is which, e.g. `dnd=#dnd`, can be repeated.
+    /// see `ultron_irc::default_channels` for the defaults
+    #[arg(long = "irc-channel", value_parser = parse_channel_mapping)]
+    pub irc_channels: Vec<(Channel, String)>,
+
+    /// a Matrix homeserver to hang out on as well as Discord, e.g. `https://matrix.example.org`.
+    /// needs `matrix_access_token`, or `--matrix-user` and `matrix_password`, in the secrets file
+    #[arg(long)]
+    pub matrix_homeserver: Option<String>,
+
+    /// who to log in to Matrix as with `matrix_password`
+    #[arg(long)]
+    pub matrix_user: Option<String>,
+
+    /// which Matrix room is which, by id or alias, e.g. `dnd=#dnd:example.org`, can be repeated
+    #[arg(long = "matrix-room", value_parser = parse_channel_mapping)]
+    pub matrix_rooms: Vec<(Channel, String)>,
+}
+
+/// `<channel>=<name>`, e.g. `dnd=#dnd`
+fn parse_channel_mapping(value: &str) -> Result<(Channel, String), String> {
+    let Some((channel, name)) = value.split_once('=') else {
+        return Err(format!("expected `<channel>=<name>`, got `{value}`"));
+    };
+
+    match channel.parse::<Channel>() {
+        Ok(channel) => Ok((channel, name.to_string())),
+        Err(_) => Err(format!("`{channel}` isn't one of Ultron's channels")),
+    }
+}
+
+/// caps on dice rolls from Discord, HTTP and MCP
+#[derive(Debug, Clone, clap::Args)]
+pub struct DiceLimitArgs {
+    /// the most dice in a single command
+    #[arg(long, default_value_t = DiceLimits::default().max_dice)]
+    pub max_dice: usize,
+
+    /// the most sides on a single die
+    #[arg(long, default_value_t = DiceLimits::default().max_sides)]
+    pub max_dice_sides: usize,
+
+    /// how deeply a dice expression can nest
+    #[arg(long, default_value_t = DiceLimits::default().max_depth)]
+    pub max_dice_depth: usize,
+
+    /// the longest a rendered roll can be, in characters
+    #[arg(long, default_value_t = DiceLimits::default().max_output_len)]
+    pub max_roll_output: usize,
+}
+
+impl From<&DiceLimitArgs> for DiceLimits {
+    fn from(value: &DiceLimitArgs) -> Self {
+        Self {
+            max_dice: value.max_dice,
+            max_sides: value.max_dice_sides,
+            max_depth: value.max_dice_depth,
+            max_output_len: value.max_roll_output,
+        }
+    }
 }
 
 impl From<&Cli> for ChatAgentConfig {
@@ -63,48 +209,167 @@ impl From<&Cli> for ChatAgentConfig {
 
 /// panics if a subscriber was already registered.
 /// configure log levels with the RUST_LOG environment variable.
-fn setup_tracing(rust_log: &str) {
-    tracing_subscriber::fmt()
+/// logs go to stderr in the terminal, so they stay out of the conversation on stdout.
+fn setup_tracing(rust_log: &str, terminal: bool) {
+    let subscriber = tracing_subscriber::fmt()
         .json()
         .with_env_filter(EnvFilter::try_new(rust_log).expect("unable to build EnvFilter"))
         .with_line_number(true)
-        .with_current_span(true)
-        .init();
+        .with_current_span(true);
+
+    match terminal {
+        true => subscriber.with_writer(std::io::stderr).init(),
+        false => subscriber.init(),
+    }
+}
+
+async fn read_secrets(path: &Path) -> anyhow::Result<Secrets> {
+    let contents = read_file_to_string(path).await.inspect_err(|error| {
+        tracing::error!(
+            %error,
+            "unable to read secrets file from CLI args",
+        );
+    })?;
+
+    Ok(toml::from_str(&contents)?)
+}
+
+fn spawn_watcher<TTarget, TBot>(
+    watcher: Option<FileWatcher<TTarget>>,
+    bot: TBot,
+    reload_interval: Duration,
+) where
+    TTarget: Reload,
+    TBot: ChatBot + 'static,
+{
+    if let Some(watcher) = watcher {
+        tokio::spawn(watcher.watch(bot, reload_interval));
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
-    setup_tracing(&args.rust_log);
+    setup_tracing(&args.rust_log, args.terminal);
     tracing::info!("starting ultron");
 
-    let contents = read_file_to_string(&args.secrets)
-        .await
-        .inspect_err(|error| {
-            tracing::error!(
-                %error,
-                "unable to read secrets file from CLI args",
-            );
-        })?;
-
-    let secrets: Secrets = toml::from_str(&contents)?;
+    let secrets = match &args.secrets {
+        Some(path) => Some(read_secrets(path).await?),
+        None => None,
+    };
 
     tracing::info!("log level: {}", args.rust_log);
 
     tracing::info!("CLI args: {args:?}");
 
-    let event_processor =
-        EventProcessor::new().with_consumer(CommandConsumer::new(DiceRoller::default()));
+    let karma: KarmaStore = Store::open(args.data_dir.join("karma.json")).await?.into();
+    let custom_commands: CustomCommands = Store::open(args.data_dir.join("custom_commands.json"))
+        .await?
+        .into();
+    let dice_macros: DiceMacros = Store::open(args.data_dir.join("dice_macros.json"))
+        .await?
+        .into();
+    let roll_history = RollHistory::open(args.data_dir.join("roll_history.jsonl")).await?;
+    let character_sheets: CharacterSheets =
+        Store::open(args.data_dir.join("character_sheets.json"))
+            .await?
+            .into();
+    let initiative: Initiative = Store::open(args.data_dir.join("initiative.json"))
+        .await?
+        .into();
+    let roll_audit: RollAudit = Store::open(args.data_dir.join("roll_audit.json"))
+        .await?
+        .into();
+    let copypastas = Copypastas::from(Store::open(args.data_dir.join("copypastas.json")).await?)
+        .with_editors(args.pasta_editors.clone());
 
-    let event_processor: Arc<EventProcessor> = if let Ok(chat_agent) = LmChatAgent::load((&args).into()).await
+    let chat_agent = LmChatAgent::load((&args).into())
+        .await
         .inspect_err(|error| {
             tracing::error!(%error, "!!! unable to create chat agent !!!");
             tracing::error!(%error, "the server will continue to run, but LLM capabilities will be unavailable");
-        }) {
-        event_processor.with_consumer(chat_agent).into()
+        })
+        .ok();
+
+    let system_prompt_watcher = chat_agent
+        .clone()
+        .map(|chat_agent| FileWatcher::new(&args.system_prompt, chat_agent));
+    let copypasta_watcher = args
+        .copypasta_file
+        .as_ref()
+        .map(|path| FileWatcher::new(path, copypastas.clone()));
+
+    let rate_limiter = RateLimiter::new();
+
+    let dice_limits = DiceLimits::from(&args.dice_limits);
+
+    let command_consumer =
+        CommandConsumer::new(DiceRoller::from(&args.dice_roller).with_limits(dice_limits))
+            .with_karma(karma.clone())
+            .with_custom_commands(custom_commands)
+            .with_rate_limiter(rate_limiter.clone())
+            .with_dice_macros(dice_macros.clone())
+            .with_roll_history(roll_history.clone())
+            .with_character_sheets(character_sheets.clone())
+            .with_initiative(initiative)
+            .with_roll_audit(roll_audit.with_roller(args.dice_roller.clone()))
+            .with_copypastas(copypastas.clone());
+
+    let foundry_token = secrets
+        .as_ref()
+        .and_then(|secrets| secrets.foundry_token.as_ref());
+    let foundry = match (&args.foundry_url, foundry_token) {
+        (Some(url), Some(token)) => Some(FoundryBridge::new(url, token)),
+        (Some(_), None) => {
+            tracing::warn!("--foundry-url is set but there's no foundry_token, not bridging");
+            None
+        }
+        (None, _) => None,
+    };
+
+    let event_processor = EventProcessor::new().with_consumer(KarmaConsumer::new(karma));
+
+    let event_processor = match &args.auto_responses {
+        Some(path) => event_processor.with_consumer(
+            AutoResponder::from_toml_file(path)
+                .await?
+                .with_copypastas(copypastas.clone()),
+        ),
+        None => event_processor,
+    };
+
+    let event_processor = match &foundry {
+        Some(foundry) => event_processor.with_listener(foundry.clone()),
+        None => event_processor,
+    };
+
+    let event_processor: Arc<EventProcessor> = if let Some(chat_agent) = chat_agent {
+        event_processor
+            .with_consumer(command_consumer.with_language_model(chat_agent.clone()))
+            .with_consumer(chat_agent)
+            .into()
     } else {
-        event_processor.into()
+        event_processor.with_consumer(command_consumer).into()
+    };
+
+    let reload_interval = Duration::from_secs(args.reload_interval_secs);
+
+    if args.terminal {
+        let bot = TerminalBot::stdout();
+        spawn_watcher(system_prompt_watcher, bot.clone(), reload_interval);
+        spawn_watcher(copypasta_watcher, bot.clone(), reload_interval);
+
+        Repl::new(event_processor, bot)
+            .run(BufReader::new(tokio::io::stdin()))
+            .await?;
+
+        tracing::info!("bye");
+        return Ok(());
+    }
+
+    let Some(secrets) = secrets else {
+        anyhow::bail!("--secrets is required to connect to Discord");
     };
 
     let discord_config = DiscordBotConfig::builder()
@@ -116,6 +381,48 @@ async fn main() -> anyhow::Result<()> {
 
     let bot = Arc::new(discord_config.run().await?);
 
+    let irc_bot = match &args.irc_server {
+        Some(server) => {
+            let irc_channels = (!args.irc_channels.is_empty()).then(|| args.irc_channels.clone());
+            let irc_config = IrcBotConfig::builder()
+                .server(server)
+                .nickname(&args.irc_nickname)
+                .maybe_password(secrets.irc_password)
+                .maybe_channels(irc_channels)
+                .event_processor(event_processor.clone())
+                .build();
+            Some(irc_config.run().await?)
+        }
+        None => None,
+    };
+
+    let matrix_bot = match &args.matrix_homeserver {
+        Some(homeserver) => {
+            let auth = match (
+                secrets.matrix_access_token,
+                &args.matrix_user,
+                secrets.matrix_password,
+            ) {
+                (Some(access_token), _, _) => MatrixAuth::AccessToken(access_token),
+                (None, Some(user), Some(password)) => MatrixAuth::Password {
+                    user: user.clone(),
+                    password,
+                },
+                _ => anyhow::bail!(
+                    "--matrix-homeserver needs `matrix_access_token`, or --matrix-user and `matrix_password`"
+                ),
+            };
+            let matrix_config = MatrixBotConfig::builder()
+                .homeserver(homeserver)
+                .auth(auth)
+                .rooms(args.matrix_rooms.clone())
+                .event_processor(event_processor.clone())
+                .build();
+            Some(matrix_config.run().await?)
+        }
+        None => None,
+    };
+
     let hostname = read_file_to_string("/etc/hostname")
         .await?
         .trim()
@@ -125,16 +432,32 @@ async fn main() -> anyhow::Result<()> {
 
     bot.debug(&startup_message).await?;
 
+    spawn_watcher(system_prompt_watcher, bot.as_ref().clone(), reload_interval);
+    spawn_watcher(copypasta_watcher, bot.as_ref().clone(), reload_interval);
+
     let discord_thread_bot = bot.clone();
     let server_thread_bot = bot.clone();
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             tracing::info!("received ctrl-c, shutting down");
             discord_thread_bot.shutdown().await?;
+            if let Some(irc_bot) = &irc_bot {
+                irc_bot.shutdown().await?;
+            }
+            if let Some(matrix_bot) = &matrix_bot {
+                matrix_bot.shutdown().await?;
+            }
         }
         result = http_server::serve(args.port, AppState {
             event_processor,
             chat_bot: server_thread_bot.clone(),
+            rate_limiter,
+            dice_macros,
+            roll_history,
+            character_sheets,
+            dice_limits,
+            roller: args.dice_roller.clone(),
+            foundry,
         }) => {
             tracing::warn!("http server shut down spontaneously: {:?}", result);
         }
@@ -164,13 +487,118 @@ mod tests {
             "secrets.toml",
             "--system-prompt",
             "./prompts/ultron.md",
+            "--data-dir",
+            "/var/lib/ultron",
+            "--max-dice",
+            "50",
+            "--dice-roller",
+            "fast:42",
+            "--pasta-editor",
+            "discord:1234",
+            "--pasta-editor",
+            "matrix:@bob:example.org",
+            "--copypasta-file",
+            "./pastas.toml",
+            "--auto-responses",
+            "./assets/auto_responses.toml",
+            "--irc-server",
+            "irc.example.com:6667",
+            "--irc-channel",
+            "dnd=#dungeon",
+            "--irc-channel",
+            "debug=#ultron",
+            "--matrix-homeserver",
+            "https://matrix.example.com",
+            "--matrix-room",
+            "dnd=#dnd:example.com",
         ]);
 
         assert_eq!(args.port, 8080);
         assert_eq!(args.rust_log, "debug");
         assert_eq!(args.lm_endpoint, "https://example.com/llm/");
         assert_eq!(args.mcp_port, 5000);
-        assert_eq!(args.secrets, PathBuf::from("secrets.toml"));
+        assert_eq!(args.secrets, Some(PathBuf::from("secrets.toml")));
+        assert!(!args.terminal);
+        assert_eq!(args.irc_server.as_deref(), Some("irc.example.com:6667"));
+        assert_eq!(args.irc_nickname, "ultron");
+        assert_eq!(
+            args.irc_channels,
+            vec![
+                (Channel::Dnd, "#dungeon".to_string()),
+                (Channel::Debug, "#ultron".to_string())
+            ]
+        );
+        assert_eq!(
+            args.matrix_homeserver.as_deref(),
+            Some("https://matrix.example.com")
+        );
+        assert_eq!(
+            args.matrix_rooms,
+            vec![(Channel::Dnd, "#dnd:example.com".to_string())]
+        );
+        assert!(parse_channel_mapping("tavern=#tavern").is_err());
+        assert!(parse_channel_mapping("#dnd").is_err());
         assert_eq!(args.system_prompt, PathBuf::from("./prompts/ultron.md"));
+        assert_eq!(args.data_dir, PathBuf::from("/var/lib/ultron"));
+        assert_eq!(args.dice_limits.max_dice, 50);
+        assert_eq!(
+            args.dice_limits.max_dice_sides,
+            DiceLimits::default().max_sides
+        );
+        assert_eq!(args.dice_roller, RollerConfig::from(42));
+        assert_eq!(
+            args.pasta_editors,
+            vec!["discord:1234", "matrix:@bob:example.org"]
+        );
+        assert_eq!(args.copypasta_file, Some(PathBuf::from("./pastas.toml")));
+        assert_eq!(args.reload_interval_secs, 5);
+        assert_eq!(
+            args.auto_responses,
+            Some(PathBuf::from("./assets/auto_responses.toml"))
+        );
+    }
+
+    #[test]
+    fn reload_interval_cant_be_zero() {
+        let parse = |interval: &str| {
+            Cli::try_parse_from([
+                "ultron",
+                "--port",
+                "8080",
+                "--mcp-port",
+                "5000",
+                "--secrets",
+                "secrets.toml",
+                "--reload-interval-secs",
+                interval,
+            ])
+        };
+
+        assert!(matches!(
+            parse("0").map_err(|error| error.kind()),
+            Err(clap::error::ErrorKind::ValueValidation)
+        ));
+        assert_eq!(parse("1").expect("should parse").reload_interval_secs, 1);
+    }
+
+    #[test]
+    fn terminal_doesnt_need_secrets() {
+        let args = Cli::parse_from([
+            "ultron",
+            "--port",
+            "8080",
+            "--mcp-port",
+            "5000",
+            "--terminal",
+        ]);
+        assert!(args.terminal);
+        assert_eq!(args.secrets, None);
+
+        let error = Cli::try_parse_from(["ultron", "--port", "8080", "--mcp-port", "5000"])
+            .expect_err("discord needs secrets");
+        assert_eq!(
+            error.kind(),
+            clap::error::ErrorKind::MissingRequiredArgument
+        );
     }
 }
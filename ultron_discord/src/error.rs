@@ -28,6 +28,11 @@ pub enum DiscordBotError {
     #[error(transparent)]
     Serenity(#[from] serenity::Error),
 
+    #[error("not a Discord message id, `{id}`")]
+    InvalidMessageId {
+        id: ultron_core::chatbot::outgoing::MessageRef,
+    },
+
     #[error("content is too long")]
     ContentTooLong,
 
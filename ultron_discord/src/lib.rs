@@ -3,14 +3,22 @@ use extend::ext;
 use serde::Deserialize;
 use serenity::{
     Client,
-    all::{ChannelId, Context, EventHandler, GatewayIntents, Message, Typing, UserId},
+    all::{
+        ChannelId, Context, CreateAllowedMentions, CreateAttachment, CreateEmbed,
+        CreateEmbedFooter, CreateMessage, EventHandler, GatewayIntents, Message, MessageId,
+        ReactionType, Typing, UserId,
+    },
     http::Http,
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, num::NonZeroU64, sync::Arc};
 use tokio::task::JoinHandle;
 use ultron_core::{
     Channel, Response, User,
-    chatbot::{ChatBot, ChatInput},
+    card::Card,
+    chatbot::{
+        ChatBot, ChatInput,
+        outgoing::{Mentions, OutgoingMessage},
+    },
     command::CommandParseError,
     dice::HELP_MESSAGE,
     event_processor::{Event, EventError, EventProcessor, EventType},
@@ -49,6 +57,16 @@ const ULTRON_USER_ID_STR: &str = "<@&777660234842898483>";
 
 const DISCORD_MAX_MESSAGE_LENGTH: usize = 2000;
 
+/// <https://discord.com/developers/docs/resources/message#embed-object-embed-limits>
+const DISCORD_MAX_EMBED_TITLE_LENGTH: usize = 256;
+const DISCORD_MAX_EMBED_DESCRIPTION_LENGTH: usize = 4096;
+const DISCORD_MAX_EMBED_FIELDS: usize = 25;
+const DISCORD_MAX_EMBED_FIELD_NAME_LENGTH: usize = 256;
+const DISCORD_MAX_EMBED_FIELD_VALUE_LENGTH: usize = 1024;
+const DISCORD_MAX_EMBED_FOOTER_LENGTH: usize = 2048;
+const DISCORD_MAX_EMBED_TOTAL_LENGTH: usize = 6000;
+const DISCORD_MAX_EMBEDS_PER_MESSAGE: usize = 10;
+
 #[derive(Builder, Debug, Clone)]
 pub struct DiscordBotConfig {
     #[builder(into)]
@@ -141,6 +159,10 @@ impl ChatBot for DiscordBot {
     type Error = DiscordBotError;
 
     async fn send_message(&self, channel: Channel, message: &str) -> DiscordBotResult<()> {
+        self.send(channel, &OutgoingMessage::from(message)).await
+    }
+
+    async fn send(&self, channel: Channel, message: &OutgoingMessage) -> DiscordBotResult<()> {
         tracing::debug!(channel = ?channel, "sending message");
 
         let id: ChannelId = *self
@@ -148,13 +170,7 @@ impl ChatBot for DiscordBot {
             .by_name(&channel)
             .ok_or(DiscordBotError::ChannelNotConfigured { channel })?;
 
-        let message_chunks = split_message(message, DISCORD_MAX_MESSAGE_LENGTH);
-
-        for chunk in message_chunks {
-            id.say(&self.http, chunk).await?;
-        }
-
-        Ok(())
+        send_outgoing(&self.http, id, message).await
     }
 }
 
@@ -225,6 +241,7 @@ impl Handler {
             .user(user)
             .content(msg.content.clone())
             .channel(*channel)
+            .identity(format!("discord:{}", msg.author.id))
             .build();
 
         let event: Event = Event::new(&chat_input, event_type)?;
@@ -249,7 +266,7 @@ impl Handler {
         tracing::debug!(?results, "processing event result");
 
         for result in results {
-            self.handle_response(&ctx, msg.channel_id, result).await?;
+            self.handle_response(&ctx, &msg, result).await?;
         }
 
         Ok(())
@@ -266,6 +283,14 @@ impl Handler {
                 CommandParseError::MissingCommand(error_msg) => {
                     Some(format!("ya blew it: {}\n\n{}", error_msg, HELP_MESSAGE))
                 }
+                CommandParseError::MissingArgument { command, argument } => Some(format!(
+                    "ya blew it: `{command}` needs a `{argument}`\n\n{HELP_MESSAGE}"
+                )),
+                error @ (CommandParseError::EmptyPipelineStage(_)
+                | CommandParseError::PipelineTooLong { .. }
+                | CommandParseError::InvalidArgument { .. }) => {
+                    Some(format!("ya blew it: {error}"))
+                }
                 CommandParseError::UndefinedCommand { command, args } => Some(format!(
                     "ya blew it: undefined command '{}' with args {:?}\n\n{}",
                     command, args, HELP_MESSAGE
@@ -279,6 +304,22 @@ impl Handler {
                 "ya blew it: {}\n\n{}",
                 dice_roll_error, HELP_MESSAGE
             )),
+            EventError::DiceMacro(dice_macro_error) => Some(format!(
+                "ya blew it: {}\n\n{}",
+                dice_macro_error, HELP_MESSAGE
+            )),
+            EventError::Sheet(sheet_error) => Some(format!("ya blew it: {sheet_error}")),
+            EventError::Initiative(initiative_error) => {
+                Some(format!("ya blew it: {initiative_error}"))
+            }
+            EventError::Audit(audit_error) => Some(format!("ya blew it: {audit_error}")),
+            EventError::Copypasta(copypasta_error) => {
+                Some(format!("ya blew it: {copypasta_error}"))
+            }
+            EventError::Storage(storage_error) => {
+                Some(format!("memory banks corrupted: {storage_error}"))
+            }
+            EventError::Task(task_error) => Some(format!("brain hurty: {task_error}")),
         };
 
         if let Some(error_message) = error_message {
@@ -288,35 +329,227 @@ impl Handler {
         Ok(())
     }
 
+    /// reply to `message` with a response
     async fn handle_response(
         &self,
         context: &Context,
-        channel: ChannelId,
+        message: &Message,
         response: Response,
     ) -> DiscordBotResult<()> {
-        let response_chunks = match response {
-            Response::PlainChat(message) => {
-                tracing::info!("handling plain chat response: {message}");
+        let outgoing = OutgoingMessage::from(response).with_reply_to(message.id.get());
 
-                split_message(&message, DISCORD_MAX_MESSAGE_LENGTH)
-            }
-            Response::Bot(bot_message) => {
-                let message: String = bot_message.render_without_thinking_parts();
+        if outgoing.is_empty() {
+            tracing::info!("response ignored");
+            return Ok(());
+        }
 
-                split_message(&message, DISCORD_MAX_MESSAGE_LENGTH)
-            }
-            Response::Ignored => {
-                tracing::info!("response ignored");
-                vec![]
-            }
+        tracing::info!(?outgoing, "handling response");
+        send_outgoing(&context.http, message.channel_id, &outgoing).await
+    }
+}
+
+/// send an [`OutgoingMessage`], split up to fit Discord's limits.
+/// the first message replies to [`OutgoingMessage::reply_to`],
+/// and embeds and attachments go along with the last of the text.
+/// cards too big for an embed are sent as text,
+/// and so are reactions when there's no message to react to
+async fn send_outgoing(
+    http: &Arc<Http>,
+    channel: ChannelId,
+    message: &OutgoingMessage,
+) -> DiscordBotResult<()> {
+    let reply_to = message
+        .reply_to
+        .as_ref()
+        .map(|id| {
+            id.0.parse::<NonZeroU64>()
+                .map(MessageId::from)
+                .map_err(|_| DiscordBotError::InvalidMessageId { id: id.clone() })
+        })
+        .transpose()?;
+
+    let (embeds, too_big): (Vec<&Card>, Vec<&Card>) = message
+        .embeds
+        .iter()
+        .partition(|card| card_fits_in_embed(card));
+    if !too_big.is_empty() {
+        tracing::debug!("card is too big for an embed, sending as text");
+    }
+
+    let content = (!message.content.is_empty()).then(|| message.content.clone());
+    let reactions =
+        (reply_to.is_none() && !message.reactions.is_empty()).then(|| message.reactions.join(" "));
+    let text = content
+        .into_iter()
+        .chain(too_big.iter().map(|card| card.to_markdown()))
+        .chain(reactions)
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let mut builders: Vec<CreateMessage> = split_message(&text, DISCORD_MAX_MESSAGE_LENGTH)
+        .into_iter()
+        .map(|chunk| CreateMessage::new().content(chunk))
+        .collect();
+
+    let mut batches = embed_batches(&embeds).into_iter();
+    let first_batch = batches.next();
+    if first_batch.is_some() || !message.attachments.is_empty() {
+        let last = match builders.pop() {
+            Some(last) => last,
+            None => CreateMessage::new(),
+        };
+        let files = message.attachments.iter().map(|attachment| {
+            CreateAttachment::bytes(attachment.data.clone(), &attachment.filename)
+        });
+        builders.push(
+            last.embeds(
+                first_batch
+                    .into_iter()
+                    .flatten()
+                    .map(card_to_embed)
+                    .collect(),
+            )
+            .files(files),
+        );
+    }
+    builders.extend(
+        batches.map(|batch| {
+            CreateMessage::new().embeds(batch.into_iter().map(card_to_embed).collect())
+        }),
+    );
+
+    for (index, builder) in builders.into_iter().enumerate() {
+        let builder = builder.allowed_mentions(allowed_mentions(&message.mentions));
+        let builder = match reply_to {
+            Some(reply_to) if index == 0 => builder.reference_message((channel, reply_to)),
+            _ => builder,
         };
+        channel.send_message(http, builder).await?;
+    }
 
-        for chunk in response_chunks {
-            channel.say(&context.http, chunk).await?;
+    if let Some(reply_to) = reply_to {
+        for emoji in &message.reactions {
+            channel
+                .create_reaction(http, reply_to, ReactionType::Unicode(emoji.clone()))
+                .await?;
         }
+    }
 
-        Ok(())
+    Ok(())
+}
+
+fn allowed_mentions(mentions: &Mentions) -> CreateAllowedMentions {
+    CreateAllowedMentions::new()
+        .all_users(mentions.users)
+        .all_roles(mentions.roles)
+        .everyone(mentions.everyone)
+        .replied_user(mentions.replied_user)
+}
+
+/// group cards that fit in embeds into as few messages as Discord allows
+fn embed_batches<'card>(cards: &[&'card Card]) -> Vec<Vec<&'card Card>> {
+    let mut batches: Vec<Vec<&Card>> = Vec::new();
+    let mut batch_length = 0;
+
+    for card in cards {
+        let length = embed_length(card);
+        match batches.last_mut() {
+            Some(batch)
+                if batch.len() < DISCORD_MAX_EMBEDS_PER_MESSAGE
+                    && batch_length + length <= DISCORD_MAX_EMBED_TOTAL_LENGTH =>
+            {
+                batch.push(card);
+                batch_length += length;
+            }
+            _ => {
+                batches.push(vec![card]);
+                batch_length = length;
+            }
+        }
+    }
+
+    batches
+}
+
+fn card_to_embed(card: &Card) -> CreateEmbed {
+    let mut embed = CreateEmbed::new();
+
+    if let Some(title) = &card.title {
+        embed = embed.title(title);
+    }
+
+    // embeds don't have a place for code blocks,
+    // so they go at the end of the description
+    let description = card
+        .description
+        .iter()
+        .cloned()
+        .chain(card.code_blocks.iter().map(ToString::to_string))
+        .collect::<Vec<String>>()
+        .join("\n");
+    if !description.is_empty() {
+        embed = embed.description(description);
     }
+
+    embed = embed.fields(
+        card.fields
+            .iter()
+            .map(|field| (field.name.clone(), field.value.clone(), field.inline)),
+    );
+
+    if let Some(footer) = &card.footer {
+        embed = embed.footer(CreateEmbedFooter::new(footer));
+    }
+
+    if let Some(color) = card.color {
+        embed = embed.color(color.0);
+    }
+
+    embed
+}
+
+fn card_fits_in_embed(card: &Card) -> bool {
+    let length = |text: &Option<String>| text.as_ref().map_or(0, |text| text.chars().count());
+
+    let title = length(&card.title);
+    let description = length(&card.description)
+        + card
+            .code_blocks
+            .iter()
+            .map(|block| block.to_string().chars().count() + 1)
+            .sum::<usize>();
+    let footer = length(&card.footer);
+    let fields_fit = card.fields.len() <= DISCORD_MAX_EMBED_FIELDS
+        && card.fields.iter().all(|field| {
+            field.name.chars().count() <= DISCORD_MAX_EMBED_FIELD_NAME_LENGTH
+                && field.value.chars().count() <= DISCORD_MAX_EMBED_FIELD_VALUE_LENGTH
+        });
+    let total = embed_length(card);
+
+    title <= DISCORD_MAX_EMBED_TITLE_LENGTH
+        && description <= DISCORD_MAX_EMBED_DESCRIPTION_LENGTH
+        && footer <= DISCORD_MAX_EMBED_FOOTER_LENGTH
+        && fields_fit
+        && total <= DISCORD_MAX_EMBED_TOTAL_LENGTH
+}
+
+/// how many characters of an embed count towards [`DISCORD_MAX_EMBED_TOTAL_LENGTH`]
+fn embed_length(card: &Card) -> usize {
+    let length = |text: &Option<String>| text.as_ref().map_or(0, |text| text.chars().count());
+
+    length(&card.title)
+        + length(&card.description)
+        + card
+            .code_blocks
+            .iter()
+            .map(|block| block.to_string().chars().count() + 1)
+            .sum::<usize>()
+        + length(&card.footer)
+        + card
+            .fields
+            .iter()
+            .map(|field| field.name.chars().count() + field.value.chars().count())
+            .sum::<usize>()
 }
 
 #[ext]
@@ -402,6 +635,48 @@ impl<'msg> SplitMessageIterator<'msg> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn big_cards_do_not_fit_in_embeds() {
+        let card = Card::builder()
+            .title("small")
+            .description("a card that fits")
+            .build();
+        assert!(card_fits_in_embed(&card));
+
+        let card = Card::builder()
+            .description("a".repeat(DISCORD_MAX_EMBED_DESCRIPTION_LENGTH + 1))
+            .build();
+        assert!(!card_fits_in_embed(&card));
+
+        let card = Card::builder()
+            .fields(vec![
+                ultron_core::card::CardField::new("field", "value");
+                DISCORD_MAX_EMBED_FIELDS + 1
+            ])
+            .build();
+        assert!(!card_fits_in_embed(&card));
+    }
+
+    #[test]
+    fn embeds_are_batched_within_limits() {
+        let small = Card::builder().description("a".repeat(100)).build();
+        let big = Card::builder()
+            .description("a".repeat(DISCORD_MAX_EMBED_DESCRIPTION_LENGTH))
+            .build();
+
+        let cards = vec![&small; DISCORD_MAX_EMBEDS_PER_MESSAGE + 1];
+        let batches = embed_batches(&cards);
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].len(), DISCORD_MAX_EMBEDS_PER_MESSAGE);
+
+        let batches = embed_batches(&[&big, &big, &small]);
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![1, 2],
+            "two big embeds don't fit in one message"
+        );
+    }
+
     #[test]
     fn split_message_works() {
         let message = "This is a test message that should be split into multiple chunks.";
@@ -0,0 +1,34 @@
+pub type IrcBotResult<T> = Result<T, IrcBotError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum IrcBotError {
+    #[error("channel not configured, `{channel}`")]
+    ChannelNotConfigured { channel: ultron_core::Channel },
+
+    #[error("channel not recognized, `{name}`")]
+    ChannelNotRecognized { name: String },
+
+    #[error("not an IRC channel name, `{name}`. it should look like `#ultron`")]
+    InvalidChannelName { name: String },
+
+    #[error(transparent)]
+    CommandParse(#[from] ultron_core::command::CommandParseError),
+
+    #[error("disconnected from the IRC server")]
+    Disconnected,
+
+    #[error("too many messages waiting to go out, try again in a bit")]
+    Backlogged,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    JoinError(#[from] tokio::task::JoinError),
+}
+
+impl From<IrcBotError> for ultron_core::error::Error {
+    fn from(value: IrcBotError) -> Self {
+        ultron_core::error::Error::ChatBot(Box::new(value))
+    }
+}
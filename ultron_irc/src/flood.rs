@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// how fast Ultron talks, so the server doesn't kick it for flooding.
+/// a burst of lines goes out right away, after that it's one line per interval
+/// until it's been quiet for a while
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloodControl {
+    pub burst: u32,
+    pub interval: Duration,
+}
+
+impl Default for FloodControl {
+    /// about what most servers put up with
+    fn default() -> Self {
+        Self {
+            burst: 4,
+            interval: Duration::from_secs(2),
+        }
+    }
+}
+
+/// keeps track of how far ahead of the [`FloodControl`] we are
+#[derive(Debug)]
+pub(crate) struct FloodGate {
+    control: FloodControl,
+    /// when the last line sent would be paid off
+    paid_off: Instant,
+}
+
+impl FloodGate {
+    pub fn new(control: FloodControl, now: Instant) -> Self {
+        Self {
+            control,
+            paid_off: now,
+        }
+    }
+
+    /// how long to wait before sending another line at `now`
+    pub fn delay(&mut self, now: Instant) -> Duration {
+        let paid_off = self.paid_off.max(now);
+        let allowance = self.control.interval * self.control.burst.saturating_sub(1);
+        self.paid_off = paid_off + self.control.interval;
+
+        (paid_off - now).saturating_sub(allowance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_then_slows_down() {
+        let control = FloodControl {
+            burst: 3,
+            interval: Duration::from_secs(2),
+        };
+        let start = Instant::now();
+        let mut gate = FloodGate::new(control, start);
+
+        let delays: Vec<_> = (0..5).map(|_| gate.delay(start).as_secs()).collect();
+        assert_eq!(delays, vec![0, 0, 0, 2, 4]);
+
+        // after a long break the whole burst is available again
+        let later = start + Duration::from_secs(60);
+        let delays: Vec<_> = (0..4).map(|_| gate.delay(later).as_secs()).collect();
+        assert_eq!(delays, vec![0, 0, 0, 2]);
+    }
+}
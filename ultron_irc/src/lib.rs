@@ -0,0 +1,763 @@
+//! an IRC [`ChatBot`], so Ultron can hang out in IRC channels as well as on Discord.
+//!
+//! IRC channels map to [`Channel`]s, and messages addressed to Ultron
+//! like `ultron: what's up` go to the language model.
+//! everything else goes through the [`EventProcessor`] as plain chat, so commands just work.
+//!
+//! responses are split into lines that fit in an IRC message,
+//! and sent no faster than the [`FloodControl`] allows.
+//! if the server goes away, Ultron keeps trying to get back in, waiting longer each time.
+//! only plain-text connections for now, use a TLS tunnel for servers that insist.
+use bon::Builder;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::{
+        TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender, error::TrySendError},
+    time::Instant,
+};
+use ultron_core::{
+    Channel, User,
+    chatbot::{ChatBot, ChatInput, outgoing::OutgoingMessage},
+    command::CommandParseError,
+    event_processor::{Event, EventError, EventProcessor, EventType},
+};
+
+use crate::{
+    error::{IrcBotError, IrcBotResult},
+    flood::FloodGate,
+    message::{IrcMessage, split_lines},
+};
+
+pub use crate::flood::FloodControl;
+
+mod error;
+mod flood;
+mod message;
+
+pub const DEFAULT_NICKNAME: &str = "ultron";
+
+const DEFAULT_CHANNELS: &[(Channel, &str)] = &[
+    (Channel::Debug, "#ultron-debug"),
+    (Channel::Psa, "#general"),
+    (Channel::Dnd, "#dnd"),
+    (Channel::FunZoneBots, "#bots"),
+    (Channel::FunZoneStream, "#stream"),
+];
+
+/// the most an IRC line can be, including the `\r\n`
+const IRC_MAX_LINE_LENGTH: usize = 512;
+
+/// room for the `:nick!user@host ` the server puts in front of a message when it relays it,
+/// which counts towards [`IRC_MAX_LINE_LENGTH`] for whoever receives it
+const IRC_RELAY_PREFIX_LENGTH: usize = 100;
+
+/// how many messages can wait for the [`FloodControl`] before new ones are turned away
+const MAX_QUEUED_LINES: usize = 50;
+
+/// how long to wait before reconnecting the first time
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// the reconnect delay doubles after every failure, up to this
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5 * 60);
+
+/// a connection that lasted this long was a good one, so the reconnect delay starts over
+const STABLE_CONNECTION: Duration = Duration::from_secs(60);
+
+/// which IRC channel is which [`Channel`] unless configured otherwise
+pub fn default_channels() -> Vec<(Channel, String)> {
+    DEFAULT_CHANNELS
+        .iter()
+        .map(|(channel, name)| (*channel, name.to_string()))
+        .collect()
+}
+
+#[derive(Builder, Debug, Clone)]
+pub struct IrcBotConfig {
+    /// `host:port`
+    #[builder(into)]
+    server: String,
+    #[builder(into, default = DEFAULT_NICKNAME.to_string())]
+    nickname: String,
+    /// the server password, if it has one
+    #[builder(into)]
+    password: Option<String>,
+    /// the IRC channels to join, and which [`Channel`] each one is
+    #[builder(default = default_channels())]
+    channels: Vec<(Channel, String)>,
+    #[builder(default)]
+    flood_control: FloodControl,
+    /// how long to wait before reconnecting, doubling after every failure
+    #[builder(default = DEFAULT_RECONNECT_DELAY)]
+    reconnect_delay: Duration,
+    event_processor: Arc<EventProcessor>,
+}
+
+impl IrcBotConfig {
+    /// connect and start registering. the channels are joined once the server welcomes us.
+    /// only the first connection has to work, after that it reconnects on its own
+    pub async fn run(self) -> IrcBotResult<IrcBot> {
+        let channels = Arc::new(Channels::new(&self.channels)?);
+
+        let stream = TcpStream::connect(&self.server).await?;
+        tracing::info!(server = %self.server, "connected to IRC server");
+
+        let (priority, priority_lines) = mpsc::unbounded_channel();
+        let (queue, queued_lines) = mpsc::channel(MAX_QUEUED_LINES);
+        let outbox = Outbox { priority, queue };
+        let quitting = Arc::new(AtomicBool::new(false));
+
+        let connection = Connection {
+            config: self,
+            channels: channels.clone(),
+            outbox: outbox.clone(),
+            priority_lines,
+            queued_lines,
+            quitting: quitting.clone(),
+        };
+        tokio::spawn(connection.stay_connected(stream));
+
+        Ok(IrcBot {
+            outbox,
+            channels,
+            quitting,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IrcBot {
+    outbox: Outbox,
+    channels: Arc<Channels>,
+    /// set on [`IrcBot::shutdown`], so hanging up doesn't mean reconnecting
+    quitting: Arc<AtomicBool>,
+}
+
+impl ChatBot for IrcBot {
+    type Error = IrcBotError;
+
+    async fn send_message(&self, channel: Channel, message: &str) -> IrcBotResult<()> {
+        tracing::debug!(channel = ?channel, "sending message");
+
+        let name = self
+            .channels
+            .by_channel(&channel)
+            .ok_or(IrcBotError::ChannelNotConfigured { channel })?;
+
+        self.outbox.privmsg(name, message)
+    }
+}
+
+impl IrcBot {
+    pub async fn shutdown(&self) -> IrcBotResult<()> {
+        self.quitting.store(true, Ordering::Relaxed);
+        self.outbox.now("QUIT :ultron out".to_string())?;
+        Ok(())
+    }
+}
+
+/// the connection to the server, made again whenever it drops
+struct Connection {
+    config: IrcBotConfig,
+    channels: Arc<Channels>,
+    outbox: Outbox,
+    priority_lines: UnboundedReceiver<String>,
+    queued_lines: Receiver<String>,
+    quitting: Arc<AtomicBool>,
+}
+
+impl Connection {
+    async fn stay_connected(mut self, mut stream: TcpStream) {
+        let mut delay = self.config.reconnect_delay;
+
+        loop {
+            let connected_at = Instant::now();
+            let error = self.talk(stream).await;
+            if self.quitting.load(Ordering::Relaxed) {
+                return;
+            }
+
+            tracing::warn!(%error, "lost the connection to the IRC server");
+            if connected_at.elapsed() >= STABLE_CONNECTION {
+                delay = self.config.reconnect_delay;
+            }
+
+            stream = loop {
+                tracing::info!(?delay, "reconnecting to IRC server");
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+
+                match TcpStream::connect(&self.config.server).await {
+                    Ok(stream) => break stream,
+                    Err(error) => tracing::warn!(%error, "couldn't reconnect to IRC server"),
+                }
+            };
+            tracing::info!(server = %self.config.server, "reconnected to IRC server");
+        }
+    }
+
+    /// register, then read and write lines until the connection drops
+    async fn talk(&mut self, stream: TcpStream) -> IrcBotError {
+        let (reader, writer) = stream.into_split();
+
+        // whatever didn't make it out was meant for the last connection
+        while self.priority_lines.try_recv().is_ok() {}
+        while self.queued_lines.try_recv().is_ok() {}
+
+        let nickname = &self.config.nickname;
+        let registration = self
+            .config
+            .password
+            .iter()
+            .map(|password| format!("PASS {password}"))
+            .chain([
+                format!("NICK {nickname}"),
+                format!("USER {nickname} 0 * :Ultron"),
+            ]);
+        for line in registration {
+            if let Err(error) = self.outbox.now(line) {
+                return error;
+            }
+        }
+
+        let handler = Handler {
+            event_processor: self.config.event_processor.clone(),
+            channels: self.channels.clone(),
+            outbox: self.outbox.clone(),
+            nickname: nickname.clone(),
+        };
+
+        let result = tokio::select! {
+            result = handler.read(reader) => result,
+            result = write_lines(
+                writer,
+                &mut self.priority_lines,
+                &mut self.queued_lines,
+                self.config.flood_control,
+            ) => result,
+        };
+
+        result.err().unwrap_or(IrcBotError::Disconnected)
+    }
+}
+
+/// lines waiting to be written to the server
+#[derive(Debug, Clone)]
+struct Outbox {
+    /// protocol lines like `PONG` that skip the [`FloodControl`]
+    priority: UnboundedSender<String>,
+    /// messages, which wait their turn
+    queue: Sender<String>,
+}
+
+impl Outbox {
+    fn now(&self, line: String) -> IrcBotResult<()> {
+        self.priority
+            .send(line)
+            .map_err(|_| IrcBotError::Disconnected)
+    }
+
+    /// send `text` to `target`, split up into as many messages as it takes
+    fn privmsg(&self, target: &str, text: &str) -> IrcBotResult<()> {
+        let overhead = format!("PRIVMSG {target} :\r\n").len() + IRC_RELAY_PREFIX_LENGTH;
+
+        for line in split_lines(text, IRC_MAX_LINE_LENGTH.saturating_sub(overhead)) {
+            self.queue
+                .try_send(format!("PRIVMSG {target} :{line}"))
+                .map_err(|error| match error {
+                    TrySendError::Full(_) => IrcBotError::Backlogged,
+                    TrySendError::Closed(_) => IrcBotError::Disconnected,
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn write_lines(
+    mut writer: OwnedWriteHalf,
+    priority: &mut UnboundedReceiver<String>,
+    queue: &mut Receiver<String>,
+    flood_control: FloodControl,
+) -> IrcBotResult<()> {
+    let mut flood_gate = FloodGate::new(flood_control, Instant::now());
+    // the next message, held until the flood gate lets it through
+    let mut waiting = None;
+    let mut ready_at = Instant::now();
+
+    loop {
+        let line = tokio::select! {
+            biased;
+            Some(line) = priority.recv() => Some(line),
+            // waiting in here rather than sleeping, so a `PONG` can still cut in
+            () = tokio::time::sleep_until(ready_at), if waiting.is_some() => waiting.take(),
+            Some(line) = queue.recv(), if waiting.is_none() => {
+                let now = Instant::now();
+                ready_at = now + flood_gate.delay(now);
+                waiting = Some(line);
+                None
+            }
+            else => return Ok(()),
+        };
+        let Some(line) = line else {
+            continue;
+        };
+
+        tracing::trace!(%line, "sending IRC line");
+        writer.write_all(format!("{line}\r\n").as_bytes()).await?;
+    }
+}
+
+/// IRC channel names, which are case insensitive
+#[derive(Debug)]
+struct Channels {
+    /// in the order they're joined
+    names: Vec<String>,
+    by_name: HashMap<String, Channel>,
+    by_channel: HashMap<Channel, String>,
+}
+
+impl Channels {
+    fn new(channels: &[(Channel, String)]) -> IrcBotResult<Self> {
+        if let Some((_, name)) = channels.iter().find(|(_, name)| !is_channel_name(name)) {
+            return Err(IrcBotError::InvalidChannelName { name: name.clone() });
+        }
+
+        let names = channels.iter().map(|(_, name)| name.clone()).collect();
+        let by_name = channels
+            .iter()
+            .map(|(channel, name)| (name.to_lowercase(), *channel))
+            .collect();
+        let by_channel = channels
+            .iter()
+            .map(|(channel, name)| (*channel, name.clone()))
+            .collect();
+
+        Ok(Self {
+            names,
+            by_name,
+            by_channel,
+        })
+    }
+
+    fn by_name(&self, name: &str) -> Option<Channel> {
+        self.by_name.get(&name.to_lowercase()).copied()
+    }
+
+    fn by_channel(&self, channel: &Channel) -> Option<&str> {
+        self.by_channel.get(channel).map(String::as_str)
+    }
+}
+
+fn is_channel_name(name: &str) -> bool {
+    name.len() > 1
+        && name.starts_with(['#', '&'])
+        && !name.contains([' ', ',', '\u{7}', '\r', '\n'])
+}
+
+#[derive(Clone)]
+struct Handler {
+    event_processor: Arc<EventProcessor>,
+    channels: Arc<Channels>,
+    outbox: Outbox,
+    /// can change if the one we asked for is taken
+    nickname: String,
+}
+
+impl Handler {
+    async fn read(mut self, reader: OwnedReadHalf) -> IrcBotResult<()> {
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+
+        loop {
+            buffer.clear();
+            if reader.read_until(b'\n', &mut buffer).await? == 0 {
+                tracing::warn!("IRC server hung up");
+                return Err(IrcBotError::Disconnected);
+            }
+
+            // not everyone on IRC is on UTF-8
+            let line = String::from_utf8_lossy(&buffer);
+            let Some(message) = IrcMessage::parse(&line) else {
+                continue;
+            };
+
+            tracing::trace!(?message, "handling IRC message");
+
+            if let Err(error) = self.handle_message(message).await {
+                tracing::error!(%error, "error handling message");
+            }
+        }
+    }
+
+    async fn handle_message(&mut self, message: IrcMessage<'_>) -> IrcBotResult<()> {
+        match message.command {
+            "PING" => match message.params.first() {
+                Some(token) => self.outbox.now(format!("PONG :{token}")),
+                None => self.outbox.now("PONG".to_string()),
+            },
+            // RPL_WELCOME, registration is done
+            "001" => {
+                tracing::info!(nickname = %self.nickname, "registered with IRC server");
+                for name in &self.channels.names {
+                    self.outbox.now(format!("JOIN {name}"))?;
+                }
+                Ok(())
+            }
+            // ERR_NICKNAMEINUSE
+            "433" => {
+                self.nickname.push('_');
+                tracing::warn!(nickname = %self.nickname, "nickname taken, trying another");
+                self.outbox.now(format!("NICK {}", self.nickname))
+            }
+            "PRIVMSG" => {
+                let (Some(nick), [target, text]) = (message.nick(), message.params.as_slice())
+                else {
+                    return Ok(());
+                };
+
+                // a slow command or the language model shouldn't hold up a `PING`
+                let handler = self.clone();
+                let (nick, target, text) = (nick.to_string(), target.to_string(), text.to_string());
+                tokio::spawn(async move {
+                    if let Err(error) = handler.handle_privmsg(&nick, &target, &text).await {
+                        tracing::error!(%error, "error handling message");
+                    }
+                });
+                Ok(())
+            }
+            "ERROR" => {
+                tracing::error!(params = ?message.params, "IRC server sent an error");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn handle_privmsg(&self, nick: &str, target: &str, text: &str) -> IrcBotResult<()> {
+        // CTCP, e.g. `/me` or `VERSION`
+        if text.starts_with('\u{1}') {
+            return Ok(());
+        }
+
+        let channel =
+            self.channels
+                .by_name(target)
+                .ok_or_else(|| IrcBotError::ChannelNotRecognized {
+                    name: target.to_string(),
+                })?;
+
+        let user = if nick.eq_ignore_ascii_case(&self.nickname) {
+            User::Ultron
+        } else {
+            User::from(nick)
+        };
+
+        tracing::debug!(user = ?user, "message from user");
+
+        let (content, event_type) = match addressed_to(&self.nickname, text) {
+            Some(content) => {
+                tracing::debug!(user = ?user, "message addressed to bot, treating as natural language");
+                (content, EventType::LanguageModel)
+            }
+            None => (text, EventType::Plain),
+        };
+
+        let chat_input = ChatInput::builder()
+            .user(user)
+            .content(content)
+            .channel(channel)
+            .build();
+
+        let event = Event::new(&chat_input, event_type)?;
+
+        let results = match Box::pin(self.event_processor.process(event.clone())).await {
+            Ok(results) => results,
+            Err(error) => {
+                tracing::error!(?event, %error, "error processing event");
+                return match error_message(error) {
+                    Some(error_message) => self.outbox.privmsg(target, &error_message),
+                    None => Ok(()),
+                };
+            }
+        };
+
+        tracing::debug!(?results, "processing event result");
+
+        for result in results {
+            let outgoing = OutgoingMessage::from(result);
+            if outgoing.is_empty() {
+                tracing::info!("response ignored");
+                continue;
+            }
+
+            self.outbox.privmsg(target, &outgoing.to_text())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// the rest of the message if it starts with `nickname: ` or `nickname, `,
+/// which is how people talk to someone on IRC
+fn addressed_to<'text>(nickname: &str, text: &'text str) -> Option<&'text str> {
+    let (start, rest) = text.split_at_checked(nickname.len())?;
+    if !start.eq_ignore_ascii_case(nickname) {
+        return None;
+    }
+
+    let rest = rest.strip_prefix([':', ','])?;
+    Some(rest.trim_start())
+}
+
+/// short enough for one IRC line, so no help message like on Discord
+fn error_message(error: EventError) -> Option<String> {
+    match error {
+        EventError::CommandParse(CommandParseError::MissingPrefix(_)) => None,
+        EventError::Agent(agent_error) => Some(format!("brain hurty: {agent_error}")),
+        EventError::Storage(storage_error) => {
+            Some(format!("memory banks corrupted: {storage_error}"))
+        }
+        error => Some(format!("ya blew it: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncWriteExt as _, Lines},
+        net::TcpListener,
+    };
+    use ultron_core::{command::CommandConsumer, dice::DiceRoller};
+
+    use super::*;
+
+    /// a pretend IRC server, so tests can check exactly what Ultron says
+    struct FakeServer {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl FakeServer {
+        async fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().await.expect("ultron should connect");
+            let (reader, writer) = stream.into_split();
+            Self {
+                lines: BufReader::new(reader).lines(),
+                writer,
+            }
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.writer
+                .write_all(format!("{line}\r\n").as_bytes())
+                .await
+                .expect("should send line to ultron");
+        }
+
+        async fn receive(&mut self) -> String {
+            tokio::time::timeout(Duration::from_secs(5), self.lines.next_line())
+                .await
+                .expect("ultron should say something")
+                .expect("should read line from ultron")
+                .expect("ultron should still be connected")
+        }
+    }
+
+    #[tokio::test]
+    async fn talks_to_a_fake_server() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("should listen on localhost");
+        let address = listener.local_addr().expect("should have an address");
+
+        let event_processor =
+            EventProcessor::new().with_consumer(CommandConsumer::new(DiceRoller::max()));
+        let config = IrcBotConfig::builder()
+            .server(address.to_string())
+            .password("hunter2")
+            .channels(vec![
+                (Channel::Debug, "#Ultron-Debug".to_string()),
+                (Channel::Dnd, "#dnd".to_string()),
+            ])
+            .flood_control(FloodControl {
+                burst: 2,
+                interval: Duration::from_millis(10),
+            })
+            .event_processor(Arc::new(event_processor))
+            .build();
+
+        let (bot, mut server) = tokio::join!(config.run(), FakeServer::accept(&listener));
+        let bot = bot.expect("should connect");
+
+        assert_eq!(server.receive().await, "PASS hunter2");
+        assert_eq!(server.receive().await, "NICK ultron");
+        assert_eq!(server.receive().await, "USER ultron 0 * :Ultron");
+
+        server
+            .send(":irc.test 433 * ultron :Nickname is already in use")
+            .await;
+        assert_eq!(server.receive().await, "NICK ultron_");
+
+        server.send(":irc.test 001 ultron_ :welcome").await;
+        assert_eq!(server.receive().await, "JOIN #Ultron-Debug");
+        assert_eq!(server.receive().await, "JOIN #dnd");
+
+        server.send("PING :irc.test").await;
+        assert_eq!(server.receive().await, "PONG :irc.test");
+
+        server
+            .send(":bob!bob@example.com PRIVMSG #ultron-debug :!ultron echo hello")
+            .await;
+        assert_eq!(server.receive().await, "PRIVMSG #ultron-debug :hello");
+
+        server
+            .send(":bob!bob@example.com PRIVMSG #dnd :!ultron frobnicate")
+            .await;
+        assert!(
+            server
+                .receive()
+                .await
+                .starts_with("PRIVMSG #dnd :ya blew it: ")
+        );
+
+        // nothing to say about these, so the next line is the one from `send_message`
+        server
+            .send(":bob!bob@example.com PRIVMSG #dnd :just chatting")
+            .await;
+        server
+            .send(":bob!bob@example.com PRIVMSG #elsewhere :!ultron echo nope")
+            .await;
+        server
+            .send(":bob!bob@example.com PRIVMSG #dnd :\u{1}VERSION\u{1}")
+            .await;
+
+        let long_message = "word ".repeat(200);
+        bot.send_message(Channel::Dnd, &long_message)
+            .await
+            .expect("should send long message");
+
+        let mut received = Vec::new();
+        while received.join(" ").len() < long_message.trim().len() {
+            let line = server.receive().await;
+            assert!(line.len() + IRC_RELAY_PREFIX_LENGTH + 2 <= IRC_MAX_LINE_LENGTH);
+            let text = line
+                .strip_prefix("PRIVMSG #dnd :")
+                .expect("should be a message to #dnd");
+            received.push(text.to_string());
+        }
+        assert_eq!(received.join(" "), long_message.trim());
+
+        assert!(matches!(
+            bot.send_message(Channel::Psa, "hi").await,
+            Err(IrcBotError::ChannelNotConfigured { .. })
+        ));
+
+        bot.shutdown().await.expect("should shut down");
+        assert!(server.receive().await.starts_with("QUIT"));
+    }
+
+    #[tokio::test]
+    async fn reconnects_when_the_server_hangs_up() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("should listen on localhost");
+        let address = listener.local_addr().expect("should have an address");
+
+        let config = IrcBotConfig::builder()
+            .server(address.to_string())
+            .reconnect_delay(Duration::from_millis(10))
+            .event_processor(Arc::new(EventProcessor::new()))
+            .build();
+
+        let (bot, mut server) = tokio::join!(config.run(), FakeServer::accept(&listener));
+        let bot = bot.expect("should connect");
+        assert_eq!(server.receive().await, "NICK ultron");
+
+        drop(server);
+        let mut server =
+            tokio::time::timeout(Duration::from_secs(5), FakeServer::accept(&listener))
+                .await
+                .expect("should reconnect");
+        assert_eq!(server.receive().await, "NICK ultron");
+        assert_eq!(server.receive().await, "USER ultron 0 * :Ultron");
+
+        bot.shutdown().await.expect("should shut down");
+        assert!(server.receive().await.starts_with("QUIT"));
+    }
+
+    #[tokio::test]
+    async fn pongs_dont_wait_for_flood_control() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("should listen on localhost");
+        let address = listener.local_addr().expect("should have an address");
+
+        let config = IrcBotConfig::builder()
+            .server(address.to_string())
+            .flood_control(FloodControl {
+                burst: 1,
+                interval: Duration::from_secs(60),
+            })
+            .event_processor(Arc::new(EventProcessor::new()))
+            .build();
+
+        let (bot, mut server) = tokio::join!(config.run(), FakeServer::accept(&listener));
+        let bot = bot.expect("should connect");
+        assert_eq!(server.receive().await, "NICK ultron");
+        assert_eq!(server.receive().await, "USER ultron 0 * :Ultron");
+
+        bot.send_message(Channel::Dnd, "one\ntwo")
+            .await
+            .expect("should send message");
+        assert_eq!(server.receive().await, "PRIVMSG #dnd :one");
+
+        // `two` has a minute to wait
+        server.send("PING :irc.test").await;
+        assert_eq!(server.receive().await, "PONG :irc.test");
+
+        let spam = "spam\n".repeat(MAX_QUEUED_LINES + 1);
+        assert!(matches!(
+            bot.send_message(Channel::Dnd, &spam).await,
+            Err(IrcBotError::Backlogged)
+        ));
+    }
+
+    #[test]
+    fn messages_can_be_addressed_to_ultron() {
+        assert_eq!(addressed_to("ultron", "ultron: hi there"), Some("hi there"));
+        assert_eq!(
+            addressed_to("ultron", "Ultron,what's up"),
+            Some("what's up")
+        );
+        assert_eq!(addressed_to("ultron", "ultronic: hi"), None);
+        assert_eq!(addressed_to("ultron", "hi ultron"), None);
+        assert_eq!(addressed_to("ultron", "ult"), None);
+        assert_eq!(addressed_to("ultron", "ultro🦀"), None);
+    }
+
+    #[test]
+    fn channel_names_are_checked() {
+        assert!(Channels::new(&default_channels()).is_ok());
+        assert!(matches!(
+            Channels::new(&[(Channel::Dnd, "dnd".to_string())]),
+            Err(IrcBotError::InvalidChannelName { .. })
+        ));
+
+        let channels = Channels::new(&[(Channel::Dnd, "#DnD".to_string())])
+            .expect("should be a valid channel");
+        assert_eq!(channels.by_name("#dnd"), Some(Channel::Dnd));
+        assert_eq!(channels.by_channel(&Channel::Dnd), Some("#DnD"));
+        assert_eq!(channels.by_name("#general"), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! just enough of the IRC line protocol, <https://modern.ircdocs.horse/#message-format>
+
+/// a line from the server, e.g. `:bob!bob@host PRIVMSG #dnd :!ultron roll d20`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IrcMessage<'line> {
+    pub prefix: Option<&'line str>,
+    pub command: &'line str,
+    /// the trailing parameter after ` :` is last, and can have spaces in it
+    pub params: Vec<&'line str>,
+}
+
+impl<'line> IrcMessage<'line> {
+    /// `None` if there's no command in the line
+    pub fn parse(line: &'line str) -> Option<Self> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        // ignore IRCv3 message tags, we don't ask for any
+        let line = match line.strip_prefix('@') {
+            Some(tagged) => tagged.split_once(' ')?.1,
+            None => line,
+        };
+
+        let (prefix, rest) = match line.strip_prefix(':') {
+            Some(prefixed) => {
+                let (prefix, rest) = prefixed.split_once(' ')?;
+                (Some(prefix), rest)
+            }
+            None => (None, line),
+        };
+
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+
+        let mut words = middle.split(' ').filter(|word| !word.is_empty());
+        let command = words.next()?;
+        let params = words.chain(trailing).collect();
+
+        Some(Self {
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// the nickname of whoever sent the message, if it was a user
+    pub fn nick(&self) -> Option<&'line str> {
+        let (nick, _) = self.prefix?.split_once('!')?;
+        Some(nick)
+    }
+}
+
+/// split text into lines of at most `max_bytes` bytes, since IRC messages can't have newlines
+/// and servers cut off anything past 512 bytes.
+/// splits between words where it can, and leaves out blank lines since IRC can't send those.
+/// a lone `\r` ends a line too, and other control characters are dropped,
+/// so text can't sneak in another command or a CTCP request
+pub(crate) fn split_lines(text: &str, max_bytes: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for line in text.split(['\n', '\r']) {
+        let line: String = line
+            .chars()
+            .map(|c| if c == '\t' { ' ' } else { c })
+            .filter(|c| !c.is_control())
+            .collect();
+        let mut rest = line.trim();
+
+        while rest.len() > max_bytes {
+            let mut end = max_bytes;
+            while end > 0 && !rest.is_char_boundary(end) {
+                end -= 1;
+            }
+            if let Some(space) = rest[..end].rfind(char::is_whitespace)
+                && space > 0
+            {
+                end = space;
+            }
+            // a single character wider than the limit still has to go somewhere
+            if end == 0 {
+                end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+            }
+
+            lines.push(rest[..end].trim_end().to_string());
+            rest = rest[end..].trim_start();
+        }
+
+        if !rest.is_empty() {
+            lines.push(rest.to_string());
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_parse() {
+        let message = IrcMessage::parse(":bob!bob@example.com PRIVMSG #dnd :!ultron roll 2d20\r\n")
+            .expect("should parse");
+        assert_eq!(message.nick(), Some("bob"));
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.params, vec!["#dnd", "!ultron roll 2d20"]);
+
+        let message = IrcMessage::parse("PING :irc.example.com").expect("should parse");
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.params, vec!["irc.example.com"]);
+
+        let message =
+            IrcMessage::parse("@time=2025-01-01T00:00:00Z :irc.example.com 001 ultron :welcome")
+                .expect("should parse tagged message");
+        assert_eq!(message.nick(), None, "servers aren't users");
+        assert_eq!(message.command, "001");
+        assert_eq!(message.params, vec!["ultron", "welcome"]);
+
+        assert_eq!(IrcMessage::parse(""), None);
+        assert_eq!(IrcMessage::parse(":prefix-only"), None);
+    }
+
+    #[test]
+    fn lines_are_split_to_fit() {
+        let lines = split_lines("one two three four\n\n  five six  \nseven", 9);
+        assert_eq!(lines, vec!["one two", "three", "four", "five six", "seven"]);
+
+        let lines = split_lines("abcdefghijkl", 5);
+        assert_eq!(lines, vec!["abcde", "fghij", "kl"]);
+
+        // 🦀 is 4 bytes, so only one fits in 6
+        let lines = split_lines("🦀🦀🦀", 6);
+        assert_eq!(lines, vec!["🦀", "🦀", "🦀"]);
+        assert_eq!(split_lines("🦀", 2), vec!["🦀"]);
+
+        assert!(split_lines("\n \n", 10).is_empty());
+    }
+
+    #[test]
+    fn lines_cant_smuggle_commands() {
+        assert_eq!(
+            split_lines("hi\rQUIT :bye\r\nthere", 50),
+            vec!["hi", "QUIT :bye", "there"]
+        );
+        assert_eq!(
+            split_lines("\u{1}VERSION\u{1} nul\0 bell\u{7}\ttab", 50),
+            vec!["VERSION nul bell tab"]
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! the parts of the Matrix client-server API that Ultron uses,
+//! <https://spec.matrix.org/latest/client-server-api/>
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{Method, Url, header};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::json;
+
+use crate::error::{MatrixBotError, MatrixBotResult};
+
+/// how long the homeserver can hold on to a `/sync` when there's nothing new
+pub(crate) const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// how long the typing notification lasts if nobody turns it off
+const TYPING_TIMEOUT_MILLIS: u64 = 30_000;
+
+/// only room messages, we don't care about presence or account data
+const SYNC_FILTER: &str = r#"{"room":{"timeline":{"types":["m.room.message"]},"state":{"lazy_load_members":true}},"presence":{"types":[]},"account_data":{"types":[]}}"#;
+
+#[derive(Debug, Clone)]
+pub(crate) struct MatrixClient {
+    http: reqwest::Client,
+    homeserver: Url,
+    access_token: String,
+    /// transaction ids only have to be unique per access token,
+    /// this plus [`Self::transaction_counter`] keeps them unique across restarts
+    started_at: u128,
+    transaction_counter: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    errcode: String,
+    #[serde(default)]
+    error: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Session {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhoAmI {
+    user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Joined {
+    room_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sent {
+    event_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Uploaded {
+    content_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SyncResponse {
+    pub next_batch: String,
+    #[serde(default)]
+    pub rooms: Rooms,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Rooms {
+    #[serde(default)]
+    pub join: HashMap<String, JoinedRoom>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct JoinedRoom {
+    #[serde(default)]
+    pub timeline: Timeline,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Timeline {
+    #[serde(default)]
+    pub events: Vec<RoomEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RoomEvent {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub sender: String,
+    pub event_id: String,
+    #[serde(default)]
+    pub content: serde_json::Value,
+}
+
+/// the content of an `m.room.message`, or as much of it as we need
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct MessageContent {
+    pub msgtype: String,
+    pub body: String,
+    #[serde(default, rename = "m.mentions")]
+    pub mentions: Option<MentionsContent>,
+    #[serde(default, rename = "m.relates_to")]
+    pub relates_to: Option<RelatesTo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct MentionsContent {
+    #[serde(default)]
+    pub user_ids: Vec<String>,
+    #[serde(default)]
+    pub room: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct RelatesTo {
+    pub rel_type: Option<String>,
+    #[serde(rename = "m.in_reply_to")]
+    pub in_reply_to: Option<InReplyTo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub(crate) struct InReplyTo {
+    pub event_id: String,
+}
+
+impl MatrixClient {
+    pub fn new(homeserver: &str, access_token: impl Into<String>) -> MatrixBotResult<Self> {
+        let homeserver = match Url::parse(homeserver) {
+            Ok(url) if !url.cannot_be_a_base() => url,
+            _ => {
+                return Err(MatrixBotError::InvalidHomeserver {
+                    homeserver: homeserver.to_string(),
+                });
+            }
+        };
+
+        let started_at = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_millis(),
+            Err(_) => 0,
+        };
+
+        Ok(Self {
+            http: reqwest::Client::new(),
+            homeserver,
+            access_token: access_token.into(),
+            started_at,
+            transaction_counter: Arc::default(),
+        })
+    }
+
+    /// start a new session with a password
+    pub async fn login(homeserver: &str, user: &str, password: &str) -> MatrixBotResult<Self> {
+        let client = Self::new(homeserver, "")?;
+        let session: Session = client
+            .request(
+                Method::POST,
+                &["login"],
+                Some(&json!({
+                    "type": "m.login.password",
+                    "identifier": { "type": "m.id.user", "user": user },
+                    "password": password,
+                    "initial_device_display_name": "Ultron",
+                })),
+            )
+            .await?;
+
+        Ok(Self {
+            access_token: session.access_token,
+            ..client
+        })
+    }
+
+    /// our own user id, e.g. `@ultron:example.org`
+    pub async fn whoami(&self) -> MatrixBotResult<String> {
+        let whoami: WhoAmI = self
+            .request(Method::GET, &["account", "whoami"], None)
+            .await?;
+        Ok(whoami.user_id)
+    }
+
+    /// join a room by its id or an alias like `#dnd:example.org`, returning its id
+    pub async fn join(&self, room: &str) -> MatrixBotResult<String> {
+        let joined: Joined = self
+            .request(Method::POST, &["join", room], Some(&json!({})))
+            .await?;
+        Ok(joined.room_id)
+    }
+
+    pub async fn sync(
+        &self,
+        since: Option<&str>,
+        timeout: Duration,
+    ) -> MatrixBotResult<SyncResponse> {
+        let mut url = self.endpoint(&["sync"])?;
+        url.query_pairs_mut()
+            .append_pair("filter", SYNC_FILTER)
+            .append_pair("timeout", &timeout.as_millis().to_string());
+        if let Some(since) = since {
+            url.query_pairs_mut().append_pair("since", since);
+        }
+
+        let response = self
+            .http
+            .get(url)
+            .bearer_auth(&self.access_token)
+            .timeout(timeout + REQUEST_TIMEOUT)
+            .send()
+            .await?;
+
+        parse_response(response).await
+    }
+
+    /// send an event to a room, returning its id
+    pub async fn send(
+        &self,
+        room_id: &str,
+        event_type: &str,
+        content: &serde_json::Value,
+    ) -> MatrixBotResult<String> {
+        let transaction_id = format!(
+            "ultron-{}-{}",
+            self.started_at,
+            self.transaction_counter.fetch_add(1, Ordering::Relaxed)
+        );
+
+        let sent: Sent = self
+            .request(
+                Method::PUT,
+                &["rooms", room_id, "send", event_type, &transaction_id],
+                Some(content),
+            )
+            .await?;
+        Ok(sent.event_id)
+    }
+
+    /// show that Ultron is typing, or stop
+    pub async fn typing(&self, room_id: &str, user_id: &str, typing: bool) -> MatrixBotResult<()> {
+        let _: serde_json::Value = self
+            .request(
+                Method::PUT,
+                &["rooms", room_id, "typing", user_id],
+                Some(&json!({ "typing": typing, "timeout": TYPING_TIMEOUT_MILLIS })),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// upload a file, returning its `mxc://` URI
+    pub async fn upload(&self, filename: &str, data: Vec<u8>) -> MatrixBotResult<String> {
+        let mut url = self.url(&["_matrix", "media", "v3", "upload"])?;
+        url.query_pairs_mut().append_pair("filename", filename);
+
+        let response = self
+            .http
+            .post(url)
+            .bearer_auth(&self.access_token)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(data)
+            .timeout(REQUEST_TIMEOUT)
+            .send()
+            .await?;
+
+        let uploaded: Uploaded = parse_response(response).await?;
+        Ok(uploaded.content_uri)
+    }
+
+    async fn request<T>(
+        &self,
+        method: Method,
+        path: &[&str],
+        body: Option<&serde_json::Value>,
+    ) -> MatrixBotResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let request = self
+            .http
+            .request(method, self.endpoint(path)?)
+            .timeout(REQUEST_TIMEOUT);
+
+        let request = match self.access_token.is_empty() {
+            true => request,
+            false => request.bearer_auth(&self.access_token),
+        };
+
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(body)?),
+            None => request,
+        };
+
+        parse_response(request.send().await?).await
+    }
+
+    /// a client-server API endpoint, with each segment of `path` escaped
+    fn endpoint(&self, path: &[&str]) -> MatrixBotResult<Url> {
+        let path: Vec<&str> = ["_matrix", "client", "v3"]
+            .into_iter()
+            .chain(path.iter().copied())
+            .collect();
+        self.url(&path)
+    }
+
+    fn url(&self, path: &[&str]) -> MatrixBotResult<Url> {
+        let mut url = self.homeserver.clone();
+        match url.path_segments_mut() {
+            Ok(mut segments) => {
+                segments.pop_if_empty().extend(path);
+            }
+            Err(()) => {
+                return Err(MatrixBotError::InvalidHomeserver {
+                    homeserver: self.homeserver.to_string(),
+                });
+            }
+        }
+        Ok(url)
+    }
+}
+
+async fn parse_response<T>(response: reqwest::Response) -> MatrixBotResult<T>
+where
+    T: DeserializeOwned,
+{
+    let status = response.status();
+    let body = response.bytes().await?;
+
+    if status.is_success() {
+        return Ok(serde_json::from_slice(&body)?);
+    }
+
+    match serde_json::from_slice::<ApiError>(&body) {
+        Ok(error) => Err(MatrixBotError::Api {
+            errcode: error.errcode,
+            error: error.error,
+        }),
+        Err(_) => Err(MatrixBotError::Api {
+            errcode: status.to_string(),
+            error: String::from_utf8_lossy(&body).to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+impl MatrixClient {
+    /// a new public room, returning its id
+    pub async fn create_room(&self) -> MatrixBotResult<String> {
+        let created: Joined = self
+            .request(
+                Method::POST,
+                &["createRoom"],
+                Some(&json!({ "preset": "public_chat" })),
+            )
+            .await?;
+        Ok(created.room_id)
+    }
+}
+
+/// register a new user on a homeserver that allows open registration,
+/// like a local conduit set up for testing
+#[cfg(test)]
+pub(crate) async fn register(
+    homeserver: &str,
+    username: &str,
+    password: &str,
+) -> MatrixBotResult<MatrixClient> {
+    let client = MatrixClient::new(homeserver, "")?;
+    let mut body = json!({
+        "username": username,
+        "password": password,
+        "initial_device_display_name": "ultron tests",
+    });
+
+    // the first try usually says which auth flow to use, and gives us a session for it
+    let response = client
+        .http
+        .post(client.endpoint(&["register"])?)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&body)?)
+        .send()
+        .await?;
+    if response.status().is_success() {
+        let session: Session = parse_response(response).await?;
+        return Ok(MatrixClient {
+            access_token: session.access_token,
+            ..client
+        });
+    }
+
+    let flows: serde_json::Value = serde_json::from_slice(&response.bytes().await?)?;
+    body["auth"] = json!({ "type": "m.login.dummy", "session": flows["session"] });
+    let session: Session = client
+        .request(Method::POST, &["register"], Some(&body))
+        .await?;
+
+    Ok(MatrixClient {
+        access_token: session.access_token,
+        ..client
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoints_are_escaped() {
+        let client =
+            MatrixClient::new("http://localhost:6167/", "token").expect("valid homeserver");
+        let url = client
+            .endpoint(&["join", "#dnd:example.org"])
+            .expect("should build url");
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:6167/_matrix/client/v3/join/%23dnd:example.org"
+        );
+
+        assert!(matches!(
+            MatrixClient::new("not a url", "token"),
+            Err(MatrixBotError::InvalidHomeserver { .. })
+        ));
+    }
+
+    #[test]
+    fn sync_responses_parse() {
+        let sync: SyncResponse = serde_json::from_str(
+            r#"{
+                "next_batch": "s72595_4483_1934",
+                "rooms": {
+                    "join": {
+                        "!dnd:example.org": {
+                            "timeline": {
+                                "events": [{
+                                    "type": "m.room.message",
+                                    "sender": "@bob:example.org",
+                                    "event_id": "$143273582443PhrSn:example.org",
+                                    "origin_server_ts": 1432735824653,
+                                    "content": {
+                                        "msgtype": "m.text",
+                                        "body": "ultron: roll for it",
+                                        "m.mentions": { "user_ids": ["@ultron:example.org"] }
+                                    }
+                                }]
+                            }
+                        }
+                    },
+                    "invite": {}
+                }
+            }"#,
+        )
+        .expect("should parse sync response");
+
+        assert_eq!(sync.next_batch, "s72595_4483_1934");
+        let events = &sync.rooms.join["!dnd:example.org"].timeline.events;
+        let content: MessageContent =
+            serde_json::from_value(events[0].content.clone()).expect("should parse message");
+        assert_eq!(content.body, "ultron: roll for it");
+        assert_eq!(
+            content.mentions.map(|mentions| mentions.user_ids),
+            Some(vec!["@ultron:example.org".to_string()])
+        );
+
+        let empty: SyncResponse =
+            serde_json::from_str(r#"{"next_batch": "s1"}"#).expect("should parse empty sync");
+        assert!(empty.rooms.join.is_empty());
+    }
+}
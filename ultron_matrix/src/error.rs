@@ -0,0 +1,31 @@
+pub type MatrixBotResult<T> = Result<T, MatrixBotError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MatrixBotError {
+    #[error("channel not configured, `{channel}`")]
+    ChannelNotConfigured { channel: ultron_core::Channel },
+
+    #[error("room not recognized, `{room_id}`")]
+    RoomNotRecognized { room_id: String },
+
+    #[error("not a homeserver URL, `{homeserver}`")]
+    InvalidHomeserver { homeserver: String },
+
+    #[error(transparent)]
+    CommandParse(#[from] ultron_core::command::CommandParseError),
+
+    #[error("the homeserver said no: {errcode} {error}")]
+    Api { errcode: String, error: String },
+
+    #[error("failed to reach the homeserver: {0}")]
+    Request(#[from] reqwest::Error),
+
+    #[error("failed to (de)serialize Matrix JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl From<MatrixBotError> for ultron_core::error::Error {
+    fn from(value: MatrixBotError) -> Self {
+        ultron_core::error::Error::ChatBot(Box::new(value))
+    }
+}
@@ -0,0 +1,212 @@
+//! render messages as the HTML subset Matrix clients understand,
+//! <https://spec.matrix.org/latest/client-server-api/#mroommessage-msgtypes>
+use ultron_core::{
+    card::Card,
+    nlp::response::{MessagePart, MessageParts},
+};
+
+/// the inline markdown Ultron writes, like `**15**` for a nat 20, and the HTML tag for each.
+/// longer markers go first, so `**` isn't read as two `*`s
+const INLINE_MARKDOWN: &[(&str, &str)] = &[
+    ("`", "code"),
+    ("**", "strong"),
+    ("__", "u"),
+    ("~~", "del"),
+    ("*", "em"),
+    ("_", "em"),
+];
+
+/// escape text so it shows up as-is in HTML
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        push_escaped(&mut escaped, character);
+    }
+    escaped
+}
+
+fn push_escaped(html: &mut String, character: char) {
+    match character {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        character => html.push(character),
+    }
+}
+
+/// markdown, with its line breaks kept.
+/// only inline formatting like `**bold**`, `_em_` and `` `code` `` is converted,
+/// the rest is escaped and shows up as written
+pub(crate) fn markdown_to_html(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(inline_markdown_to_html)
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+fn inline_markdown_to_html(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    let mut position = 0;
+
+    while let Some(character) = text[position..].chars().next() {
+        match formatting_at(text, position) {
+            Some((marker, tag, inner)) => {
+                // nothing is formatted inside code
+                let inner_html = match tag {
+                    "code" => escape(inner),
+                    _ => inline_markdown_to_html(inner),
+                };
+                html.push_str(&format!("<{tag}>{inner_html}</{tag}>"));
+                position += inner.len() + 2 * marker.len();
+            }
+            None => {
+                push_escaped(&mut html, character);
+                position += character.len_utf8();
+            }
+        }
+    }
+
+    html
+}
+
+/// the marker, tag and text of formatting that starts at `position`, if it's closed.
+/// like most markdown, `_` doesn't count inside words, so `rick_and_morty` stays as is
+fn formatting_at(text: &str, position: usize) -> Option<(&'static str, &'static str, &str)> {
+    let before = text[..position].chars().next_back();
+    let rest = &text[position..];
+
+    INLINE_MARKDOWN.iter().find_map(|&(marker, tag)| {
+        let opened = rest.strip_prefix(marker)?;
+        let end = opened.find(marker)?;
+        let inner = &opened[..end];
+        if inner.is_empty() || inner.starts_with(' ') || inner.ends_with(' ') {
+            return None;
+        }
+
+        let after = opened[end + marker.len()..].chars().next();
+        let in_word =
+            before.is_some_and(char::is_alphanumeric) || after.is_some_and(char::is_alphanumeric);
+        if marker.starts_with('_') && in_word {
+            return None;
+        }
+
+        Some((marker, tag, inner))
+    })
+}
+
+/// thinking goes in a collapsed `<details>`, so it's there for the curious
+/// without getting in everyone else's way
+pub(crate) fn message_parts_to_html(message: &MessageParts) -> String {
+    message
+        .parts()
+        .iter()
+        .map(|part| match part {
+            MessagePart::Thinking(thinking) => format!(
+                "<details><summary>💭 thinking</summary>{}</details>",
+                markdown_to_html(thinking)
+            ),
+            MessagePart::Text(text) => markdown_to_html(text),
+        })
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+pub(crate) fn card_to_html(card: &Card) -> String {
+    let title = card
+        .title
+        .iter()
+        .map(|title| format!("<strong>{}</strong>", escape(title)));
+    let description = card.description.iter().map(|text| markdown_to_html(text));
+    let fields = card.fields.iter().map(|field| {
+        format!(
+            "<strong>{}</strong>: {}",
+            escape(&field.name),
+            markdown_to_html(&field.value)
+        )
+    });
+    let code_blocks = card.code_blocks.iter().map(|block| {
+        let class = match &block.language {
+            Some(language) => format!(" class=\"language-{}\"", escape(language)),
+            None => String::new(),
+        };
+        format!("<pre><code{class}>{}</code></pre>", escape(&block.code))
+    });
+    let footer = card
+        .footer
+        .iter()
+        .map(|footer| format!("<em>{}</em>", escape(footer)));
+
+    // blocks like <pre> already break the line
+    let mut html = String::new();
+    for part in title
+        .chain(description)
+        .chain(fields)
+        .chain(code_blocks)
+        .chain(footer)
+    {
+        if !html.is_empty() && !html.ends_with("</pre>") && !part.starts_with("<pre>") {
+            html.push_str("<br>");
+        }
+        html.push_str(&part);
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use ultron_core::card::{CardField, CodeBlock};
+
+    use super::*;
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(
+            markdown_to_html("<script>alert('hi')</script> & co\nline two"),
+            "&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; co<br>line two"
+        );
+    }
+
+    #[test]
+    fn markdown_is_formatted() {
+        assert_eq!(
+            markdown_to_html("rolled ~~2~~ **20** __1__ = **21**\n_nice_ *very* `2d20kh1`"),
+            "rolled <del>2</del> <strong>20</strong> <u>1</u> = <strong>21</strong><br><em>nice</em> <em>very</em> <code>2d20kh1</code>"
+        );
+        assert_eq!(
+            markdown_to_html("**bold with _em_** and `**not bold** <code>`"),
+            "<strong>bold with <em>em</em></strong> and <code>**not bold** &lt;code&gt;</code>"
+        );
+        assert_eq!(
+            markdown_to_html("pasta rick_and_morty, 2 * 3 * 4, **unclosed and <b>"),
+            "pasta rick_and_morty, 2 * 3 * 4, **unclosed and &lt;b&gt;"
+        );
+    }
+
+    #[test]
+    fn thinking_is_tucked_away() {
+        let message: MessageParts = [
+            MessagePart::Thinking("should I\nroll <d20>?".to_string()),
+            MessagePart::Text("\nnat 20 🎲".to_string()),
+        ]
+        .into_iter()
+        .collect();
+
+        insta::assert_snapshot!(message_parts_to_html(&message), @"<details><summary>💭 thinking</summary>should I<br>roll &lt;d20&gt;?</details><br>nat 20 🎲");
+    }
+
+    #[test]
+    fn cards_render() {
+        let card = Card::builder()
+            .title("2d20")
+            .description("rolled by <bob>")
+            .fields(vec![CardField::new("total", "40")])
+            .code_blocks(vec![CodeBlock::new("[20, 20]").with_language("json")])
+            .footer("max roller")
+            .build();
+
+        insta::assert_snapshot!(card_to_html(&card), @r#"<strong>2d20</strong><br>rolled by &lt;bob&gt;<br><strong>total</strong>: 40<pre><code class="language-json">[20, 20]</code></pre><em>max roller</em>"#);
+    }
+}
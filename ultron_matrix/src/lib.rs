@@ -0,0 +1,686 @@
+//! a Matrix [`ChatBot`], for the part of the group that moved to Matrix.
+//!
+//! talks to a homeserver over the client-server API: a `/sync` loop for incoming messages,
+//! and room events for everything Ultron says.
+//! rooms map to [`Channel`]s, and messages that mention Ultron go to the language model.
+//! responses are sent as HTML, with any thinking tucked away in a `<details>`.
+//!
+//! there's no end-to-end encryption, so Ultron only understands unencrypted rooms.
+use bon::Builder;
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+use ultron_core::{
+    Channel, Response, User,
+    card::Card,
+    chatbot::{
+        ChatBot, ChatInput,
+        outgoing::{Mentions, MessageRef, OutgoingMessage},
+    },
+    command::CommandParseError,
+    event_processor::{Event, EventError, EventProcessor, EventType},
+    nlp::response::MessageParts,
+};
+
+use crate::{
+    client::{MatrixClient, MessageContent, RoomEvent, SYNC_TIMEOUT},
+    error::{MatrixBotError, MatrixBotResult},
+    html::{card_to_html, escape, markdown_to_html, message_parts_to_html},
+};
+
+mod client;
+mod error;
+mod html;
+
+/// how long to wait before trying again when a `/sync` fails
+const SYNC_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// the HTML format of `formatted_body`, the only one there is
+const HTML_FORMAT: &str = "org.matrix.custom.html";
+
+/// how Ultron logs in to the homeserver
+#[derive(Debug, Clone)]
+pub enum MatrixAuth {
+    /// the access token of an existing session
+    AccessToken(String),
+    /// log in with a password, which starts a new session every time
+    Password { user: String, password: String },
+}
+
+#[derive(Builder, Debug, Clone)]
+pub struct MatrixBotConfig {
+    /// e.g. `https://matrix.example.org`
+    #[builder(into)]
+    homeserver: String,
+    auth: MatrixAuth,
+    /// the rooms to join by id or alias, like `#dnd:example.org`, and which [`Channel`] each one is
+    rooms: Vec<(Channel, String)>,
+    event_processor: Arc<EventProcessor>,
+}
+
+impl MatrixBotConfig {
+    /// log in, join the rooms and start listening
+    pub async fn run(self) -> MatrixBotResult<MatrixBot> {
+        let client = match &self.auth {
+            MatrixAuth::AccessToken(access_token) => {
+                MatrixClient::new(&self.homeserver, access_token)?
+            }
+            MatrixAuth::Password { user, password } => {
+                MatrixClient::login(&self.homeserver, user, password).await?
+            }
+        };
+
+        let user_id = client.whoami().await?;
+        tracing::info!(%user_id, "logged in to Matrix");
+
+        let mut rooms = Vec::with_capacity(self.rooms.len());
+        for (channel, room) in &self.rooms {
+            let room_id = client.join(room).await?;
+            tracing::info!(%room, %room_id, %channel, "joined Matrix room");
+            rooms.push((*channel, room_id));
+        }
+        let rooms = Arc::new(RoomChannels::new(rooms));
+
+        // don't answer whatever was said while Ultron was away
+        let since = client.sync(None, Duration::ZERO).await?.next_batch;
+
+        let handler = Handler {
+            client: client.clone(),
+            event_processor: self.event_processor,
+            rooms: rooms.clone(),
+            user_id,
+        };
+        let sync_handle = tokio::spawn(handler.sync(since));
+
+        Ok(MatrixBot {
+            client,
+            rooms,
+            sync_handle: Arc::new(sync_handle),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatrixBot {
+    client: MatrixClient,
+    rooms: Arc<RoomChannels>,
+    sync_handle: Arc<JoinHandle<()>>,
+}
+
+impl ChatBot for MatrixBot {
+    type Error = MatrixBotError;
+
+    async fn send_message(&self, channel: Channel, message: &str) -> MatrixBotResult<()> {
+        self.send(channel, &OutgoingMessage::from(message)).await
+    }
+
+    async fn send(&self, channel: Channel, message: &OutgoingMessage) -> MatrixBotResult<()> {
+        tracing::debug!(channel = ?channel, "sending message");
+
+        let room_id = self
+            .rooms
+            .by_channel(&channel)
+            .ok_or(MatrixBotError::ChannelNotConfigured { channel })?;
+
+        send_outgoing(&self.client, room_id, message).await
+    }
+}
+
+impl MatrixBot {
+    pub async fn shutdown(&self) -> MatrixBotResult<()> {
+        self.sync_handle.abort();
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct RoomChannels {
+    by_room_id: HashMap<String, Channel>,
+    by_channel: HashMap<Channel, String>,
+}
+
+impl RoomChannels {
+    fn new(rooms: Vec<(Channel, String)>) -> Self {
+        let by_room_id = rooms
+            .iter()
+            .map(|(channel, room_id)| (room_id.clone(), *channel))
+            .collect();
+        let by_channel = rooms.into_iter().collect();
+
+        Self {
+            by_room_id,
+            by_channel,
+        }
+    }
+
+    fn by_room_id(&self, room_id: &str) -> Option<Channel> {
+        self.by_room_id.get(room_id).copied()
+    }
+
+    fn by_channel(&self, channel: &Channel) -> Option<&str> {
+        self.by_channel.get(channel).map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Handler {
+    client: MatrixClient,
+    event_processor: Arc<EventProcessor>,
+    rooms: Arc<RoomChannels>,
+    /// Ultron's own user id, e.g. `@ultron:example.org`
+    user_id: String,
+}
+
+impl Handler {
+    async fn sync(self, mut since: String) {
+        loop {
+            let sync = match self.client.sync(Some(&since), SYNC_TIMEOUT).await {
+                Ok(sync) => sync,
+                Err(error) => {
+                    tracing::error!(%error, "Matrix sync failed, trying again soon");
+                    tokio::time::sleep(SYNC_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            since = sync.next_batch;
+
+            for (room_id, room) in sync.rooms.join {
+                for event in room.timeline.events {
+                    tracing::debug!("handling room event: {:?}", event);
+
+                    let handler = self.clone();
+                    let room_id = room_id.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = handler.handle_event(room_id, event).await {
+                            tracing::error!(%error, "error handling message");
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    async fn handle_event(&self, room_id: String, event: RoomEvent) -> MatrixBotResult<()> {
+        if event.kind != "m.room.message" || event.sender == self.user_id {
+            return Ok(());
+        }
+
+        let channel =
+            self.rooms
+                .by_room_id(&room_id)
+                .ok_or_else(|| MatrixBotError::RoomNotRecognized {
+                    room_id: room_id.clone(),
+                })?;
+
+        // redacted messages have no content
+        let Ok(content) = serde_json::from_value::<MessageContent>(event.content) else {
+            return Ok(());
+        };
+
+        // only what people type. no notices from other bots, images or edits
+        let is_edit = content
+            .relates_to
+            .as_ref()
+            .and_then(|relates_to| relates_to.rel_type.as_deref())
+            == Some("m.replace");
+        if content.msgtype != "m.text" || is_edit {
+            return Ok(());
+        }
+
+        let user = user_from_id(&event.sender);
+
+        tracing::debug!(user = ?user, "message from user");
+
+        let text = match content
+            .relates_to
+            .as_ref()
+            .and_then(|relates_to| relates_to.in_reply_to.as_ref())
+        {
+            Some(_) => strip_reply_fallback(&content.body),
+            None => content.body.as_str(),
+        };
+
+        let (text, event_type) = if self.mentions_ultron(&content, text) {
+            tracing::debug!(user = ?user, "message mentions bot, treating as natural language");
+            let text = addressed_to(localpart(&self.user_id), text).unwrap_or(text);
+            (text, EventType::LanguageModel)
+        } else {
+            (text, EventType::Plain)
+        };
+
+        if event_type == EventType::LanguageModel {
+            self.typing(&room_id, true).await;
+        }
+
+        // the homeserver vouches for the sender
+        let chat_input = ChatInput::builder()
+            .user(user)
+            .content(text)
+            .channel(channel)
+            .identity(format!("matrix:{}", event.sender))
+            .build();
+
+        let ultron_event = Event::new(&chat_input, event_type)?;
+        let reply_to = MessageRef(event.event_id);
+
+        let results = Box::pin(self.event_processor.process(ultron_event.clone())).await;
+
+        if event_type == EventType::LanguageModel {
+            self.typing(&room_id, false).await;
+        }
+
+        let results = match results {
+            Ok(results) => results,
+            Err(error) => {
+                tracing::error!(event = ?ultron_event, %error, "error processing event");
+                return match error_message(error) {
+                    Some(error_message) => {
+                        let outgoing = OutgoingMessage::from(error_message).with_reply_to(reply_to);
+                        send_outgoing(&self.client, &room_id, &outgoing).await
+                    }
+                    None => Ok(()),
+                };
+            }
+        };
+
+        tracing::debug!(?results, "processing event result");
+
+        for result in results {
+            match result {
+                Response::Bot(message) => {
+                    send_message_parts(&self.client, &room_id, &message, &reply_to).await?
+                }
+                response => {
+                    let outgoing = OutgoingMessage::from(response).with_reply_to(reply_to.clone());
+                    if outgoing.is_empty() {
+                        tracing::info!("response ignored");
+                        continue;
+                    }
+                    send_outgoing(&self.client, &room_id, &outgoing).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// mentions are in `m.mentions` these days. older clients just put the name in the text
+    fn mentions_ultron(&self, content: &MessageContent, text: &str) -> bool {
+        match &content.mentions {
+            Some(mentions) => mentions.user_ids.contains(&self.user_id),
+            None => {
+                text.contains(&self.user_id)
+                    || addressed_to(localpart(&self.user_id), text).is_some()
+            }
+        }
+    }
+
+    /// nice to have, so failing is fine
+    async fn typing(&self, room_id: &str, typing: bool) {
+        if let Err(error) = self.client.typing(room_id, &self.user_id, typing).await {
+            tracing::warn!(%error, "unable to set typing notification");
+        }
+    }
+}
+
+/// send an [`OutgoingMessage`] as one HTML message, followed by its attachments.
+/// reactions go on [`OutgoingMessage::reply_to`], or in the text when there's nothing to react to
+async fn send_outgoing(
+    client: &MatrixClient,
+    room_id: &str,
+    message: &OutgoingMessage,
+) -> MatrixBotResult<()> {
+    let content = (!message.content.is_empty()).then_some(message.content.as_str());
+    let reactions = (message.reply_to.is_none() && !message.reactions.is_empty())
+        .then(|| message.reactions.join(" "));
+
+    let body = content
+        .map(str::to_string)
+        .into_iter()
+        .chain(message.embeds.iter().map(Card::to_markdown))
+        .chain(reactions.clone())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let html = content
+        .map(markdown_to_html)
+        .into_iter()
+        .chain(message.embeds.iter().map(card_to_html))
+        .chain(reactions.as_deref().map(escape))
+        .collect::<Vec<_>>()
+        .join("<br><br>");
+
+    if !body.is_empty() {
+        let content = text_message(&body, &html, message.reply_to.as_ref(), &message.mentions);
+        client.send(room_id, "m.room.message", &content).await?;
+    }
+
+    for attachment in &message.attachments {
+        let url = client
+            .upload(&attachment.filename, attachment.data.clone())
+            .await?;
+        let content = json!({
+            "msgtype": "m.file",
+            "body": attachment.filename,
+            "url": url,
+            "info": { "size": attachment.data.len() },
+        });
+        client.send(room_id, "m.room.message", &content).await?;
+    }
+
+    if let Some(reply_to) = &message.reply_to {
+        for emoji in &message.reactions {
+            let content = json!({
+                "m.relates_to": {
+                    "rel_type": "m.annotation",
+                    "event_id": reply_to.0,
+                    "key": emoji,
+                }
+            });
+            client.send(room_id, "m.reaction", &content).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// a language model response, thinking and all
+async fn send_message_parts(
+    client: &MatrixClient,
+    room_id: &str,
+    message: &MessageParts,
+    reply_to: &MessageRef,
+) -> MatrixBotResult<()> {
+    let body = message.render_without_thinking_parts();
+    if body.trim().is_empty() {
+        tracing::info!("response ignored");
+        return Ok(());
+    }
+
+    let content = text_message(
+        &body,
+        &message_parts_to_html(message),
+        Some(reply_to),
+        &Mentions::default(),
+    );
+    client.send(room_id, "m.room.message", &content).await?;
+    Ok(())
+}
+
+/// the content of an `m.room.message` from Ultron.
+/// it's an `m.notice`, which other bots know not to answer.
+/// Matrix has no roles, and pinging the replied user would need their user id,
+/// so only [`Mentions::users`] and [`Mentions::everyone`] make a difference
+fn text_message(
+    body: &str,
+    html: &str,
+    reply_to: Option<&MessageRef>,
+    mentions: &Mentions,
+) -> serde_json::Value {
+    let user_ids = match mentions.users {
+        true => mentioned_user_ids(body),
+        false => Vec::new(),
+    };
+
+    let mut content = json!({
+        "msgtype": "m.notice",
+        "body": body,
+        "format": HTML_FORMAT,
+        "formatted_body": html,
+        "m.mentions": {
+            "user_ids": user_ids,
+            "room": mentions.everyone,
+        },
+    });
+
+    if let Some(reply_to) = reply_to {
+        content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": reply_to.0 } });
+    }
+
+    content
+}
+
+/// user ids like `@bob:example.org` in the text, in order, without repeats
+fn mentioned_user_ids(text: &str) -> Vec<&str> {
+    let mut user_ids = Vec::new();
+
+    for word in text.split_whitespace() {
+        let word = word
+            .trim_start_matches(|character: char| character != '@')
+            .trim_end_matches(|character: char| !character.is_alphanumeric());
+        if is_user_id(word) && !user_ids.contains(&word) {
+            user_ids.push(word);
+        }
+    }
+
+    user_ids
+}
+
+fn is_user_id(word: &str) -> bool {
+    match word.strip_prefix('@').and_then(|rest| rest.split_once(':')) {
+        Some((localpart, server)) => !localpart.is_empty() && !server.is_empty(),
+        None => false,
+    }
+}
+
+/// users go by their whole ID, so `@ultron:elsewhere.org` can't pass for Ultron
+/// and `@bob:example.org` doesn't share karma with every other bob
+fn user_from_id(user_id: &str) -> User {
+    User::from(user_id)
+}
+
+/// `bob` from `@bob:example.org`
+fn localpart(user_id: &str) -> &str {
+    match user_id
+        .strip_prefix('@')
+        .and_then(|rest| rest.split_once(':'))
+    {
+        Some((localpart, _)) => localpart,
+        None => user_id,
+    }
+}
+
+/// the rest of the message if it starts with `name: ` or `name, `,
+/// which is how most clients write a mention in the plain text
+fn addressed_to<'text>(name: &str, text: &'text str) -> Option<&'text str> {
+    let (start, rest) = text.split_at_checked(name.len())?;
+    if !start.eq_ignore_ascii_case(name) {
+        return None;
+    }
+
+    let rest = rest.strip_prefix([':', ','])?;
+    Some(rest.trim_start())
+}
+
+/// replies from older clients quote the original message first, `> <@bob:example.org> hi`,
+/// which Ultron shouldn't read as part of the reply
+fn strip_reply_fallback(body: &str) -> &str {
+    let mut rest = body;
+    while rest.starts_with('>') {
+        rest = match rest.split_once('\n') {
+            Some((_, rest)) => rest,
+            None => "",
+        };
+    }
+    rest.trim_start_matches('\n')
+}
+
+fn error_message(error: EventError) -> Option<String> {
+    match error {
+        EventError::CommandParse(CommandParseError::MissingPrefix(_)) => None,
+        EventError::Agent(agent_error) => Some(format!("brain hurty: {agent_error}")),
+        EventError::Storage(storage_error) => {
+            Some(format!("memory banks corrupted: {storage_error}"))
+        }
+        error => Some(format!("ya blew it: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use ultron_core::{command::CommandConsumer, dice::DiceRoller};
+
+    use super::*;
+    use crate::client::register;
+
+    #[test]
+    fn user_ids_are_found_in_text() {
+        assert_eq!(
+            mentioned_user_ids("hey @bob:example.org, (@carol:matrix.org) and @bob:example.org!"),
+            vec!["@bob:example.org", "@carol:matrix.org"]
+        );
+        assert!(mentioned_user_ids("email me@ or @everyone").is_empty());
+
+        assert_eq!(localpart("@ultron:example.org"), "ultron");
+        assert_eq!(localpart("ultron"), "ultron");
+    }
+
+    #[test]
+    fn users_go_by_their_whole_id() {
+        assert_eq!(
+            user_from_id("@ultron:elsewhere.org"),
+            User::Normal("@ultron:elsewhere.org".to_string())
+        );
+        assert_ne!(
+            user_from_id("@bob:example.org"),
+            user_from_id("@bob:matrix.org")
+        );
+    }
+
+    #[test]
+    fn mentions_and_replies_are_cleaned_up() {
+        assert_eq!(
+            addressed_to("ultron", "Ultron: roll for it"),
+            Some("roll for it")
+        );
+        assert_eq!(addressed_to("ultron", "ultronic: hi"), None);
+
+        assert_eq!(
+            strip_reply_fallback("> <@bob:example.org> what's 2+2\n> in base 3\n\nultron: 11"),
+            "ultron: 11"
+        );
+        assert_eq!(strip_reply_fallback("> just a quote"), "");
+        assert_eq!(strip_reply_fallback("no quote"), "no quote");
+    }
+
+    #[test]
+    fn messages_are_notices_with_html() {
+        let content = text_message(
+            "hi @bob:example.org",
+            "hi @bob:example.org",
+            Some(&MessageRef("$abc:example.org".to_string())),
+            &Mentions::default(),
+        );
+
+        assert_eq!(
+            content,
+            json!({
+                "msgtype": "m.notice",
+                "body": "hi @bob:example.org",
+                "format": "org.matrix.custom.html",
+                "formatted_body": "hi @bob:example.org",
+                "m.mentions": { "user_ids": ["@bob:example.org"], "room": false },
+                "m.relates_to": { "m.in_reply_to": { "event_id": "$abc:example.org" } },
+            })
+        );
+
+        let content = text_message("hi @bob:example.org", "", None, &Mentions::NONE);
+        assert_eq!(
+            content["m.mentions"],
+            json!({ "user_ids": [], "room": false })
+        );
+    }
+
+    /// run a homeserver with open registration first, e.g. with `just matrix_test`
+    #[tokio::test]
+    #[ignore = "needs a local homeserver, see `just matrix_test`"]
+    async fn talks_to_a_local_homeserver() {
+        let homeserver = match std::env::var("ULTRON_MATRIX_HOMESERVER") {
+            Ok(homeserver) => homeserver,
+            Err(_) => "http://localhost:6167".to_string(),
+        };
+        let suffix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock should be after 1970")
+            .as_millis();
+
+        let ultron_user = format!("ultron_{suffix}");
+        register(&homeserver, &ultron_user, "ultron-password")
+            .await
+            .expect("should register ultron");
+        let alice = register(&homeserver, &format!("alice_{suffix}"), "alice-password")
+            .await
+            .expect("should register alice");
+        let alice_id = alice.whoami().await.expect("alice should exist");
+        let room_id = alice.create_room().await.expect("should create room");
+
+        let event_processor =
+            EventProcessor::new().with_consumer(CommandConsumer::new(DiceRoller::max()));
+        let bot = MatrixBotConfig::builder()
+            .homeserver(&homeserver)
+            .auth(MatrixAuth::Password {
+                user: ultron_user,
+                password: "ultron-password".to_string(),
+            })
+            .rooms(vec![(Channel::Debug, room_id.clone())])
+            .event_processor(Arc::new(event_processor))
+            .build()
+            .run()
+            .await
+            .expect("ultron should log in and join");
+
+        let mut since = alice
+            .sync(None, Duration::ZERO)
+            .await
+            .expect("alice should sync")
+            .next_batch;
+
+        let question = alice
+            .send(
+                &room_id,
+                "m.room.message",
+                &json!({ "msgtype": "m.text", "body": "!ultron echo hello" }),
+            )
+            .await
+            .expect("alice should send message");
+        bot.send_message(Channel::Debug, "<b>not bold</b>")
+            .await
+            .expect("ultron should send message");
+
+        let mut from_ultron = Vec::new();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+        while from_ultron.len() < 2 && tokio::time::Instant::now() < deadline {
+            let sync = alice
+                .sync(Some(&since), Duration::from_secs(5))
+                .await
+                .expect("alice should sync");
+            since = sync.next_batch;
+
+            if let Some(room) = sync.rooms.join.get(&room_id) {
+                from_ultron.extend(
+                    room.timeline
+                        .events
+                        .iter()
+                        .filter(|event| event.sender != alice_id)
+                        .map(|event| event.content.clone()),
+                );
+            }
+        }
+
+        let reply = from_ultron
+            .iter()
+            .find(|content| content["body"] == "hello")
+            .expect("ultron should answer the command");
+        assert_eq!(reply["msgtype"], "m.notice");
+        assert_eq!(reply["m.relates_to"]["m.in_reply_to"]["event_id"], question);
+
+        let escaped = from_ultron
+            .iter()
+            .find(|content| content["body"] == "<b>not bold</b>")
+            .expect("ultron should send the message");
+        assert_eq!(escaped["formatted_body"], "&lt;b&gt;not bold&lt;/b&gt;");
+
+        bot.shutdown().await.expect("should shut down");
+    }
+}